@@ -0,0 +1,29 @@
+use std::sync::Arc;
+use std::time::Instant;
+use wasmedge_wasi_socket::cache::DnsCache;
+use wasmedge_wasi_socket::{Resolver, SocketAddr};
+
+fn main() {
+    let dns_server: SocketAddr = std::env::var("DNS_SERVER")
+        .unwrap_or_else(|_| "8.8.8.8:53".to_string())
+        .parse()
+        .expect("DNS_SERVER should be an ip:port address");
+    let name = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "example.com".to_string());
+
+    let cache = Arc::new(DnsCache::new(16));
+    let resolver = Resolver::new(vec![dns_server]).with_cache(Some(cache.clone()));
+
+    for _ in 0..2 {
+        let start = Instant::now();
+        let r = resolver.lookup(&name, "http");
+        println!("{name}: {:?} in {:?}", r, start.elapsed());
+    }
+    // the second lookup was answered from the cache
+    println!("{} cached answers", cache.len());
+    assert!(!cache.is_empty());
+
+    cache.flush();
+    assert!(cache.is_empty());
+}
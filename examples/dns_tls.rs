@@ -0,0 +1,299 @@
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use wasmedge_wasi_socket::dns::Transport;
+use wasmedge_wasi_socket::resolver::IpPreference;
+use wasmedge_wasi_socket::tls::{self, DohMethod, TlsSettings};
+use wasmedge_wasi_socket::{
+    resolve, Ipv4Addr, Resolver, SocketAddr, TcpListener, TcpStream, WasiErrno,
+};
+
+// a certificate for `dns.test`, signed by itself
+const CERT: &[u8] = include_bytes!("certs/dns.test.crt");
+const KEY: &[u8] = include_bytes!("certs/dns.test.key");
+
+type Tls = StreamOwned<ServerConnection, TcpStream>;
+
+/// The answer to a query for `web.test`, and no such name for others.
+fn answer(query: &[u8]) -> Vec<u8> {
+    let mut pos = 12;
+    let mut labels = vec![];
+    while query[pos] != 0 {
+        let len = query[pos] as usize;
+        labels.push(String::from_utf8_lossy(&query[pos + 1..pos + 1 + len]).to_string());
+        pos += 1 + len;
+    }
+    let end = pos + 5;
+    let qtype = u16::from_be_bytes([query[pos + 1], query[pos + 2]]);
+    let data: Vec<u8> = match (labels.join(".").as_str(), qtype) {
+        ("web.test", 1) => vec![10, 0, 0, 1],
+        ("web.test", 28) => "fd00::1"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets()
+            .to_vec(),
+        _ => vec![],
+    };
+    let rcode = if labels.join(".") == "web.test" { 0 } else { 3 };
+    let mut out = query[..2].to_vec();
+    out.extend_from_slice(&[
+        0x81,
+        0x80 | rcode,
+        0,
+        1,
+        0,
+        !data.is_empty() as u8,
+        0,
+        0,
+        0,
+        0,
+    ]);
+    out.extend_from_slice(&query[12..end]);
+    if !data.is_empty() {
+        out.extend_from_slice(&[0xc0, 0x0c]);
+        out.extend_from_slice(&qtype.to_be_bytes());
+        out.extend_from_slice(&[0, 1, 0, 0, 0, 60, 0, data.len() as u8]);
+        out.extend_from_slice(&data);
+    }
+    out
+}
+
+fn serve(listener: TcpListener, config: Arc<ServerConfig>, handle: fn(Tls) -> io::Result<()>) {
+    std::thread::spawn(move || loop {
+        let (sock, _) = listener.accept(false).unwrap();
+        let conn = ServerConnection::new(config.clone()).unwrap();
+        // a client that rejects the certificate ends its connection early
+        std::thread::spawn(move || handle(StreamOwned::new(conn, sock)));
+    });
+}
+
+// DNS over TLS: messages with a 2-byte length prefix
+fn dot(mut stream: Tls) -> io::Result<()> {
+    loop {
+        let mut len = [0u8; 2];
+        if stream.read_exact(&mut len).is_err() {
+            return Ok(());
+        }
+        let mut query = vec![0u8; u16::from_be_bytes(len) as usize];
+        stream.read_exact(&mut query)?;
+        let response = answer(&query);
+        stream.write_all(&(response.len() as u16).to_be_bytes())?;
+        stream.write_all(&response)?;
+    }
+}
+
+static REQUESTS: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+// DNS over HTTPS at /dns-query. At /close, the answer has no length and the
+// connection is closed after it; at /unframed, it has no length and the
+// connection stays open.
+fn doh(stream: Tls) -> io::Result<()> {
+    let mut conn = BufReader::new(stream);
+    loop {
+        let mut request = String::new();
+        if conn.read_line(&mut request)? == 0 {
+            return Ok(());
+        }
+        let mut length = 0;
+        let mut host = String::new();
+        loop {
+            let mut line = String::new();
+            conn.read_line(&mut line)?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').unwrap();
+            match name.to_ascii_lowercase().as_str() {
+                "content-length" => length = value.trim().parse().unwrap(),
+                "host" => host = value.trim().to_string(),
+                _ => {}
+            }
+        }
+        let target = request.split_whitespace().nth(1).unwrap_or("").to_string();
+        REQUESTS
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", request.split(' ').next().unwrap(), host));
+        let query = match target.split_once("?dns=") {
+            Some(("/dns-query", dns)) => Some(base64url_decode(dns)),
+            None if ["/dns-query", "/close", "/unframed"].contains(&target.as_str()) => {
+                let mut body = vec![0u8; length];
+                conn.read_exact(&mut body)?;
+                Some(body)
+            }
+            _ => None,
+        };
+        let stream = conn.get_mut();
+        match query {
+            Some(query) if ["/close", "/unframed"].contains(&target.as_str()) => {
+                let close = if target == "/close" {
+                    "Connection: close\r\n"
+                } else {
+                    ""
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/dns-message\r\n{}\r\n",
+                    close
+                )?;
+                stream.write_all(&answer(&query))?;
+                if target == "/close" {
+                    stream.conn.send_close_notify();
+                    return stream.flush();
+                }
+                stream.flush()?;
+                continue;
+            }
+            Some(query) => {
+                let response = answer(&query);
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/dns-message\r\nContent-Length: {}\r\n\r\n",
+                    response.len()
+                )?;
+                stream.write_all(&response)?;
+            }
+            None => write!(
+                stream,
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+            )?,
+        }
+        stream.flush()?;
+    }
+}
+
+fn base64url_decode(s: &str) -> Vec<u8> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => c - b'A',
+        b'a'..=b'z' => c - b'a' + 26,
+        b'0'..=b'9' => c - b'0' + 52,
+        b'-' => 62,
+        _ => 63,
+    } as u32;
+    let mut out = vec![];
+    for chunk in s.as_bytes().chunks(4) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0, |n, (i, &c)| n | value(c) << (18 - 6 * i));
+        out.extend_from_slice(&n.to_be_bytes()[1..chunk.len()]);
+    }
+    out
+}
+
+fn main() -> io::Result<()> {
+    let cert = CertificateDer::from_pem_slice(CERT).unwrap();
+    let key = PrivateKeyDer::from_pem_slice(KEY).unwrap();
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.clone()], key)
+        .unwrap();
+    let config = Arc::new(config);
+
+    let dot_listener = TcpListener::bind("127.0.0.1:0", false)?;
+    let dot_addr: SocketAddr = dot_listener.local_addr()?;
+    serve(dot_listener, config.clone(), dot);
+    let doh_listener = TcpListener::bind("127.0.0.1:0", false)?;
+    let doh_addr: SocketAddr = doh_listener.local_addr()?;
+    serve(doh_listener, config, doh);
+
+    let settings = TlsSettings::with_root_certificates("dns.test", vec![cert.clone()])?;
+    let web: Vec<SocketAddr> = vec![
+        "10.0.0.1:443".parse().unwrap(),
+        "[fd00::1]:443".parse().unwrap(),
+    ];
+    let resolver = |server, transport| {
+        Resolver::new(vec![server])
+            .with_transport(transport)
+            .with_tls(settings.clone())
+            .with_cache(None)
+            .with_tcp_timeout(Duration::from_secs(2))
+    };
+
+    let mut addrs = resolver(dot_addr, Transport::Tls).lookup("web.test", "https")?;
+    addrs.sort();
+    assert_eq!(addrs, web);
+    let mut stream = tls::connect(dot_addr, &settings, Duration::from_secs(2))?;
+    let ips: Vec<Ipv4Addr> = resolve(&mut stream, "web.test").unwrap();
+    assert_eq!(ips, vec![Ipv4Addr::new(10, 0, 0, 1)]);
+    println!("tls: {:?}", addrs);
+
+    for method in [DohMethod::Post, DohMethod::Get] {
+        let mut addrs = resolver(doh_addr, Transport::Https(method)).lookup("web.test", "https")?;
+        addrs.sort();
+        assert_eq!(addrs, web);
+        println!("https {:?}: {:?}", method, addrs);
+    }
+    let requests = REQUESTS.lock().unwrap().clone();
+    assert_eq!(
+        requests,
+        [
+            "POST dns.test",
+            "POST dns.test",
+            "GET dns.test",
+            "GET dns.test"
+        ]
+    );
+
+    let err = resolver(dot_addr, Transport::Tls)
+        .lookup("nx.test", "https")
+        .unwrap_err();
+    assert_eq!(WasiErrno::from_io_error(&err), Some(WasiErrno::AiNoname));
+    println!("no such name: {}", err);
+
+    // the certificate is not for this name
+    let wrong = TlsSettings::with_root_certificates("other.test", vec![cert.clone()])?;
+    let err = tls::connect(dot_addr, &wrong, Duration::from_secs(2)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    println!("wrong name: {}", err);
+    // or not trusted at all
+    let err = Resolver::new(vec![dot_addr])
+        .with_transport(Transport::Tls)
+        .with_tls(TlsSettings::new("dns.test")?)
+        .with_cache(None)
+        .lookup("web.test", "https")
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    println!("untrusted: {}", err);
+
+    // a body without a length ends with the connection, so the server has
+    // to close it; one question is asked, as the connection ends with it
+    let doh_at = |path| {
+        Resolver::new(vec![doh_addr])
+            .with_transport(Transport::Https(DohMethod::Post))
+            .with_tls(settings.clone().with_path(path))
+            .with_cache(None)
+            .with_ip_preference(IpPreference::Ipv4Only)
+            .with_tcp_timeout(Duration::from_secs(2))
+    };
+    let addrs = doh_at("/close").lookup("web.test", "https")?;
+    assert_eq!(addrs, &web[..1]);
+    let start = Instant::now();
+    let err = doh_at("/unframed").lookup("web.test", "https").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    println!("no length: {}", err);
+
+    let settings = settings.with_path("/elsewhere");
+    let err = Resolver::new(vec![doh_addr])
+        .with_transport(Transport::Https(DohMethod::Post))
+        .with_tls(settings)
+        .with_cache(None)
+        .lookup("web.test", "https")
+        .unwrap_err();
+    assert!(err.to_string().contains("404"), "{}", err);
+    println!("wrong path: {}", err);
+
+    let err = Resolver::new(vec![dot_addr])
+        .with_transport(Transport::Tls)
+        .with_cache(None)
+        .lookup("web.test", "https")
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    println!("tls: ok");
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use wasmedge_wasi_socket::socket::{self, AddressFamily, AiFlags, AiProtocol, Hints, SocketType};
+use wasmedge_wasi_socket::{IpAddr, SocketAddr};
+
+fn main() -> std::io::Result<()> {
+    // one result per socket type
+    let infos: Vec<_> = socket::getaddrinfo("127.0.0.1", "8080", &Hints::new())?.collect();
+    for info in &infos {
+        println!("{:?}", info);
+        assert_eq!(info.addr, "127.0.0.1:8080".parse::<SocketAddr>().unwrap());
+        assert!(info.canonname.is_none());
+        match info.socktype {
+            SocketType::Stream => assert_eq!(info.protocol, AiProtocol::IPProtoTCP),
+            SocketType::Datagram => assert_eq!(info.protocol, AiProtocol::IPProtoUDP),
+            SocketType::Any => {}
+        }
+    }
+    assert!(infos.iter().any(|i| i.socktype == SocketType::Stream));
+    assert!(infos.iter().any(|i| i.socktype == SocketType::Datagram));
+    println!("socket types: ok");
+
+    let udp = Hints::new()
+        .with_socktype(SocketType::Datagram)
+        .with_protocol(AiProtocol::IPProtoUDP);
+    let infos: Vec<_> = socket::getaddrinfo("fe80::1%1", "53", &udp)?.collect();
+    assert_eq!(infos.len(), 1);
+    assert_eq!(infos[0].socktype, SocketType::Datagram);
+    match infos[0].addr {
+        SocketAddr::V6(addr) => {
+            assert_eq!(addr.ip(), &"fe80::1".parse::<std::net::Ipv6Addr>().unwrap());
+            assert_eq!(addr.port(), 53);
+            assert_eq!(addr.scope_id(), 1);
+        }
+        addr => panic!("expected IPv6, got {}", addr),
+    }
+    println!("ipv6: ok");
+
+    let tcp = Hints::new()
+        .with_socktype(SocketType::Stream)
+        .with_protocol(AiProtocol::IPProtoTCP);
+
+    // the address to listen on when no node is given
+    let passive = tcp
+        .with_flags(AiFlags::PASSIVE | AiFlags::NUMERICSERV)
+        .with_family(AddressFamily::Inet4);
+    let infos: Vec<_> = socket::getaddrinfo("", "8080", &passive)?.collect();
+    assert_eq!(infos[0].addr, "0.0.0.0:8080".parse::<SocketAddr>().unwrap());
+    println!("passive: ok");
+
+    let canonname = tcp.with_flags(AiFlags::CANONNAME);
+    let infos: Vec<_> = socket::getaddrinfo("localhost", "80", &canonname)?.collect();
+    assert!(infos.iter().all(|i| i.addr.ip().is_loopback()));
+    assert!(infos[0].canonname.is_some());
+    println!("canonname: {:?}", infos[0].canonname);
+
+    let mapped = tcp
+        .with_flags(AiFlags::V4MAPPED | AiFlags::ALL)
+        .with_family(AddressFamily::Inet6);
+    let infos: Vec<_> = socket::getaddrinfo("127.0.0.1", "80", &mapped)?.collect();
+    let expected: IpAddr = "::ffff:127.0.0.1".parse().unwrap();
+    assert!(infos.iter().all(|i| i.addr.ip() == expected));
+    println!("v4mapped: ok");
+
+    let numeric = tcp.with_flags(AiFlags::NUMERICHOST);
+    assert!(socket::getaddrinfo("localhost", "80", &numeric).is_err());
+    assert!(socket::getaddrinfo("no-such-host.invalid", "80", &tcp).is_err());
+    println!("errors: ok");
+    Ok(())
+}
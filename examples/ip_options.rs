@@ -0,0 +1,64 @@
+use std::io::{self, ErrorKind};
+use std::time::Duration;
+use wasmedge_wasi_socket::socket::{
+    AddressFamily, Socket, SocketOptLevel, SocketOptName, SocketType, TcpKeepalive,
+};
+use wasmedge_wasi_socket::{Ipv6Addr, TcpListener, TcpStream, UdpSocket};
+
+fn main() -> io::Result<()> {
+    let udp = UdpSocket::bind("127.0.0.1:0")?;
+    udp.set_ttl(7)?;
+    assert_eq!(udp.ttl()?, 7);
+    assert_eq!(udp.set_ttl(0).unwrap_err().kind(), ErrorKind::InvalidInput);
+    let s = udp.as_ref();
+    // CS1, a low priority class
+    s.set_tos(0x20)?;
+    assert_eq!(s.tos()?, 0x20);
+    println!("ip: ok");
+
+    let listener = TcpListener::bind("127.0.0.1:0", false)?;
+    let stream = TcpStream::connect(listener.local_addr()?)?;
+    let s = stream.as_ref();
+    assert!(!stream.nodelay()?);
+    stream.set_nodelay(true)?;
+    assert!(stream.nodelay()?);
+    let nodelay: i32 = s.getsockopt(SocketOptLevel::IpProtoTcp, SocketOptName::TcpNodelay)?;
+    assert_eq!(nodelay, 1);
+    stream.set_ttl(64)?;
+    assert_eq!(stream.ttl()?, 64);
+
+    assert_eq!(stream.keepalive()?, None);
+    let keepalive = TcpKeepalive::new()
+        .with_time(Duration::from_secs(60))
+        .with_interval(Duration::from_millis(9500))
+        .with_retries(3);
+    stream.set_keepalive(Some(&keepalive))?;
+    let expected = keepalive.with_interval(Duration::from_secs(10));
+    assert_eq!(stream.keepalive()?, Some(expected));
+    // settings that are left out are not changed
+    stream.set_keepalive(Some(&TcpKeepalive::new().with_retries(5)))?;
+    assert_eq!(stream.keepalive()?, Some(expected.with_retries(5)));
+    let err = stream
+        .set_keepalive(Some(&TcpKeepalive::new().with_time(Duration::ZERO)))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    stream.set_keepalive(None)?;
+    assert_eq!(stream.keepalive()?, None);
+    println!("tcp: ok");
+
+    // an option must be set at its own level
+    let err = s
+        .setsockopt(SocketOptLevel::SolSocket, SocketOptName::TcpNodelay, 1i32)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    println!("wrong level: {}", err);
+
+    let v6 = Socket::new(AddressFamily::Inet6, SocketType::Datagram)?;
+    v6.set_only_v6(true)?;
+    assert!(v6.only_v6()?);
+    v6.bind(&(Ipv6Addr::UNSPECIFIED, 0).into())?;
+    v6.set_unicast_hops_v6(9)?;
+    assert_eq!(v6.unicast_hops_v6()?, 9);
+    println!("ipv6: ok");
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use std::io;
+use std::time::Duration;
+use wasmedge_wasi_socket::mdns::{self, Querier, Responder, Service};
+use wasmedge_wasi_socket::records::Txt;
+use wasmedge_wasi_socket::socket::{
+    AddressFamily, Socket, SocketOptLevel, SocketOptName, SocketType,
+};
+use wasmedge_wasi_socket::{IpAddr, Ipv4Addr, UdpSocket, WasiErrno};
+
+const WAIT: Duration = Duration::from_millis(300);
+
+/// A socket that hears what is sent to the mDNS group, as other responders
+/// on the host do.
+fn listener() -> io::Result<UdpSocket> {
+    let s = Socket::new(AddressFamily::Inet4, SocketType::Datagram)?;
+    s.setsockopt(SocketOptLevel::SolSocket, SocketOptName::SoReuseaddr, 1i32)?;
+    s.bind(&(Ipv4Addr::UNSPECIFIED, mdns::MDNS_PORT).into())?;
+    let socket = UdpSocket::from(s);
+    socket.join_multicast_v4(&mdns::MDNS_V4, &Ipv4Addr::UNSPECIFIED)?;
+    socket.as_ref().set_recv_timeout(Some(WAIT))?;
+    Ok(socket)
+}
+
+fn main() -> io::Result<()> {
+    let ip: IpAddr = "10.0.0.7".parse().unwrap();
+    let mut responder = Responder::new("edge-1.local", vec![ip])?;
+    responder.add_service(
+        &Service::new("edge 1", "_http._tcp", 8080)
+            .with_txt("path=/api")
+            .with_txt("v=2"),
+    )?;
+    responder.add_service(&Service::new("edge 1", "_grpc._tcp", 9090))?;
+
+    // names that are not valid DNS names are refused up front
+    let long = "a".repeat(64);
+    let err = responder
+        .add_service(&Service::new(&long, "_http._tcp", 8080))
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(responder
+        .add_service(&Service::new("edge 1", "_http.._tcp", 8080))
+        .is_err());
+    let err = Responder::new(&format!("{}.local", long), vec![ip])
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    println!("names: ok");
+    let socket = responder.socket();
+    assert!(socket.multicast_loop_v4()?);
+    assert_eq!(socket.multicast_ttl_v4()?, 255);
+    socket.set_multicast_loop_v4(false)?;
+    assert!(!socket.multicast_loop_v4()?);
+    socket.set_multicast_loop_v4(true)?;
+    println!("socket options: ok");
+
+    let group = listener()?;
+    responder.announce()?;
+    let mut buf = [0u8; 9000];
+    let (n, _) = group.recv_from(&mut buf)?;
+    // a response with one address, two services and their type list
+    assert_eq!(buf[2] & 0x80, 0x80);
+    assert_eq!(u16::from_be_bytes([buf[6], buf[7]]), 9);
+    println!("announced {} bytes", n);
+    group.leave_multicast_v4(&mdns::MDNS_V4, &Ipv4Addr::UNSPECIFIED)?;
+
+    std::thread::spawn(move || responder.serve());
+
+    let querier = Querier::new()?;
+    assert_eq!(querier.resolve("edge-1.local", WAIT)?, [ip]);
+    assert_eq!(querier.resolve("EDGE-1.local.", WAIT)?, [ip]);
+    let err = querier.resolve("edge-2.local", WAIT).unwrap_err();
+    assert_eq!(WasiErrno::from_io_error(&err), Some(WasiErrno::AiNoname));
+    println!("resolve: ok");
+
+    let mut types = querier.service_types(WAIT)?;
+    types.sort();
+    assert_eq!(types, ["_grpc._tcp.local", "_http._tcp.local"]);
+
+    let found = querier.browse("_http._tcp", WAIT)?;
+    assert_eq!(found.len(), 1);
+    let http = &found[0];
+    assert_eq!(http.name, "edge 1._http._tcp.local");
+    assert_eq!(http.host, "edge-1.local");
+    assert_eq!(http.socket_addrs(), ["10.0.0.7:8080".parse().unwrap()]);
+    assert_eq!(http.txt.0, [b"path=/api".to_vec(), b"v=2".to_vec()]);
+    println!("browse: {:?}", http);
+
+    let txt: Vec<Txt> = querier.query("edge 1._grpc._tcp.local", WAIT)?;
+    assert_eq!(txt, [Txt(vec![vec![]])]);
+    assert!(querier.browse("_ipp._tcp", WAIT)?.is_empty());
+    println!("mdns: ok");
+    Ok(())
+}
@@ -9,7 +9,7 @@ fn main() -> std::io::Result<()> {
     let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port))?;
     stream.set_nonblocking(true)?;
     println!("sending hello message");
-    stream.write(b"Hello\n")?;
+    stream.write_all(b"Hello\n")?;
 
     loop {
         let mut buf = [0; 128];
@@ -0,0 +1,23 @@
+use std::time::Duration;
+use wasmedge_wasi_socket::dns::{nslookup_with_options, DnsOptions};
+
+fn main() {
+    let dns_server = std::env::var("DNS_SERVER").unwrap_or_else(|_| "8.8.8.8:53".to_string());
+    let options = DnsOptions {
+        timeout: Duration::from_secs(2),
+        attempts: 3,
+        ..Default::default()
+    };
+
+    let mut code = 0;
+    for name in std::env::args().skip(1) {
+        match nslookup_with_options(&dns_server, &name, "http", &options) {
+            Ok(addrs) => println!("{name}: {:?}", addrs),
+            Err(e) => {
+                eprintln!("Error resolving {:?}: {}", name, e);
+                code = 1;
+            }
+        }
+    }
+    std::process::exit(code);
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+use wasmedge_wasi_socket::cache::DnsCache;
+use wasmedge_wasi_socket::pending::PendingLookup;
+use wasmedge_wasi_socket::poll::{poll, EventType, Subscription};
+use wasmedge_wasi_socket::{
+    IpAddr, Resolver, SocketAddr, TcpListener, TcpStream, UdpSocket, WasiErrno,
+};
+
+const SERVER: u64 = 0;
+const TYPE_A: u16 = 1;
+const TYPE_CNAME: u16 = 5;
+const TYPE_AAAA: u16 = 28;
+
+/// A DNS server for the `test` zone, run in the same event loop as the
+/// lookups, over UDP and TCP on the same port. It drops the first query for
+/// each type of `slow.test`, and truncates the UDP answers for `big.test`.
+struct Server {
+    socket: UdpSocket,
+    listener: TcpListener,
+    // each connection with what has been read from it
+    conns: Vec<(TcpStream, Vec<u8>)>,
+    dropped: Vec<(String, u16)>,
+    tcp_queries: usize,
+}
+
+impl Server {
+    fn bind() -> io::Result<Self> {
+        loop {
+            let listener = TcpListener::bind("127.0.0.1:0", true)?;
+            let addr = listener.local_addr()?;
+            // the port may be taken for UDP
+            if let Ok(socket) = UdpSocket::bind(addr) {
+                socket.as_ref().set_nonblocking(true)?;
+                return Ok(Server {
+                    socket,
+                    listener,
+                    conns: vec![],
+                    dropped: vec![],
+                    tcp_queries: 0,
+                });
+            }
+        }
+    }
+
+    fn subscriptions(&self) -> Vec<Subscription> {
+        let mut subs = vec![
+            Subscription::io(SERVER, &self.socket, true, false, None),
+            Subscription::io(SERVER, &self.listener, true, false, None),
+        ];
+        for (stream, _) in &self.conns {
+            subs.push(Subscription::io(SERVER, stream, true, false, None));
+        }
+        subs
+    }
+
+    fn handle(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 512];
+        loop {
+            let (n, from) = match self.socket.recv_from(&mut buf) {
+                Ok(r) => r,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            };
+            if let Some(response) = self.answer(&buf[..n], false) {
+                self.socket.send_to(&response, from)?;
+            }
+        }
+        loop {
+            match self.listener.accept(true) {
+                Ok((stream, _)) => self.conns.push((stream, vec![])),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        let mut conns = std::mem::take(&mut self.conns);
+        conns.retain_mut(|(stream, read)| {
+            let open = loop {
+                match stream.read(&mut buf) {
+                    Ok(0) => break false,
+                    Ok(n) => read.extend_from_slice(&buf[..n]),
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break true,
+                    Err(_) => break false,
+                }
+            };
+            // each query is preceded by its length
+            while read.len() >= 2 {
+                let len = u16::from_be_bytes([read[0], read[1]]) as usize;
+                if read.len() < 2 + len {
+                    break;
+                }
+                let query: Vec<u8> = read.drain(..2 + len).skip(2).collect();
+                self.tcp_queries += 1;
+                if let Some(response) = self.answer(&query, true) {
+                    let mut framed = (response.len() as u16).to_be_bytes().to_vec();
+                    framed.extend_from_slice(&response);
+                    stream.write_all(&framed).unwrap();
+                }
+            }
+            open
+        });
+        self.conns = conns;
+        Ok(())
+    }
+
+    fn answer(&mut self, query: &[u8], tcp: bool) -> Option<Vec<u8>> {
+        let (name, end) = qname(query);
+        let qtype = u16::from_be_bytes([query[end], query[end + 1]]);
+        if name == "slow.test" && !self.dropped.contains(&(name.clone(), qtype)) {
+            self.dropped.push((name, qtype));
+            return None;
+        }
+        if name == "big.test" && !tcp {
+            let mut truncated = response(query, end + 4, 0, &[]);
+            truncated[2] |= 0x02;
+            return Some(truncated);
+        }
+        let (rcode, answers) = match (name.as_str(), qtype) {
+            ("web.test" | "slow.test", TYPE_A) => (0, vec![(TYPE_A, vec![10, 0, 0, 1])]),
+            ("web.test" | "slow.test", TYPE_AAAA) => {
+                let ip: std::net::Ipv6Addr = "fd00::1".parse().unwrap();
+                (0, vec![(TYPE_AAAA, ip.octets().to_vec())])
+            }
+            ("big.test", TYPE_A) => (0, vec![(TYPE_A, vec![10, 0, 0, 2])]),
+            ("big.test", TYPE_AAAA) => {
+                let ip: std::net::Ipv6Addr = "fd00::2".parse().unwrap();
+                (0, vec![(TYPE_AAAA, ip.octets().to_vec())])
+            }
+            ("alias.test", _) => (0, vec![(TYPE_CNAME, encode_name("web.test"))]),
+            ("empty.test", _) => (0, vec![]),
+            _ => (3, vec![]),
+        };
+        Some(response(query, end + 4, rcode, &answers))
+    }
+}
+
+fn qname(query: &[u8]) -> (String, usize) {
+    let mut labels = vec![];
+    let mut pos = 12;
+    while query[pos] != 0 {
+        let len = query[pos] as usize;
+        labels.push(String::from_utf8_lossy(&query[pos + 1..pos + 1 + len]).to_string());
+        pos += 1 + len;
+    }
+    (labels.join("."), pos + 1)
+}
+
+fn encode_name(name: &str) -> Vec<u8> {
+    let mut out = vec![];
+    for label in name.split('.') {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+    out
+}
+
+// the question of `query` ends at `end`; each answer is owned by it
+fn response(query: &[u8], end: usize, rcode: u8, answers: &[(u16, Vec<u8>)]) -> Vec<u8> {
+    let mut out = query[..2].to_vec();
+    out.extend_from_slice(&[0x81, 0x80 | rcode, 0, 1]);
+    out.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+    out.extend_from_slice(&[0, 0, 0, 0]);
+    out.extend_from_slice(&query[12..end]);
+    for (rtype, data) in answers {
+        out.extend_from_slice(&[0xc0, 0x0c]);
+        out.extend_from_slice(&rtype.to_be_bytes());
+        out.extend_from_slice(&[0, 1, 0, 0, 0, 60]);
+        out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        out.extend_from_slice(data);
+    }
+    out
+}
+
+fn main() -> io::Result<()> {
+    let mut server = Server::bind()?;
+    let server_addr = server.listener.local_addr()?;
+
+    // the server only answers over TCP when the event loop runs, so a
+    // lookup that waited for the TCP answer would time out
+    let resolver = Resolver::new(vec![server_addr])
+        .with_timeout(Duration::from_millis(200))
+        .with_tcp_timeout(Duration::from_secs(2))
+        .with_cache(Some(Arc::new(DnsCache::new(16))))
+        .with_search(vec!["test".to_string()]);
+
+    let names = [
+        "web.test",
+        "alias.test",
+        "slow.test",
+        "web",
+        "nx.invalid",
+        "empty.test",
+        "big.test",
+    ];
+    let mut lookups: HashMap<u64, (&str, PendingLookup)> = HashMap::new();
+    for (i, name) in names.iter().enumerate() {
+        lookups.insert(i as u64 + 1, (name, resolver.start_lookup(name, "http")?));
+    }
+    let mut results = HashMap::new();
+
+    while !lookups.is_empty() {
+        let mut subs = server.subscriptions();
+        subs.extend(
+            lookups
+                .iter()
+                .map(|(&id, (_, lookup))| lookup.subscription(id)),
+        );
+        for event in poll(&subs)? {
+            if let EventType::Error(e) = event.event_type {
+                return Err(e);
+            }
+            if event.userdata == SERVER {
+                server.handle()?;
+                continue;
+            }
+            let (name, lookup) = match lookups.get_mut(&event.userdata) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let result = match lookup.try_wait() {
+                Ok(None) => continue,
+                Ok(Some(addrs)) => Ok(addrs),
+                Err(e) => Err(e),
+            };
+            println!("{}: {:?}", name, result);
+            results.insert(*name, result);
+            lookups.remove(&event.userdata);
+        }
+    }
+
+    let web: Vec<SocketAddr> = vec![
+        "10.0.0.1:80".parse().unwrap(),
+        "[fd00::1]:80".parse().unwrap(),
+    ];
+    let mut addrs = results.remove("web.test").unwrap()?;
+    addrs.sort();
+    assert_eq!(addrs, web);
+    for name in ["alias.test", "slow.test", "web"] {
+        let mut addrs = results.remove(name).unwrap()?;
+        addrs.sort();
+        assert_eq!(addrs, web, "{}", name);
+    }
+    let mut addrs = results.remove("big.test").unwrap()?;
+    addrs.sort();
+    let big: Vec<SocketAddr> = vec![
+        "10.0.0.2:80".parse().unwrap(),
+        "[fd00::2]:80".parse().unwrap(),
+    ];
+    assert_eq!(addrs, big);
+    assert_eq!(server.tcp_queries, 2);
+    let errno = |r: io::Result<Vec<SocketAddr>>| WasiErrno::from_io_error(&r.unwrap_err());
+    assert_eq!(
+        errno(results.remove("nx.invalid").unwrap()),
+        Some(WasiErrno::AiNoname)
+    );
+    assert_eq!(
+        errno(results.remove("empty.test").unwrap()),
+        Some(WasiErrno::AiNodata)
+    );
+    println!("event loop: ok");
+
+    // answered from the cache without waiting
+    let mut lookup = resolver.start_lookup("web.test", "443")?;
+    let addrs = lookup.try_wait()?.expect("cached");
+    assert!(addrs.iter().all(|a| a.port() == 443));
+    let ip: IpAddr = "10.0.0.1".parse().unwrap();
+    assert!(addrs.iter().any(|a| a.ip() == ip));
+    println!("cache: ok");
+    Ok(())
+}
@@ -0,0 +1,29 @@
+use std::time::Duration;
+use wasmedge_wasi_socket::resolver::IpPreference;
+use wasmedge_wasi_socket::{Resolver, SocketAddr};
+
+fn main() {
+    let dns_server: SocketAddr = std::env::var("DNS_SERVER")
+        .unwrap_or_else(|_| "8.8.8.8:53".to_string())
+        .parse()
+        .expect("DNS_SERVER should be an ip:port address");
+
+    // nothing answers on the discard port, so every query fails over to
+    // the second server after a second
+    let resolver = Resolver::new(vec!["127.0.0.1:9".parse().unwrap(), dns_server])
+        .with_timeout(Duration::from_secs(1))
+        .with_attempts(1)
+        .with_ip_preference(IpPreference::Ipv6First);
+
+    let mut code = 0;
+    for name in std::env::args().skip(1) {
+        match resolver.lookup(&name, "http") {
+            Ok(addrs) => println!("{name}: {:?}", addrs),
+            Err(e) => {
+                eprintln!("Error resolving {:?}: {}", name, e);
+                code = 1;
+            }
+        }
+    }
+    std::process::exit(code);
+}
@@ -0,0 +1,30 @@
+use std::net::IpAddr;
+use wasmedge_wasi_socket::dns::reverse_name;
+use wasmedge_wasi_socket::Resolver;
+
+fn main() -> std::io::Result<()> {
+    let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+    assert_eq!(reverse_name(ip("192.0.2.10")), "10.2.0.192.in-addr.arpa");
+    assert_eq!(
+        reverse_name(ip("::ffff:192.0.2.10")),
+        "10.2.0.192.in-addr.arpa"
+    );
+    assert_eq!(
+        reverse_name(ip("2001:db8::567:89ab")),
+        "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+    );
+
+    let resolver = Resolver::from_env()?;
+    for arg in std::env::args().skip(1) {
+        let addr = ip(&arg);
+        match resolver.lookup_addr(addr) {
+            Ok(names) => println!("{}: {:?}", addr, names),
+            Err(e) => println!("{}: {}", addr, e),
+        }
+        match resolver.lookup_addr_confirmed(addr) {
+            Ok(names) => println!("{} confirmed: {:?}", addr, names),
+            Err(e) => println!("{} not confirmed: {}", addr, e),
+        }
+    }
+    Ok(())
+}
@@ -14,7 +14,7 @@ fn main() -> std::io::Result<()> {
     // from slice
     let addr1 = SocketAddr::from(([0, 0, 0, 0], 80));
     let addr2 = SocketAddr::from(([127, 0, 0, 1], 443));
-    let addrs = vec![addr1, addr2];
+    let addrs = [addr1, addr2];
 
     let mut addrs_iter = (&addrs[..]).to_socket_addrs().unwrap();
 
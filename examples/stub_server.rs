@@ -0,0 +1,247 @@
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use wasmedge_wasi_socket::cache::DnsCache;
+use wasmedge_wasi_socket::dns::{DnsOptions, Transport};
+use wasmedge_wasi_socket::records::{Srv, Txt};
+use wasmedge_wasi_socket::stub::{Fault, RecordData, StubServer, Zone};
+use wasmedge_wasi_socket::{
+    nslookup_with_dns_server, resolve, Ipv4Addr, Resolver, SocketAddr, TcpStream, UdpSocket,
+    WasiErrno,
+};
+
+fn zone() -> io::Result<Zone> {
+    // a TTL of 0 keeps the shared cache from answering for the server
+    let mut zone = Zone::new("test")
+        .with_ttl(0)
+        .with_negative_ttl(0)
+        .with("web.test", Ipv4Addr::new(10, 0, 0, 1))
+        .with("web.test", "fd00::1".parse::<std::net::Ipv6Addr>().unwrap())
+        .with("v6.test", "fd00::6".parse::<std::net::Ipv6Addr>().unwrap())
+        .with("alias.test", RecordData::Cname("web.test".to_string()))
+        .with(
+            "_http._tcp.web.test",
+            Srv {
+                priority: 10,
+                weight: 5,
+                port: 8080,
+                target: "web.test".to_string(),
+            },
+        )
+        .with("web.test", Txt(vec![b"v=spf1 -all".to_vec()]))
+        .with(
+            &format!("{}.test", "a".repeat(63)),
+            Ipv4Addr::new(10, 0, 0, 63),
+        );
+    // too many addresses for a UDP answer
+    for i in 0..40 {
+        zone.insert("big.test", 0, Ipv4Addr::new(10, 0, 1, i).into())?;
+    }
+    // names that do not fit in a message are refused when they are added
+    let long = format!("{}.test", "a".repeat(64));
+    let err = zone
+        .insert(&long, 0, Ipv4Addr::LOCALHOST.into())
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let cname = RecordData::Cname("web..test".to_string());
+    assert!(zone.insert("bad.test", 0, cname).is_err());
+    Ok(zone)
+}
+
+/// A server that answers every query for `web.test` with an address for
+/// another name, as a forged response would.
+fn forger() -> io::Result<SocketAddr> {
+    let socket = UdpSocket::bind("127.0.0.1:0")?;
+    let addr = socket.as_ref().get_local()?;
+    let mut forged = vec![0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0];
+    forged.extend_from_slice(b"\x04evil\x04test\x00\x00\x01\x00\x01");
+    forged.extend_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 1, 0x2c, 0, 4, 10, 6, 6, 6]);
+    std::thread::spawn(move || loop {
+        let mut buf = [0u8; 512];
+        let (_, from) = socket.recv_from(&mut buf).unwrap();
+        let mut response = buf[..2].to_vec();
+        response.extend_from_slice(&forged);
+        socket.send_to(&response, from).unwrap();
+    });
+    Ok(addr)
+}
+
+fn main() -> io::Result<()> {
+    // with an address, serve the zone there until stopped
+    if let Some(addr) = std::env::args().nth(1) {
+        let server = StubServer::bind(addr.as_str(), zone()?)?;
+        println!("serving the test zone on {}", server.addr());
+        return server.serve();
+    }
+
+    let server = StubServer::bind("127.0.0.1:0", zone()?)?;
+    let serving = server.clone();
+    std::thread::spawn(move || serving.serve());
+    let addr = server.addr().to_string();
+    let web: SocketAddr = "10.0.0.1:80".parse().unwrap();
+    let web6: SocketAddr = "[fd00::1]:80".parse().unwrap();
+
+    // A and AAAA are asked for together; the IPv4 address sorts ahead of
+    // the unique local IPv6 one
+    assert_eq!(
+        nslookup_with_dns_server(&addr, "web.test", "80")?,
+        [web, web6]
+    );
+    assert_eq!(
+        nslookup_with_dns_server(&addr, "alias.test", "80")?,
+        [web, web6]
+    );
+    assert_eq!(
+        nslookup_with_dns_server(&addr, "v6.test", "80")?,
+        ["[fd00::6]:80".parse::<SocketAddr>().unwrap()]
+    );
+    assert_eq!(nslookup_with_dns_server(&addr, "big.test", "80")?.len(), 40);
+    let tcp: Vec<String> = server
+        .queries()
+        .into_iter()
+        .filter(|q| q.tcp)
+        .map(|q| q.name)
+        .collect();
+    assert_eq!(tcp, ["big.test"]);
+    println!("udp: ok");
+
+    let mut stream = TcpStream::connect(server.addr())?;
+    let ips: Vec<Ipv4Addr> = resolve(&mut stream, "alias.test").unwrap();
+    assert_eq!(ips, [Ipv4Addr::new(10, 0, 0, 1)]);
+    let srv: Vec<Srv> = resolve(&mut stream, "_http._tcp.web.test").unwrap();
+    assert_eq!(srv[0].port, 8080);
+    let txt: Vec<Txt> = resolve(&mut stream, "web.test").unwrap();
+    assert_eq!(txt[0].text(), "v=spf1 -all");
+    println!("tcp: ok");
+
+    let errno = |r: io::Result<Vec<SocketAddr>>| WasiErrno::from_io_error(&r.unwrap_err());
+    assert_eq!(
+        errno(nslookup_with_dns_server(&addr, "nx.test", "80")),
+        Some(WasiErrno::AiNoname)
+    );
+    // names outside the zone are refused
+    assert!(nslookup_with_dns_server(&addr, "example.com", "80").is_err());
+    // a label may hold 63 octets, but no more, and may not be empty
+    let long = format!("{}.test.", "a".repeat(63));
+    assert_eq!(nslookup_with_dns_server(&addr, &long, "80")?.len(), 1);
+    for name in [format!("{}.test", "a".repeat(64)), "web..test".to_string()] {
+        let err = nslookup_with_dns_server(&addr, &name, "80").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    server.inject_times("web.test", Fault::NxDomain, 1);
+    assert_eq!(
+        errno(nslookup_with_dns_server(&addr, "web.test", "80")),
+        Some(WasiErrno::AiNoname)
+    );
+    assert_eq!(
+        nslookup_with_dns_server(&addr, "web.test", "80")?,
+        [web, web6]
+    );
+
+    server.inject("web.test", Fault::ServFail);
+    assert_eq!(
+        errno(nslookup_with_dns_server(&addr, "web.test", "80")),
+        Some(WasiErrno::AiAgain)
+    );
+    server.clear_faults();
+
+    let before = server.queries().len();
+    server.inject("web.test", Fault::Truncate);
+    assert_eq!(
+        nslookup_with_dns_server(&addr, "web.test", "80")?,
+        [web, web6]
+    );
+    let retried: Vec<bool> = server.queries()[before..].iter().map(|q| q.tcp).collect();
+    // both questions go out over UDP, and are both asked again over TCP
+    assert_eq!(retried, [false, false, true, true]);
+    server.clear_faults();
+
+    // a server failure from the first server is not lost to a timeout from
+    // the next
+    let silent = UdpSocket::bind("127.0.0.1:0")?;
+    let options = DnsOptions {
+        timeout: Duration::from_millis(200),
+        attempts: 1,
+        ..DnsOptions::default()
+    };
+    let resolver = Resolver::new(vec![server.addr(), silent.as_ref().get_local()?])
+        .with_options(options)
+        .with_cache(None);
+    server.inject("web.test", Fault::ServFail);
+    assert_eq!(
+        errno(resolver.lookup("web.test", "80")),
+        Some(WasiErrno::AiAgain)
+    );
+    server.clear_faults();
+    println!("faults: ok");
+
+    let options = DnsOptions {
+        timeout: Duration::from_millis(300),
+        attempts: 2,
+        ..DnsOptions::default()
+    };
+    let resolver = Resolver::new(vec![server.addr()])
+        .with_options(options)
+        .with_cache(None);
+    server.inject_times("web.test", Fault::Delay(Duration::from_millis(100)), 2);
+    let start = Instant::now();
+    assert_eq!(resolver.lookup("web.test", "80")?.len(), 2);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    // a query that is never answered is sent again
+    server.inject_times("web.test", Fault::Drop, 1);
+    assert_eq!(resolver.lookup("web.test", "80")?.len(), 2);
+    server.inject("web.test", Fault::Delay(Duration::from_secs(2)));
+    assert_eq!(
+        errno(resolver.lookup("web.test", "80")),
+        Some(WasiErrno::Timedout)
+    );
+    // and over TCP
+    let err = resolver
+        .clone()
+        .with_transport(Transport::Tcp)
+        .with_tcp_timeout(Duration::from_millis(300))
+        .lookup("web.test", "80")
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    server.clear_faults();
+    println!("delays: ok");
+
+    // answers are cached for their TTL, but failures are not
+    let zone = zone()?
+        .with_ttl(60)
+        .with("cached.test", Ipv4Addr::new(10, 0, 0, 2))
+        .with("flaky.test", Ipv4Addr::new(10, 0, 0, 3));
+    server.set_zone(zone);
+    let cache = Arc::new(DnsCache::new(16));
+    let options = DnsOptions {
+        timeout: Duration::from_millis(300),
+        attempts: 1,
+        ..DnsOptions::default()
+    };
+    let resolver = Resolver::new(vec![server.addr()])
+        .with_options(options.clone())
+        .with_cache(Some(cache.clone()));
+    let asked = |name: &str| server.queries().iter().filter(|q| q.name == name).count();
+    for _ in 0..2 {
+        let ips: Vec<Ipv4Addr> = resolver.query("cached.test")?;
+        assert_eq!(ips, [Ipv4Addr::new(10, 0, 0, 2)]);
+    }
+    assert_eq!(asked("cached.test"), 1);
+    server.inject_times("flaky.test", Fault::ServFail, 1);
+    assert!(resolver.query::<Ipv4Addr>("flaky.test").is_err());
+    assert_eq!(resolver.query::<Ipv4Addr>("flaky.test")?.len(), 1);
+    assert_eq!(asked("flaky.test"), 2);
+
+    // a response to another question is neither used nor cached
+    let cache = Arc::new(DnsCache::new(16));
+    let err = Resolver::new(vec![forger()?])
+        .with_options(options)
+        .with_cache(Some(cache.clone()))
+        .query::<Ipv4Addr>("web.test")
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(cache.is_empty());
+    println!("cache: ok");
+    Ok(())
+}
@@ -4,12 +4,12 @@ use wasmedge_wasi_socket::{Shutdown, TcpListener, TcpStream};
 
 fn handle_client((mut stream, addr): (TcpStream, SocketAddr)) -> std::io::Result<()> {
     let local_addr = stream.local_addr()?;
-    println!("{} <-> {}", addr.to_string(), local_addr);
+    println!("{} <-> {}", addr, local_addr);
     let mut buf = String::new();
     stream.read_to_string(&mut buf)?;
     println!("get message: {}", buf);
     println!("sendback reversed message...");
-    stream.write(&buf.chars().rev().collect::<String>().into_bytes())?;
+    stream.write_all(&buf.chars().rev().collect::<String>().into_bytes())?;
 
     stream.shutdown(Shutdown::Both)?;
     Ok(())
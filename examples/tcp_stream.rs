@@ -8,7 +8,7 @@ fn main() -> std::io::Result<()> {
     println!("local address {}", stream.local_addr().unwrap());
     println!("peer address {}", stream.peer_addr().unwrap());
     println!("sending hello message...");
-    stream.write(b"hello")?;
+    stream.write_all(b"hello")?;
     stream.shutdown(Shutdown::Both)?;
     Ok(())
 }
@@ -0,0 +1,19 @@
+use std::io::Write;
+use wasmedge_wasi_socket::{Shutdown, SocketAddr, TcpStream};
+
+fn main() -> std::io::Result<()> {
+    let port = std::env::var("PORT").unwrap_or("1234".to_string());
+    let host = std::env::var("HOST").unwrap_or("::1".to_string());
+    let addr: SocketAddr = format!("[{}]:{}", host, port)
+        .parse()
+        .expect("invalid IPv6 address");
+    println!("connect to {}", addr);
+    let mut stream = TcpStream::connect(addr)?;
+    println!("local address {}", stream.local_addr().unwrap());
+    println!("peer address {}", stream.peer_addr().unwrap());
+    assert!(stream.peer_addr()?.is_ipv6());
+    println!("sending hello message...");
+    stream.write_all(b"hello")?;
+    stream.shutdown(Shutdown::Both)?;
+    Ok(())
+}
@@ -11,6 +11,6 @@ fn main() -> std::io::Result<()> {
     let buf = &mut buf[..size];
     buf.reverse();
 
-    socket.send_to(buf, &addr)?;
+    socket.send_to(buf, addr)?;
     Ok(())
 }
@@ -12,7 +12,10 @@ fn main() -> std::io::Result<()> {
     let (size, addr) = socket.recv_from(&mut buf).unwrap();
 
     match std::str::from_utf8(&buf[..size]) {
-        Ok(s) => Ok(println!("{} {}", s, addr)),
+        Ok(s) => {
+            println!("{} {}", s, addr);
+            Ok(())
+        }
         Err(e) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
     }
 }
@@ -0,0 +1,263 @@
+//! Destination address ordering after RFC 6724, section 6.
+//!
+//! The source address for each destination is found by connecting a UDP
+//! socket to it, which sends nothing. Rules 3, 4 and 7 need information the
+//! host does not expose and are skipped.
+
+use crate::socket::{AddressFamily, Socket, SocketType};
+use std::cmp::Reverse;
+use std::net::{IpAddr, SocketAddr};
+
+// (prefix, prefix length, precedence, label), longest prefixes first
+const POLICY_TABLE: [(u128, u32, u8, u8); 9] = [
+    (0x0000_0000_0000_0000_0000_0000_0000_0001, 128, 50, 0),
+    (0x0000_0000_0000_0000_0000_ffff_0000_0000, 96, 35, 4),
+    (0x0000_0000_0000_0000_0000_0000_0000_0000, 96, 1, 3),
+    (0x2001_0000_0000_0000_0000_0000_0000_0000, 32, 5, 5),
+    (0x2002_0000_0000_0000_0000_0000_0000_0000, 16, 30, 2),
+    (0x3ffe_0000_0000_0000_0000_0000_0000_0000, 16, 1, 12),
+    (0xfec0_0000_0000_0000_0000_0000_0000_0000, 10, 1, 11),
+    (0xfc00_0000_0000_0000_0000_0000_0000_0000, 7, 3, 13),
+    (0x0000_0000_0000_0000_0000_0000_0000_0000, 0, 40, 1),
+];
+
+const SCOPE_LINK_LOCAL: u8 = 0x2;
+const SCOPE_SITE_LOCAL: u8 = 0x5;
+const SCOPE_GLOBAL: u8 = 0xe;
+
+#[derive(Clone, Copy)]
+struct Candidate {
+    addr: IpAddr,
+    source: Option<IpAddr>,
+}
+
+/// Sort `addrs` so that the destinations most likely to work come first.
+/// Addresses that compare equal keep their order.
+pub(crate) fn sort(addrs: &mut [IpAddr]) {
+    if addrs.len() < 2 {
+        return;
+    }
+    let candidates = addrs
+        .iter()
+        .map(|&addr| Candidate {
+            addr,
+            source: source_for(addr),
+        })
+        .collect();
+    for (addr, c) in addrs.iter_mut().zip(order(candidates)) {
+        *addr = c.addr;
+    }
+}
+
+fn source_for(addr: IpAddr) -> Option<IpAddr> {
+    let dest = SocketAddr::new(addr, 9);
+    let s = Socket::new(AddressFamily::from(&dest), SocketType::Datagram).ok()?;
+    s.connect(&dest).ok()?;
+    s.get_local().ok().map(|a| a.ip())
+}
+
+/// `candidates` in the order of the rules.
+///
+/// Rules 1 to 8 look at each destination on its own, so sorting by them and
+/// then by the original position (rule 10) is a total order. Rule 9 only
+/// compares destinations of the same family, which is not transitive across
+/// families; within each run of ties, it reorders the destinations of one
+/// family among the places that family already holds.
+fn order(candidates: Vec<Candidate>) -> Vec<Candidate> {
+    let mut indexed: Vec<(Candidate, usize)> = candidates.into_iter().zip(0..).collect();
+    indexed.sort_by_key(|(c, i)| (key(c), *i));
+    for run in indexed.chunk_by_mut(|(a, _), (b, _)| key(a) == key(b)) {
+        for v4 in [true, false] {
+            let slots: Vec<usize> = (0..run.len())
+                .filter(|&k| run[k].0.addr.is_ipv4() == v4)
+                .collect();
+            let mut family: Vec<(Candidate, usize)> = slots.iter().map(|&k| run[k]).collect();
+            // rule 9: use longest matching prefix
+            family.sort_by_key(|(c, i)| (Reverse(prefix_len(c)), *i));
+            for (k, entry) in slots.into_iter().zip(family) {
+                run[k] = entry;
+            }
+        }
+    }
+    indexed.into_iter().map(|(c, _)| c).collect()
+}
+
+/// What rules 1 to 8 look at, smallest first.
+type Key = (bool, bool, bool, Reverse<u8>, u8);
+
+fn key(c: &Candidate) -> Key {
+    let (d, s) = match c.source {
+        Some(s) => (c.addr, s),
+        // rule 1: avoid unusable destinations
+        None => return (true, false, false, Reverse(0), 0),
+    };
+    (
+        false,
+        // rule 2: prefer matching scope
+        scope(d) != scope(s),
+        // rule 5: prefer matching label
+        policy(d).1 != policy(s).1,
+        // rule 6: prefer higher precedence
+        Reverse(policy(d).0),
+        // rule 8: prefer smaller scope
+        scope(d),
+    )
+}
+
+fn prefix_len(c: &Candidate) -> u32 {
+    c.source.map_or(0, |s| common_prefix_len(c.addr, s))
+}
+
+fn as_u128(addr: IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(a) => u128::from(a.to_ipv6_mapped()),
+        IpAddr::V6(a) => u128::from(a),
+    }
+}
+
+/// The precedence and label of `addr` in the default policy table.
+fn policy(addr: IpAddr) -> (u8, u8) {
+    let bits = as_u128(addr);
+    for &(prefix, len, precedence, label) in POLICY_TABLE.iter() {
+        if len == 0 || (bits ^ prefix) >> (128 - len) == 0 {
+            return (precedence, label);
+        }
+    }
+    unreachable!("::/0 matches every address")
+}
+
+fn scope(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(a) => {
+            if a.is_loopback() || a.is_link_local() {
+                SCOPE_LINK_LOCAL
+            } else {
+                SCOPE_GLOBAL
+            }
+        }
+        IpAddr::V6(a) => {
+            let seg = a.segments()[0];
+            if a.is_multicast() {
+                (seg & 0x000f) as u8
+            } else if a.is_loopback() || seg & 0xffc0 == 0xfe80 {
+                SCOPE_LINK_LOCAL
+            } else if seg & 0xffc0 == 0xfec0 {
+                SCOPE_SITE_LOCAL
+            } else {
+                SCOPE_GLOBAL
+            }
+        }
+    }
+}
+
+/// The number of leading bits `dest` shares with `source`, up to the length
+/// of an IPv6 subnet prefix.
+fn common_prefix_len(dest: IpAddr, source: IpAddr) -> u32 {
+    match (dest, source) {
+        (IpAddr::V4(d), IpAddr::V4(s)) => (u32::from(d) ^ u32::from(s)).leading_zeros(),
+        (IpAddr::V6(d), IpAddr::V6(s)) => (u128::from(d) ^ u128::from(s)).leading_zeros().min(64),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn candidate(addr: &str, source: Option<&str>) -> Candidate {
+        Candidate {
+            addr: ip(addr),
+            source: source.map(ip),
+        }
+    }
+
+    #[test]
+    fn policy_table() {
+        assert_eq!(policy(ip("::1")), (50, 0));
+        assert_eq!(policy(ip("10.0.0.1")), (35, 4));
+        assert_eq!(policy(ip("2001::1")), (5, 5));
+        assert_eq!(policy(ip("2002::1")), (30, 2));
+        assert_eq!(policy(ip("fd00::1")), (3, 13));
+        assert_eq!(policy(ip("2606:4700::1")), (40, 1));
+    }
+
+    #[test]
+    fn scopes() {
+        assert_eq!(scope(ip("127.0.0.1")), SCOPE_LINK_LOCAL);
+        assert_eq!(scope(ip("169.254.1.1")), SCOPE_LINK_LOCAL);
+        assert_eq!(scope(ip("8.8.8.8")), SCOPE_GLOBAL);
+        assert_eq!(scope(ip("fe80::1")), SCOPE_LINK_LOCAL);
+        assert_eq!(scope(ip("fec0::1")), SCOPE_SITE_LOCAL);
+        assert_eq!(scope(ip("ff05::1")), SCOPE_SITE_LOCAL);
+        assert_eq!(scope(ip("2001:db8::1")), SCOPE_GLOBAL);
+    }
+
+    fn ordered(candidates: Vec<Candidate>) -> Vec<IpAddr> {
+        order(candidates).into_iter().map(|c| c.addr).collect()
+    }
+
+    #[test]
+    fn rules() {
+        // rule 1: a destination without a source goes last
+        let usable = candidate("2001:db8::1", Some("2001:db8::2"));
+        let unusable = candidate("10.0.0.1", None);
+        assert!(key(&usable) < key(&unusable));
+        // rule 2: the scope of the source matches
+        let global = candidate("2001:db8::1", Some("2001:db8::2"));
+        let mismatched = candidate("fe80::1", Some("2001:db8::2"));
+        assert!(key(&global) < key(&mismatched));
+        // rule 6: native IPv6 before IPv4, IPv4 before unique local IPv6
+        let v4 = candidate("192.0.2.1", Some("192.0.2.2"));
+        let ula = candidate("fd00::1", Some("fd00::2"));
+        assert!(key(&global) < key(&v4));
+        assert!(key(&v4) < key(&ula));
+        // rule 9: the longer prefix shared with the source
+        let near = candidate("192.0.2.1", Some("192.0.2.9"));
+        let far = candidate("198.51.100.1", Some("192.0.2.9"));
+        assert_eq!(
+            ordered(vec![far, near]),
+            [ip("192.0.2.1"), ip("198.51.100.1")]
+        );
+        assert_eq!(common_prefix_len(ip("fd00::1"), ip("fd00::2")), 64);
+    }
+
+    #[test]
+    fn ties_keep_their_order() {
+        let a = candidate("192.0.2.1", Some("192.0.2.9"));
+        let b = candidate("192.0.2.2", Some("192.0.2.9"));
+        let unusable = candidate("10.0.0.1", None);
+        let other = candidate("10.0.0.2", None);
+        assert_eq!(
+            ordered(vec![unusable, b, other, a]),
+            [
+                ip("192.0.2.2"),
+                ip("192.0.2.1"),
+                ip("10.0.0.1"),
+                ip("10.0.0.2")
+            ]
+        );
+    }
+
+    #[test]
+    fn rule_9_across_families() {
+        // the keys tie, but rule 9 only orders the two IPv4 addresses: the
+        // nearer one takes the first IPv4 place, and the IPv4-mapped IPv6
+        // address keeps its place between them
+        let far = candidate("198.51.100.1", Some("192.0.2.9"));
+        let mapped = candidate("::ffff:192.0.2.200", Some("::ffff:192.0.2.9"));
+        let near = candidate("192.0.2.1", Some("192.0.2.9"));
+        assert_eq!(key(&far), key(&mapped));
+        assert_eq!(
+            ordered(vec![far, mapped, near]),
+            [
+                ip("192.0.2.1"),
+                ip("::ffff:192.0.2.200"),
+                ip("198.51.100.1")
+            ]
+        );
+    }
+}
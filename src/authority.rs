@@ -0,0 +1,224 @@
+//! Parsing of `host:port` strings.
+
+use crate::Resolver;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+// RFC 1035 limits
+const MAX_NAME_LEN: usize = 253;
+const MAX_LABEL_LEN: usize = 63;
+
+/// The host part of an [`Authority`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Host {
+    /// An IP address, with the scope of an IPv6 zone such as `%eth0` or 0.
+    Addr(IpAddr, u32),
+    /// A host name, with the trailing dot of absolute names.
+    Name(String),
+}
+
+/// A host with an optional port, as in `example.com:443`, `[fe80::1%eth0]:80`
+/// or `db.internal`.
+///
+/// IPv6 addresses are written in brackets when a port follows. A bare IPv6
+/// address, without a port, is accepted too. The port may be a number or a
+/// service name such as `https`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Authority {
+    pub host: Host,
+    pub port: Option<String>,
+}
+
+impl Authority {
+    pub fn parse(s: &str) -> io::Result<Authority> {
+        if s.is_empty() {
+            return Err(invalid("empty socket address".to_string()));
+        }
+
+        if let Some(rest) = s.strip_prefix('[') {
+            let (addr, rest) = rest
+                .split_once(']')
+                .ok_or_else(|| invalid(format!("missing ']' in {:?}", s)))?;
+            let (ip, scope_id) = parse_ip(addr, s)?;
+            if ip.is_ipv4() {
+                return Err(invalid(format!(
+                    "IPv4 address {:?} in brackets in {:?}",
+                    addr, s
+                )));
+            }
+            let port = match rest {
+                "" => None,
+                _ => match rest.strip_prefix(':') {
+                    Some(port) => Some(parse_port(port, s)?),
+                    None => return Err(invalid(format!("expected ':' after ']' in {:?}", s))),
+                },
+            };
+            return Ok(Authority {
+                host: Host::Addr(ip, scope_id),
+                port,
+            });
+        }
+
+        if s.matches(':').count() > 1 {
+            // only a bare IPv6 address may have more than one colon
+            return match parse_ip(s, s) {
+                Ok((ip, scope_id)) => Ok(Authority {
+                    host: Host::Addr(ip, scope_id),
+                    port: None,
+                }),
+                Err(_) => Err(invalid(format!(
+                    "IPv6 address with a port must be in brackets in {:?}",
+                    s
+                ))),
+            };
+        }
+
+        let (host, port) = match s.split_once(':') {
+            Some((host, port)) => (host, Some(parse_port(port, s)?)),
+            None => (s, None),
+        };
+        if host.is_empty() {
+            return Err(invalid(format!("empty host in {:?}", s)));
+        }
+        if host.contains('%') {
+            return Err(invalid(format!(
+                "zone on a host that is not an IPv6 address in {:?}",
+                s
+            )));
+        }
+        let host = match host.parse::<IpAddr>() {
+            Ok(ip) => Host::Addr(ip, 0),
+            Err(_) => {
+                check_name(host, s)?;
+                Host::Name(host.to_string())
+            }
+        };
+        Ok(Authority { host, port })
+    }
+
+    /// Resolve the authority with [`Resolver::env`], using
+    /// `default_port` when it has no port.
+    pub fn to_socket_addrs(&self, default_port: u16) -> io::Result<Vec<SocketAddr>> {
+        self.resolve(default_port, None)
+    }
+
+    /// Like [`to_socket_addrs`](Self::to_socket_addrs), but look the host up
+    /// with `resolver`.
+    pub fn to_socket_addrs_with(
+        &self,
+        default_port: u16,
+        resolver: &Resolver,
+    ) -> io::Result<Vec<SocketAddr>> {
+        self.resolve(default_port, Some(resolver))
+    }
+
+    pub(crate) fn resolve(
+        &self,
+        default_port: u16,
+        resolver: Option<&Resolver>,
+    ) -> io::Result<Vec<SocketAddr>> {
+        let default_port = default_port.to_string();
+        let service = self.port.as_deref().unwrap_or(&default_port);
+        match &self.host {
+            Host::Addr(ip, scope_id) => {
+                let mut addrs: Vec<SocketAddr> =
+                    crate::lookup_host(&ip.to_string(), service, resolver)?.collect();
+                for addr in addrs.iter_mut() {
+                    if let SocketAddr::V6(addr) = addr {
+                        addr.set_scope_id(*scope_id);
+                    }
+                }
+                Ok(addrs)
+            }
+            Host::Name(name) => Ok(crate::lookup_host(name, service, resolver)?.collect()),
+        }
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Parse an IP address with an optional IPv6 zone, which is an interface
+/// index or name.
+fn parse_ip(addr: &str, s: &str) -> io::Result<(IpAddr, u32)> {
+    let (addr, zone) = match addr.split_once('%') {
+        Some((addr, zone)) => (addr, Some(zone)),
+        None => (addr, None),
+    };
+    let ip = addr
+        .parse::<IpAddr>()
+        .map_err(|_| invalid(format!("invalid IP address {:?} in {:?}", addr, s)))?;
+    let scope_id = match zone {
+        None => 0,
+        Some(_) if ip.is_ipv4() => {
+            return Err(invalid(format!(
+                "zone on IPv4 address {:?} in {:?}",
+                addr, s
+            )))
+        }
+        Some(zone) => match zone.parse::<u32>() {
+            Ok(index) => index,
+            Err(_) => interface_index(zone)
+                .ok_or_else(|| invalid(format!("unknown zone {:?} in {:?}", zone, s)))?,
+        },
+    };
+    Ok((ip, scope_id))
+}
+
+#[cfg(not(target_os = "wasi"))]
+fn interface_index(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => None,
+        index => Some(index),
+    }
+}
+
+// WASI has no interface names; zones must be given as numbers
+#[cfg(target_os = "wasi")]
+fn interface_index(_name: &str) -> Option<u32> {
+    None
+}
+
+fn parse_port(port: &str, s: &str) -> io::Result<String> {
+    if port.is_empty() {
+        return Err(invalid(format!("empty port in {:?}", s)));
+    }
+    if port.bytes().all(|b| b.is_ascii_digit()) {
+        if port.parse::<u16>().is_err() {
+            return Err(invalid(format!("port {} out of range in {:?}", port, s)));
+        }
+    } else if !port
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        return Err(invalid(format!("invalid port {:?} in {:?}", port, s)));
+    }
+    Ok(port.to_string())
+}
+
+/// Check that `name` is a host name: labels of letters, digits, `-` and `_`
+/// separated by dots, with an optional trailing dot.
+fn check_name(name: &str, s: &str) -> io::Result<()> {
+    let bad = |why: &str| invalid(format!("invalid host name {:?} in {:?}: {}", name, s, why));
+    let relative = name.strip_suffix('.').unwrap_or(name);
+    if relative.len() > MAX_NAME_LEN {
+        return Err(bad("too long"));
+    }
+    for label in relative.split('.') {
+        if label.is_empty() {
+            return Err(bad("empty label"));
+        }
+        if label.len() > MAX_LABEL_LEN {
+            return Err(bad("label too long"));
+        }
+        if let Some(c) = label
+            .chars()
+            .find(|&c| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        {
+            return Err(bad(&format!("unexpected character {:?}", c)));
+        }
+    }
+    Ok(())
+}
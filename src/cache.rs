@@ -0,0 +1,238 @@
+//! A cache of DNS answers, positive and negative, kept for as long as the
+//! records' TTL allows.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+// don't trust records for longer than a day
+const MAX_TTL: u32 = 86400;
+
+const GLOBAL_CAPACITY: usize = 512;
+
+/// A size-bounded cache of DNS responses keyed by name and query type.
+///
+/// Answers are kept for the smallest TTL of their records. "No such name" and
+/// "no records of this type" answers are kept for the negative TTL of the
+/// zone's SOA record, as described in RFC 2308; negative answers without
+/// one are not cached. When the cache is full, expired entries are dropped
+/// first and then the entry closest to expiring.
+#[derive(Debug)]
+pub struct DnsCache {
+    capacity: usize,
+    entries: Mutex<HashMap<(String, u16), Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    response: Vec<u8>,
+    id: u16,
+    expires: Instant,
+}
+
+impl DnsCache {
+    pub fn new(capacity: usize) -> Self {
+        DnsCache {
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The cache shared by every [`Resolver`](crate::Resolver) that has not
+    /// been given its own.
+    pub fn global() -> Arc<DnsCache> {
+        static GLOBAL: OnceLock<Arc<DnsCache>> = OnceLock::new();
+        GLOBAL
+            .get_or_init(|| Arc::new(DnsCache::new(GLOBAL_CAPACITY)))
+            .clone()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of entries, including ones that have expired but have not
+    /// been dropped yet.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop every entry.
+    pub fn flush(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Drop the entries of `name`, for every query type.
+    pub fn remove(&self, name: &str) {
+        let name = key_name(name);
+        self.entries.lock().unwrap().retain(|(n, _), _| *n != name);
+    }
+
+    /// The cached response to the query for `name` and `qtype`, with the id it
+    /// was sent with.
+    pub(crate) fn get(&self, name: &str, qtype: u16) -> Option<(Vec<u8>, u16)> {
+        let mut entries = self.entries.lock().unwrap();
+        let key = (key_name(name), qtype);
+        match entries.get(&key) {
+            Some(entry) if entry.expires > Instant::now() => {
+                Some((entry.response.clone(), entry.id))
+            }
+            Some(_) => {
+                entries.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Remember `response` if its records say for how long it may be kept.
+    pub(crate) fn insert(&self, name: &str, qtype: u16, response: &[u8], id: u16) {
+        if self.capacity == 0 {
+            return;
+        }
+        let ttl = match response_ttl(response) {
+            Some(ttl) if ttl > 0 => ttl.min(MAX_TTL),
+            _ => return,
+        };
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        let key = (key_name(name), qtype);
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.retain(|_, e| e.expires > now);
+            if entries.len() >= self.capacity {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, e)| e.expires)
+                    .map(|(k, _)| k.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        entries.insert(
+            key,
+            Entry {
+                response: response.to_vec(),
+                id,
+                expires: now + Duration::from_secs(ttl as u64),
+            },
+        );
+    }
+}
+
+fn key_name(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// How long `response` may be cached: the smallest TTL of the answers, or the
+/// negative TTL from the SOA record when there are none.
+fn response_ttl(response: &[u8]) -> Option<u32> {
+    use dns_parser::{Packet, RData, ResponseCode};
+    let (response, _) = crate::dns::masked(response).ok()?;
+    let pkt = Packet::parse(&response).ok()?;
+    match pkt.header.response_code {
+        ResponseCode::NoError | ResponseCode::NameError => {}
+        _ => return None,
+    }
+    if pkt.header.response_code == ResponseCode::NoError && !pkt.answers.is_empty() {
+        return pkt.answers.iter().map(|rr| rr.ttl).min();
+    }
+    pkt.nameservers.iter().find_map(|rr| match rr.data {
+        RData::SOA(ref soa) => Some(rr.ttl.min(soa.minimum_ttl)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TYPE_A: u16 = 1;
+
+    // a response for example.com with an A record of `ttl`, or with an SOA
+    // record of `negative` in the authority section
+    fn response(rcode: u8, ttl: Option<u32>, negative: Option<u32>) -> Vec<u8> {
+        let (an, ns) = (ttl.is_some() as u8, negative.is_some() as u8);
+        let mut out = vec![0, 1, 0x81, 0x80 | rcode, 0, 1, 0, an, 0, ns, 0, 0];
+        out.extend_from_slice(b"\x07example\x03com\x00\x00\x01\x00\x01");
+        if let Some(ttl) = ttl {
+            out.extend_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1]);
+            out.extend_from_slice(&ttl.to_be_bytes());
+            out.extend_from_slice(&[0, 4, 10, 0, 0, 1]);
+        }
+        if let Some(negative) = negative {
+            let mut soa = b"\x02ns\xc0\x0c\x04host\xc0\x0c".to_vec();
+            for field in [1u32, 3600, 600, 86400, negative] {
+                soa.extend_from_slice(&field.to_be_bytes());
+            }
+            out.extend_from_slice(&[0xc0, 0x0c, 0, 6, 0, 1]);
+            out.extend_from_slice(&3600u32.to_be_bytes());
+            out.extend_from_slice(&(soa.len() as u16).to_be_bytes());
+            out.extend_from_slice(&soa);
+        }
+        out
+    }
+
+    #[test]
+    fn keeps_answers_with_a_ttl() {
+        let cache = DnsCache::new(4);
+        let answer = response(0, Some(60), None);
+        cache.insert("example.com", TYPE_A, &answer, 7);
+        assert_eq!(cache.get("EXAMPLE.com.", TYPE_A), Some((answer, 7)));
+        assert_eq!(cache.get("example.com", 28), None);
+
+        cache.insert("zero.test", TYPE_A, &response(0, Some(0), None), 7);
+        assert_eq!(cache.get("zero.test", TYPE_A), None);
+        cache.remove("Example.com");
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn keeps_negative_answers_with_an_soa() {
+        let cache = DnsCache::new(4);
+        cache.insert("nx.test", TYPE_A, &response(3, None, Some(30)), 1);
+        assert!(cache.get("nx.test", TYPE_A).is_some());
+        assert_eq!(response_ttl(&response(3, None, Some(30))), Some(30));
+        cache.insert("nosoa.test", TYPE_A, &response(3, None, None), 1);
+        assert!(cache.get("nosoa.test", TYPE_A).is_none());
+        // failures are not cached, whatever their records say
+        cache.insert("fail.test", TYPE_A, &response(2, Some(60), None), 1);
+        assert!(cache.get("fail.test", TYPE_A).is_none());
+    }
+
+    #[test]
+    fn drops_the_entry_closest_to_expiring() {
+        let cache = DnsCache::new(2);
+        cache.insert("a.test", TYPE_A, &response(0, Some(60), None), 1);
+        cache.insert("b.test", TYPE_A, &response(0, Some(30), None), 1);
+        cache.insert("c.test", TYPE_A, &response(0, Some(90), None), 1);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("b.test", TYPE_A).is_none());
+        assert!(cache.get("a.test", TYPE_A).is_some());
+
+        let off = DnsCache::new(0);
+        off.insert("a.test", TYPE_A, &response(0, Some(60), None), 1);
+        assert!(off.is_empty());
+    }
+
+    #[test]
+    fn ttl_is_capped() {
+        assert_eq!(
+            response_ttl(&response(0, Some(i32::MAX as u32), None)),
+            Some(i32::MAX as u32)
+        );
+        let cache = DnsCache::new(1);
+        cache.insert(
+            "a.test",
+            TYPE_A,
+            &response(0, Some(i32::MAX as u32), None),
+            1,
+        );
+        let expires = cache.entries.lock().unwrap()[&("a.test".to_string(), TYPE_A)].expires;
+        assert!(expires <= Instant::now() + Duration::from_secs(MAX_TTL as u64));
+    }
+}
@@ -0,0 +1,685 @@
+//! Name resolution by talking to a DNS server directly.
+//!
+//! Queries are sent over UDP first. When the answer comes back with the TC
+//! bit set, the query is repeated over TCP as described in RFC 1035. Most
+//! programs should go through a [`Resolver`] rather than call these
+//! functions.
+
+use crate::poll::{poll, EventType, Subscription};
+use crate::resolver::{IpPreference, Resolver};
+use crate::{TcpStream, ToSocketAddrs, UdpSocket, WasiErrno};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::fd::{AsRawFd, RawFd};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Timeouts and retransmission of queries sent to a DNS server.
+#[derive(Clone, Debug)]
+pub struct DnsOptions {
+    /// How long to wait for the answers to UDP queries before sending them
+    /// again.
+    pub timeout: Duration,
+    /// How many times a query is sent to each server before giving up.
+    pub attempts: u32,
+    /// Read and write timeout of TCP, TLS and HTTPS connections to the
+    /// server.
+    pub tcp_timeout: Duration,
+}
+
+impl Default for DnsOptions {
+    fn default() -> Self {
+        DnsOptions {
+            timeout: Duration::from_secs(5),
+            attempts: 2,
+            tcp_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// How queries reach the DNS server.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Transport {
+    /// UDP, repeating the query over TCP when the answer is truncated.
+    Udp,
+    /// TCP only.
+    Tcp,
+    /// DNS over TLS, usually to port 853.
+    #[cfg(feature = "tls")]
+    Tls,
+    /// DNS over HTTPS, usually to port 443.
+    #[cfg(feature = "tls")]
+    Https(crate::tls::DohMethod),
+}
+
+// a DNS header is 12 bytes; the TC flag is in the third one
+const HEADER_LEN: usize = 12;
+const FLAG_TRUNCATED: u8 = 0x02;
+
+pub(crate) const UDP_BUFFER_LEN: usize = 4096;
+
+pub fn nslookup_with_dns_server(
+    dns_server: &str,
+    node: &str,
+    service: &str,
+) -> io::Result<Vec<SocketAddr>> {
+    nslookup_with_options(dns_server, node, service, &DnsOptions::default())
+}
+
+/// Ask `dns_server` for the A and AAAA records of `node` at once, and return
+/// the addresses in the order of RFC 6724 with the port of `service`.
+pub fn nslookup_with_options(
+    dns_server: &str,
+    node: &str,
+    service: &str,
+    options: &DnsOptions,
+) -> io::Result<Vec<SocketAddr>> {
+    let server = dns_server
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid DNS server address"))?;
+    Resolver::new(vec![server])
+        .with_options(options.clone())
+        .with_ip_preference(IpPreference::Any)
+        .lookup(node, service)
+}
+
+/// Ask `server` for the records of type `T` of `name`, over UDP and then over
+/// TCP if the answer does not fit in a datagram.
+pub fn query<T: ToQType>(
+    server: SocketAddr,
+    name: &str,
+    options: &DnsOptions,
+) -> io::Result<Vec<T>> {
+    Resolver::new(vec![server])
+        .with_options(options.clone())
+        .query(name)
+}
+
+/// The name the PTR records of `ip` are kept under: the reversed octets under
+/// `in-addr.arpa` for IPv4, and the reversed nibbles under `ip6.arpa` for
+/// IPv6. IPv4-mapped IPv6 addresses use the IPv4 name.
+pub fn reverse_name(ip: IpAddr) -> String {
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        v4 => v4,
+    };
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, d] = v4.octets();
+            format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a)
+        }
+        IpAddr::V6(v6) => {
+            let mut name = String::with_capacity(72);
+            for byte in v6.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", byte & 0x0f, byte >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        }
+    }
+}
+
+/// A response as received, with the id of the query it answers.
+pub(crate) type Response = (Vec<u8>, u16);
+
+/// Send a query for each of `qtypes` to `server` at once and wait for the
+/// answers, in the order of `qtypes`.
+///
+/// Over UDP, all queries share one nonblocking socket and the answers are
+/// collected with [`poll`](crate::poll::poll) until they are in or the timeout
+/// passes. Truncated answers are fetched again over TCP.
+pub(crate) fn exchange_many(
+    server: SocketAddr,
+    name: &str,
+    qtypes: &[u16],
+    transport: Transport,
+    options: &DnsOptions,
+) -> io::Result<Vec<io::Result<Response>>> {
+    let mut ids: Vec<u16> = vec![];
+    let mut packets = vec![];
+    for &qtype in qtypes {
+        let mut id = rand::random();
+        while ids.contains(&id) {
+            id = rand::random();
+        }
+        ids.push(id);
+        packets.push(build_query(id, name, qtype)?);
+    }
+
+    if transport == Transport::Tcp {
+        return Ok(ids
+            .iter()
+            .zip(packets.iter())
+            .map(|(&id, packet)| exchange_tcp(server, packet, options.tcp_timeout).map(|r| (r, id)))
+            .collect());
+    }
+
+    let socket = udp_socket_for(server)?;
+    for packet in &packets {
+        socket.send_to(packet, server)?;
+    }
+
+    let mut results: Vec<Option<io::Result<Response>>> = qtypes.iter().map(|_| None).collect();
+    let mut buf = vec![0u8; UDP_BUFFER_LEN];
+    let deadline = SystemTime::now() + options.timeout;
+    while results.iter().any(Option::is_none) {
+        let subs = [Subscription::io(0, &socket, true, false, Some(deadline))];
+        let mut readable = false;
+        for event in poll(&subs)? {
+            match event.event_type {
+                EventType::Read => readable = true,
+                EventType::Error(e) => return Err(e),
+                _ => {}
+            }
+        }
+        if !readable {
+            break;
+        }
+        loop {
+            let (n, from) = match socket.recv_from(&mut buf) {
+                Ok(r) => r,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            };
+            // ignore datagrams that are not the answer to one of the queries
+            let id = match response_id(&buf[..n], from, server) {
+                Some(id) => id,
+                None => continue,
+            };
+            let i = match ids.iter().position(|&i| i == id) {
+                Some(i) if results[i].is_none() => i,
+                _ => continue,
+            };
+            results[i] = Some(if is_truncated(&buf[..n]) {
+                exchange_tcp(server, &packets[i], options.tcp_timeout).map(|r| (r, id))
+            } else {
+                Ok((buf[..n].to_vec(), id))
+            });
+        }
+    }
+    Ok(results
+        .into_iter()
+        .map(|r| r.unwrap_or_else(|| Err(WasiErrno::Timedout.into())))
+        .collect())
+}
+
+/// A nonblocking UDP socket to send queries to `server` from.
+pub(crate) fn udp_socket_for(server: SocketAddr) -> io::Result<UdpSocket> {
+    let local: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.as_ref().set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// The id of a datagram `from` a peer, if it can be a response of `server`.
+pub(crate) fn response_id(datagram: &[u8], from: SocketAddr, server: SocketAddr) -> Option<u16> {
+    if from.ip() != server.ip() || from.port() != server.port() || datagram.len() < HEADER_LEN {
+        return None;
+    }
+    Some(u16::from_be_bytes([datagram[0], datagram[1]]))
+}
+
+/// Whether the TC bit of `response` is set: the answer did not fit and has
+/// to be asked for over TCP.
+pub(crate) fn is_truncated(response: &[u8]) -> bool {
+    response.len() > 2 && response[2] & FLAG_TRUNCATED != 0
+}
+
+/// Whether `response` settles the question, as opposed to a server failure
+/// or refusal that another server may not have.
+pub(crate) fn is_final(response: &[u8]) -> bool {
+    use dns_parser::ResponseCode;
+    response.len() >= HEADER_LEN
+        && matches!(
+            ResponseCode::from(response[3] & 0x0f),
+            ResponseCode::NoError | ResponseCode::NameError
+        )
+}
+
+pub(crate) fn exchange_tcp(
+    server: SocketAddr,
+    packet: &[u8],
+    timeout: Duration,
+) -> io::Result<Vec<u8>> {
+    let mut conn = TcpStream::connect(server)?;
+    conn.as_ref().set_send_timeout(Some(timeout))?;
+    conn.as_ref().set_recv_timeout(Some(timeout))?;
+    let deadline = Deadline::new(Instant::now() + timeout, &conn);
+    exchange_stream(&mut conn, packet, Some(deadline))
+}
+
+/// When to give up on a DNS-over-TCP exchange, and the socket to wait on
+/// while the stream has no data.
+#[derive(Clone, Copy)]
+pub(crate) struct Deadline {
+    at: Instant,
+    fd: RawFd,
+}
+
+impl Deadline {
+    pub(crate) fn new<F: AsRawFd>(at: Instant, socket: &F) -> Self {
+        Deadline {
+            at,
+            fd: socket.as_raw_fd(),
+        }
+    }
+
+    /// Wait until the socket is readable or the deadline passes.
+    fn wait(&self) -> io::Result<()> {
+        let timeout = SystemTime::now() + self.at.saturating_duration_since(Instant::now());
+        poll(&[Subscription::io(0, &self.fd, true, false, Some(timeout))])?;
+        Ok(())
+    }
+}
+
+/// Send `packet` with the 2-byte length prefix used by DNS over TCP and read
+/// the answer.
+///
+/// Reads are repeated until the whole length prefix and message have arrived.
+/// A message too short to hold a header is rejected as `InvalidData`.
+/// Once `deadline` has passed, the exchange fails with `TimedOut`; until
+/// then, a read that would block waits for the socket to become readable.
+/// Without a deadline, a timeout reported by the stream is returned as is.
+pub(crate) fn exchange_stream<S: Write + Read>(
+    conn: &mut S,
+    packet: &[u8],
+    deadline: Option<Deadline>,
+) -> io::Result<Vec<u8>> {
+    conn.write_all(&framed(packet))?;
+
+    let mut psize = [0u8; 2];
+    read_full(conn, &mut psize, deadline)?;
+
+    let len = u16::from_be_bytes(psize) as usize;
+    if len < HEADER_LEN {
+        return Err(too_short(len));
+    }
+    let mut buf = vec![0u8; len];
+    read_full(conn, &mut buf, deadline)?;
+    Ok(buf)
+}
+
+/// `packet` with the 2-byte length prefix used by DNS over TCP.
+pub(crate) fn framed(packet: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(packet.len() + 2);
+    out.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+    out.extend_from_slice(packet);
+    out
+}
+
+/// Take the first length-prefixed message off the front of `buf`, once all
+/// of it has been read.
+pub(crate) fn take_framed(buf: &mut Vec<u8>) -> io::Result<Option<Vec<u8>>> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    let len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+    if len < HEADER_LEN {
+        return Err(too_short(len));
+    }
+    if buf.len() < 2 + len {
+        return Ok(None);
+    }
+    Ok(Some(buf.drain(..2 + len).skip(2).collect()))
+}
+
+fn too_short(len: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("DNS message of {} bytes is shorter than a header", len),
+    )
+}
+
+fn read_full<S: Read>(conn: &mut S, buf: &mut [u8], deadline: Option<Deadline>) -> io::Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        if deadline.is_some_and(|d| Instant::now() >= d.at) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "DNS response timed out",
+            ));
+        }
+        match conn.read(&mut buf[filled..]) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Partial packet received",
+                ))
+            }
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                match deadline {
+                    Some(deadline) => deadline.wait()?,
+                    None => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn build_query(id: u16, name: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    let mut packet = Vec::with_capacity(HEADER_LEN + name.len() + 6);
+    packet.extend_from_slice(&id.to_be_bytes());
+    // recursion desired, one question
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    encode_name(&mut packet, name)?;
+    packet.extend_from_slice(&qtype.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(packet)
+}
+
+pub(crate) const CLASS_IN: u16 = 1;
+
+/// Append `name` in wire format. Each label holds 1 to 63 octets and the
+/// whole name at most 255; one trailing dot is allowed, and `.` alone is the
+/// root.
+pub(crate) fn encode_name(out: &mut Vec<u8>, name: &str) -> io::Result<()> {
+    let invalid = |why: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid DNS name {:?}: {}", name, why),
+        )
+    };
+    let trimmed = name.strip_suffix('.').unwrap_or(name);
+    let mut wire = vec![];
+    if !trimmed.is_empty() {
+        for label in trimmed.split('.') {
+            if label.is_empty() {
+                return Err(invalid("empty label"));
+            }
+            if label.len() > 63 {
+                return Err(invalid("label longer than 63 octets"));
+            }
+            wire.push(label.len() as u8);
+            wire.extend_from_slice(label.as_bytes());
+        }
+    } else if name.is_empty() {
+        return Err(invalid("empty name"));
+    }
+    wire.push(0);
+    if wire.len() > 255 {
+        return Err(invalid("longer than 255 octets"));
+    }
+    out.extend_from_slice(&wire);
+    Ok(())
+}
+
+// dns_parser leaves the data of NULL records as is
+const TYPE_NULL: u16 = 10;
+
+/// A copy of `buf` that dns_parser can parse, with the type of the first
+/// question, if there is one.
+///
+/// dns_parser rejects a whole message when one of its records has a type it
+/// does not know, so those records are turned into NULL records.
+pub(crate) fn masked(buf: &[u8]) -> io::Result<(Vec<u8>, Option<u16>)> {
+    use dns_parser::{QueryType, Type};
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed DNS message");
+    let mut buf = buf.to_vec();
+    if buf.len() < HEADER_LEN {
+        return Err(invalid());
+    }
+    let count = |i: usize| u16::from_be_bytes([buf[i], buf[i + 1]]) as usize;
+    let questions = count(4);
+    let records = count(6) + count(8) + count(10);
+
+    let mut pos = HEADER_LEN;
+    let mut first = None;
+    for _ in 0..questions {
+        pos = skip_name(&buf, pos).ok_or_else(invalid)?;
+        let field = buf.get(pos..pos + 4).ok_or_else(invalid)?;
+        let qtype = u16::from_be_bytes([field[0], field[1]]);
+        first = first.or(Some(qtype));
+        if QueryType::parse(qtype).is_err() {
+            buf[pos..pos + 2].copy_from_slice(&TYPE_NULL.to_be_bytes());
+        }
+        pos += 4;
+    }
+
+    for _ in 0..records {
+        pos = skip_name(&buf, pos).ok_or_else(invalid)?;
+        let fields = buf.get(pos..pos + 10).ok_or_else(invalid)?;
+        let rtype = u16::from_be_bytes([fields[0], fields[1]]);
+        let rdlen = u16::from_be_bytes([fields[8], fields[9]]) as usize;
+        if Type::parse(rtype).is_err() {
+            buf[pos..pos + 2].copy_from_slice(&TYPE_NULL.to_be_bytes());
+        }
+        pos += 10 + rdlen;
+        if pos > buf.len() {
+            return Err(invalid());
+        }
+    }
+    Ok((buf, first))
+}
+
+/// The position after the name that starts at `pos`.
+fn skip_name(buf: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *buf.get(pos)?;
+        match len {
+            0 => return Some(pos + 1),
+            // a pointer to a name elsewhere in the message
+            l if l & 0xc0 == 0xc0 => return Some(pos + 2),
+            l => pos += 1 + l as usize,
+        }
+    }
+}
+
+/// The records of a response that answer the question.
+pub(crate) struct Answers<T> {
+    pub records: Vec<T>,
+    /// The end of the CNAME chain, when the response has no records for it.
+    pub alias: Option<String>,
+    /// The number of CNAME records followed.
+    pub hops: usize,
+}
+
+impl<T> Answers<T> {
+    /// Check that following `alias` stays within [`MAX_CNAME_CHAIN`] hops,
+    /// counting `hops` already followed before this response.
+    pub fn check_chain(&self, hops: usize) -> io::Result<()> {
+        if hops + self.hops > MAX_CNAME_CHAIN {
+            return Err(cname_chain_error());
+        }
+        Ok(())
+    }
+}
+
+/// How many CNAME records are followed from the name queried before giving
+/// up on the chain.
+pub const MAX_CNAME_CHAIN: usize = 8;
+
+fn cname_chain_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "CNAME chain is too long or loops",
+    )
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.')
+        .eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+/// Check that `buf` is a well-formed response to the query `id` for the
+/// `qtype` records of `name`: it must repeat the question.
+pub(crate) fn check_response(buf: &[u8], id: u16, name: &str, qtype: u16) -> io::Result<()> {
+    use dns_parser::{Packet, QueryClass};
+    let (buf, first) = masked(buf)?;
+    let pkt = Packet::parse(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if pkt.header.id != id {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Illegal id"));
+    }
+    match pkt.questions.as_slice() {
+        [q] if !pkt.header.query
+            && first == Some(qtype)
+            && q.qclass == QueryClass::IN
+            && same_name(&q.qname.to_string(), name) =>
+        {
+            Ok(())
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "response does not match the question",
+        )),
+    }
+}
+
+/// Parse the response to the query for the `T` records of `name`.
+///
+/// The response must repeat the question. Only records owned by `name`, or by
+/// the names its CNAME records lead to, are returned.
+pub(crate) fn parse_answers<T: ToQType>(buf: &[u8], id: u16, name: &str) -> io::Result<Answers<T>> {
+    use dns_parser::{Packet, RData, ResponseCode};
+    check_response(buf, id, name, T::type_code())?;
+    let (buf, _) = masked(buf)?;
+    let pkt = Packet::parse(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let errno = match pkt.header.response_code {
+        ResponseCode::NoError => None,
+        ResponseCode::NameError => Some(WasiErrno::AiNoname),
+        ResponseCode::ServerFailure => Some(WasiErrno::AiAgain),
+        _ => Some(WasiErrno::AiFail),
+    };
+    if let Some(errno) = errno {
+        return Err(errno.into());
+    }
+
+    // follow the CNAME records from the name queried
+    let mut chain = vec![name.trim_end_matches('.').to_string()];
+    loop {
+        let owner = chain.last().unwrap();
+        let target = pkt.answers.iter().find_map(|ans| match ans.data {
+            RData::CNAME(ref cname) if same_name(&ans.name.to_string(), owner) => {
+                Some(cname.0.to_string())
+            }
+            _ => None,
+        });
+        match target {
+            Some(target) => {
+                if chain.iter().any(|n| same_name(n, &target)) || chain.len() > MAX_CNAME_CHAIN {
+                    return Err(cname_chain_error());
+                }
+                chain.push(target);
+            }
+            None => break,
+        }
+    }
+
+    let mut records = vec![];
+    for ans in pkt.answers {
+        let owner = ans.name.to_string();
+        if !chain.iter().any(|n| same_name(n, &owner)) {
+            continue;
+        }
+        if let Some(record) = T::from_rr(ans.data) {
+            records.push(record);
+        }
+    }
+    let hops = chain.len() - 1;
+    let alias = if records.is_empty() && hops > 0 {
+        chain.pop()
+    } else {
+        None
+    };
+    Ok(Answers {
+        records,
+        alias,
+        hops,
+    })
+}
+
+pub trait ToQType: Sized {
+    fn q_type() -> dns_parser::QueryType;
+
+    /// The type code sent in the query. Types that dns_parser has no
+    /// `QueryType` for override it, and their records reach `from_rr` as
+    /// `RData::Unknown`.
+    fn type_code() -> u16 {
+        Self::q_type() as u16
+    }
+
+    fn from_rr(rr: dns_parser::RData) -> Option<Self>;
+}
+
+impl ToQType for Ipv4Addr {
+    fn q_type() -> dns_parser::QueryType {
+        dns_parser::QueryType::A
+    }
+
+    fn from_rr(rr: dns_parser::RData) -> Option<Self> {
+        if let dns_parser::RData::A(ip) = rr {
+            Some(ip.0)
+        } else {
+            None
+        }
+    }
+}
+
+impl ToQType for Ipv6Addr {
+    fn q_type() -> dns_parser::QueryType {
+        dns_parser::QueryType::AAAA
+    }
+
+    fn from_rr(rr: dns_parser::RData) -> Option<Self> {
+        if let dns_parser::RData::AAAA(ip) = rr {
+            Some(ip.0)
+        } else {
+            None
+        }
+    }
+}
+
+/// Resolve `name` over an already connected DNS-over-TCP stream.
+pub fn resolve<S: Write + Read, T: ToQType>(
+    conn: &mut S,
+    name: &str,
+) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    resolve_stream(conn, name, None)
+}
+
+/// Like [`resolve`], but give up with `TimedOut` once `deadline` has passed.
+///
+/// The stream should be nonblocking, or have a read timeout, so that a read
+/// does not wait past the deadline. While it has no data, the socket of the
+/// stream is polled until it is readable or the deadline passes.
+pub fn resolve_with_deadline<S: Write + Read + AsRawFd, T: ToQType>(
+    conn: &mut S,
+    name: &str,
+    deadline: Instant,
+) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    let deadline = Deadline::new(deadline, conn);
+    resolve_stream(conn, name, Some(deadline))
+}
+
+fn resolve_stream<S: Write + Read, T: ToQType>(
+    conn: &mut S,
+    name: &str,
+    deadline: Option<Deadline>,
+) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    let mut name = name.to_string();
+    let mut hops = 0;
+    loop {
+        let id = rand::random();
+        let packet = build_query(id, &name, T::type_code())?;
+        let buf = exchange_stream(conn, &packet, deadline)?;
+        let answers = parse_answers::<T>(&buf, id, &name)?;
+        answers.check_chain(hops)?;
+        match answers.alias {
+            Some(alias) => {
+                hops += answers.hops;
+                name = alias;
+            }
+            None => return Ok(answers.records),
+        }
+    }
+}
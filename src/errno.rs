@@ -0,0 +1,333 @@
+use std::io;
+
+/// Error codes returned by the WASI socket and poll functions.
+///
+/// These are the `wasi_snapshot_preview1` errno values, followed by the
+/// `getaddrinfo` codes that WasmEdge adds to the table. They differ from the
+/// errno values of the host, so an [`io::Error`] created by this crate carries
+/// the `WasiErrno` itself; use [`WasiErrno::from_io_error`] to get it back.
+/// Its [`raw_os_error`](io::Error::raw_os_error) is `None`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[repr(u16)]
+pub enum WasiErrno {
+    Success = 0,
+    Toobig = 1,
+    Acces = 2,
+    Addrinuse = 3,
+    Addrnotavail = 4,
+    Afnosupport = 5,
+    Again = 6,
+    Already = 7,
+    Badf = 8,
+    Badmsg = 9,
+    Busy = 10,
+    Canceled = 11,
+    Child = 12,
+    Connaborted = 13,
+    Connrefused = 14,
+    Connreset = 15,
+    Deadlk = 16,
+    Destaddrreq = 17,
+    Dom = 18,
+    Dquot = 19,
+    Exist = 20,
+    Fault = 21,
+    Fbig = 22,
+    Hostunreach = 23,
+    Idrm = 24,
+    Ilseq = 25,
+    Inprogress = 26,
+    Intr = 27,
+    Inval = 28,
+    Io = 29,
+    Isconn = 30,
+    Isdir = 31,
+    Loop = 32,
+    Mfile = 33,
+    Mlink = 34,
+    Msgsize = 35,
+    Multihop = 36,
+    Nametoolong = 37,
+    Netdown = 38,
+    Netreset = 39,
+    Netunreach = 40,
+    Nfile = 41,
+    Nobufs = 42,
+    Nodev = 43,
+    Noent = 44,
+    Noexec = 45,
+    Nolck = 46,
+    Nolink = 47,
+    Nomem = 48,
+    Nomsg = 49,
+    Noprotoopt = 50,
+    Nospc = 51,
+    Nosys = 52,
+    Notconn = 53,
+    Notdir = 54,
+    Notempty = 55,
+    Notrecoverable = 56,
+    Notsock = 57,
+    Notsup = 58,
+    Notty = 59,
+    Nxio = 60,
+    Overflow = 61,
+    Ownerdead = 62,
+    Perm = 63,
+    Pipe = 64,
+    Proto = 65,
+    Protonosupport = 66,
+    Prototype = 67,
+    Range = 68,
+    Rofs = 69,
+    Spipe = 70,
+    Srch = 71,
+    Stale = 72,
+    Timedout = 73,
+    Txtbsy = 74,
+    Xdev = 75,
+    Notcapable = 76,
+    AiAddrfamily = 77,
+    AiAgain = 78,
+    AiBadflag = 79,
+    AiFail = 80,
+    AiFamily = 81,
+    AiMemory = 82,
+    AiNodata = 83,
+    AiNoname = 84,
+    AiService = 85,
+    AiSocktype = 86,
+    AiSystem = 87,
+}
+
+const ERRNO_TABLE: [(WasiErrno, &str); 88] = [
+    (WasiErrno::Success, "No error occurred"),
+    (WasiErrno::Toobig, "Argument list too long"),
+    (WasiErrno::Acces, "Permission denied"),
+    (WasiErrno::Addrinuse, "Address in use"),
+    (WasiErrno::Addrnotavail, "Address not available"),
+    (WasiErrno::Afnosupport, "Address family not supported"),
+    (
+        WasiErrno::Again,
+        "Resource unavailable, or operation would block",
+    ),
+    (WasiErrno::Already, "Connection already in progress"),
+    (WasiErrno::Badf, "Bad file descriptor"),
+    (WasiErrno::Badmsg, "Bad message"),
+    (WasiErrno::Busy, "Device or resource busy"),
+    (WasiErrno::Canceled, "Operation canceled"),
+    (WasiErrno::Child, "No child processes"),
+    (WasiErrno::Connaborted, "Connection aborted"),
+    (WasiErrno::Connrefused, "Connection refused"),
+    (WasiErrno::Connreset, "Connection reset"),
+    (WasiErrno::Deadlk, "Resource deadlock would occur"),
+    (WasiErrno::Destaddrreq, "Destination address required"),
+    (
+        WasiErrno::Dom,
+        "Mathematics argument out of domain of function",
+    ),
+    (WasiErrno::Dquot, "Disk quota exceeded"),
+    (WasiErrno::Exist, "File exists"),
+    (WasiErrno::Fault, "Bad address"),
+    (WasiErrno::Fbig, "File too large"),
+    (WasiErrno::Hostunreach, "Host is unreachable"),
+    (WasiErrno::Idrm, "Identifier removed"),
+    (WasiErrno::Ilseq, "Illegal byte sequence"),
+    (WasiErrno::Inprogress, "Operation in progress"),
+    (WasiErrno::Intr, "Interrupted function"),
+    (WasiErrno::Inval, "Invalid argument"),
+    (WasiErrno::Io, "I/O error"),
+    (WasiErrno::Isconn, "Socket is connected"),
+    (WasiErrno::Isdir, "Is a directory"),
+    (WasiErrno::Loop, "Too many levels of symbolic links"),
+    (WasiErrno::Mfile, "File descriptor value too large"),
+    (WasiErrno::Mlink, "Too many links"),
+    (WasiErrno::Msgsize, "Message too large"),
+    (WasiErrno::Multihop, "Multihop attempted"),
+    (WasiErrno::Nametoolong, "Filename too long"),
+    (WasiErrno::Netdown, "Network is down"),
+    (WasiErrno::Netreset, "Connection aborted by network"),
+    (WasiErrno::Netunreach, "Network unreachable"),
+    (WasiErrno::Nfile, "Too many files open in system"),
+    (WasiErrno::Nobufs, "No buffer space available"),
+    (WasiErrno::Nodev, "No such device"),
+    (WasiErrno::Noent, "No such file or directory"),
+    (WasiErrno::Noexec, "Executable file format error"),
+    (WasiErrno::Nolck, "No locks available"),
+    (WasiErrno::Nolink, "Link has been severed"),
+    (WasiErrno::Nomem, "Not enough space"),
+    (WasiErrno::Nomsg, "No message of the desired type"),
+    (WasiErrno::Noprotoopt, "Protocol not available"),
+    (WasiErrno::Nospc, "No space left on device"),
+    (WasiErrno::Nosys, "Function not supported"),
+    (WasiErrno::Notconn, "The socket is not connected"),
+    (
+        WasiErrno::Notdir,
+        "Not a directory or a symbolic link to a directory",
+    ),
+    (WasiErrno::Notempty, "Directory not empty"),
+    (WasiErrno::Notrecoverable, "State not recoverable"),
+    (WasiErrno::Notsock, "Not a socket"),
+    (
+        WasiErrno::Notsup,
+        "Not supported, or operation not supported on socket",
+    ),
+    (WasiErrno::Notty, "Inappropriate I/O control operation"),
+    (WasiErrno::Nxio, "No such device or address"),
+    (
+        WasiErrno::Overflow,
+        "Value too large to be stored in data type",
+    ),
+    (WasiErrno::Ownerdead, "Previous owner died"),
+    (WasiErrno::Perm, "Operation not permitted"),
+    (WasiErrno::Pipe, "Broken pipe"),
+    (WasiErrno::Proto, "Protocol error"),
+    (WasiErrno::Protonosupport, "Protocol not supported"),
+    (WasiErrno::Prototype, "Protocol wrong type for socket"),
+    (WasiErrno::Range, "Result too large"),
+    (WasiErrno::Rofs, "Read-only file system"),
+    (WasiErrno::Spipe, "Invalid seek"),
+    (WasiErrno::Srch, "No such process"),
+    (WasiErrno::Stale, "Stale file handle"),
+    (WasiErrno::Timedout, "Connection timed out"),
+    (WasiErrno::Txtbsy, "Text file busy"),
+    (WasiErrno::Xdev, "Cross-device link"),
+    (
+        WasiErrno::Notcapable,
+        "Extension: Capabilities insufficient",
+    ),
+    (
+        WasiErrno::AiAddrfamily,
+        "Address family for hostname not supported",
+    ),
+    (WasiErrno::AiAgain, "Temporary failure in name resolution"),
+    (WasiErrno::AiBadflag, "Bad value for ai_flags"),
+    (
+        WasiErrno::AiFail,
+        "Non-recoverable failure in name resolution",
+    ),
+    (WasiErrno::AiFamily, "ai_family not supported"),
+    (WasiErrno::AiMemory, "Memory allocation failure"),
+    (WasiErrno::AiNodata, "No address associated with hostname"),
+    (WasiErrno::AiNoname, "Name or service not known"),
+    (
+        WasiErrno::AiService,
+        "Servname not supported for ai_socktype",
+    ),
+    (WasiErrno::AiSocktype, "ai_socktype not supported"),
+    (WasiErrno::AiSystem, "System error"),
+];
+
+impl WasiErrno {
+    /// Look up a raw errno value returned by the host.
+    pub fn from_raw(code: u32) -> Option<WasiErrno> {
+        ERRNO_TABLE.get(code as usize).map(|(errno, _)| *errno)
+    }
+
+    pub fn raw(self) -> u16 {
+        self as u16
+    }
+
+    /// Recover the errno carried by an error returned from this crate.
+    ///
+    /// On `wasm32-wasi` the raw OS error of other `std` errors is a WASI errno
+    /// as well, so those are recognized too.
+    pub fn from_io_error(e: &io::Error) -> Option<WasiErrno> {
+        if let Some(errno) = e.get_ref().and_then(|e| e.downcast_ref::<WasiErrno>()) {
+            return Some(*errno);
+        }
+        if cfg!(target_os = "wasi") {
+            e.raw_os_error()
+                .and_then(|code| WasiErrno::from_raw(code as u32))
+        } else {
+            None
+        }
+    }
+
+    pub fn is_getaddrinfo_error(self) -> bool {
+        self as u16 >= WasiErrno::AiAddrfamily as u16
+    }
+
+    /// The `io::ErrorKind` of the error. Kinds such as `HostUnreachable` and
+    /// `ResourceBusy` were stabilized in Rust 1.83, the `rust-version` of the
+    /// crate; errors whose kind came later are `Other`.
+    pub fn kind(self) -> io::ErrorKind {
+        use io::ErrorKind::*;
+        match self {
+            WasiErrno::Toobig => ArgumentListTooLong,
+            WasiErrno::Acces | WasiErrno::Perm | WasiErrno::Notcapable => PermissionDenied,
+            WasiErrno::Addrinuse => AddrInUse,
+            WasiErrno::Addrnotavail => AddrNotAvailable,
+            WasiErrno::Afnosupport
+            | WasiErrno::Nosys
+            | WasiErrno::Notsup
+            | WasiErrno::Protonosupport
+            | WasiErrno::AiFamily
+            | WasiErrno::AiAddrfamily
+            | WasiErrno::AiSocktype => Unsupported,
+            // a nonblocking connect that has not completed yet
+            WasiErrno::Again | WasiErrno::Inprogress => WouldBlock,
+            WasiErrno::Busy => ResourceBusy,
+            WasiErrno::Connaborted => ConnectionAborted,
+            WasiErrno::Connrefused => ConnectionRefused,
+            WasiErrno::Connreset | WasiErrno::Netreset => ConnectionReset,
+            WasiErrno::Deadlk => Deadlock,
+            WasiErrno::Exist => AlreadyExists,
+            WasiErrno::Fbig => FileTooLarge,
+            WasiErrno::Hostunreach => HostUnreachable,
+            WasiErrno::Ilseq | WasiErrno::Badmsg | WasiErrno::Proto => InvalidData,
+            WasiErrno::Intr => Interrupted,
+            WasiErrno::Inval
+            | WasiErrno::Destaddrreq
+            | WasiErrno::Msgsize
+            | WasiErrno::Prototype
+            | WasiErrno::AiBadflag
+            | WasiErrno::AiService => InvalidInput,
+            WasiErrno::Isdir => IsADirectory,
+            WasiErrno::Mlink => TooManyLinks,
+            WasiErrno::Netdown => NetworkDown,
+            WasiErrno::Netunreach => NetworkUnreachable,
+            WasiErrno::Noent | WasiErrno::AiNoname | WasiErrno::AiNodata => NotFound,
+            WasiErrno::Nomem | WasiErrno::AiMemory => OutOfMemory,
+            WasiErrno::Nospc => StorageFull,
+            WasiErrno::Notconn => NotConnected,
+            WasiErrno::Notdir => NotADirectory,
+            WasiErrno::Notempty => DirectoryNotEmpty,
+            WasiErrno::Pipe => BrokenPipe,
+            WasiErrno::Rofs => ReadOnlyFilesystem,
+            WasiErrno::Spipe => NotSeekable,
+            WasiErrno::Stale => StaleNetworkFileHandle,
+            WasiErrno::Timedout => TimedOut,
+            WasiErrno::Txtbsy => ExecutableFileBusy,
+            // QuotaExceeded, InvalidFilename and CrossesDevices need a newer
+            // Rust than the crate's rust-version
+            _ => Other,
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        ERRNO_TABLE[self as usize].1
+    }
+}
+
+impl std::fmt::Display for WasiErrno {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (WASI errno {})", self.message(), *self as u16)
+    }
+}
+
+impl std::error::Error for WasiErrno {}
+
+impl From<WasiErrno> for io::Error {
+    fn from(errno: WasiErrno) -> Self {
+        io::Error::new(errno.kind(), errno)
+    }
+}
+
+/// Convert an errno value returned by the host into an [`io::Error`].
+pub(crate) fn io_error(code: u32) -> io::Error {
+    match WasiErrno::from_raw(code) {
+        Some(errno) => errno.into(),
+        None => io::Error::other(format!("unknown WASI errno {}", code)),
+    }
+}
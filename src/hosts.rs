@@ -0,0 +1,100 @@
+//! A static table of host names, checked before any DNS query.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Names pinned to addresses, in the format of `/etc/hosts`.
+///
+/// Each line holds an address followed by a canonical name and any aliases,
+/// and `#` starts a comment. Names match without regard to case or a
+/// trailing dot. Lines whose address does not parse are skipped, as the C
+/// library does.
+///
+/// ```ignore
+/// let hosts = Hosts::parse("10.0.0.5 db.internal db\n::1 localhost");
+/// let resolver = Resolver::from_env()?.with_hosts(hosts);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Hosts {
+    by_name: HashMap<String, Vec<IpAddr>>,
+    // (address, name) in the order they were added
+    entries: Vec<(IpAddr, String)>,
+}
+
+impl Hosts {
+    pub fn new() -> Self {
+        Hosts::default()
+    }
+
+    /// Parse the contents of a hosts file.
+    pub fn parse(text: &str) -> Self {
+        let mut hosts = Hosts::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut fields = line.split_whitespace();
+            let ip = match fields.next().and_then(parse_ip) {
+                Some(ip) => ip,
+                None => continue,
+            };
+            for name in fields {
+                hosts.insert(name, ip);
+            }
+        }
+        hosts
+    }
+
+    /// Read a hosts file. On WASI, its directory must be preopened.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Hosts::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// Add `ip` to the addresses of `name`.
+    pub fn insert(&mut self, name: &str, ip: IpAddr) {
+        let key = key_name(name);
+        let ips = self.by_name.entry(key.clone()).or_default();
+        if !ips.contains(&ip) {
+            ips.push(ip);
+            self.entries.push((ip, key));
+        }
+    }
+
+    /// Like [`insert`](Self::insert), for building a table in code.
+    pub fn with(mut self, name: &str, ip: IpAddr) -> Self {
+        self.insert(name, ip);
+        self
+    }
+
+    /// The addresses of `name`, in the order they were added.
+    pub fn get(&self, name: &str) -> Option<&[IpAddr]> {
+        self.by_name.get(&key_name(name)).map(Vec::as_slice)
+    }
+
+    /// The names of `ip`, the canonical name first.
+    pub fn names(&self, ip: IpAddr) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|(i, _)| *i == ip)
+            .map(|(_, name)| name.as_str())
+            .collect()
+    }
+
+    /// The number of names.
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+}
+
+fn key_name(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+// addresses may carry a zone, as in `fe80::1%eth0`, which is dropped
+fn parse_ip(s: &str) -> Option<IpAddr> {
+    s.split('%').next()?.parse().ok()
+}
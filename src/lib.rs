@@ -1,14 +1,38 @@
+mod addr_select;
+pub mod authority;
+pub mod cache;
+pub mod dns;
+pub mod errno;
+pub mod hosts;
+pub mod mdns;
+#[cfg(wasmedge_socket_mock)]
+pub mod mock;
+#[cfg(all(not(target_os = "wasi"), not(wasmedge_socket_mock)))]
+mod native;
+pub mod pending;
 pub mod poll;
+pub mod records;
+pub mod resolv_conf;
+pub mod resolver;
+pub mod services;
 pub mod socket;
+#[cfg(feature = "stub")]
+pub mod stub;
+#[cfg(feature = "tls")]
+pub mod tls;
 #[cfg(feature = "wasi_poll")]
 pub mod wasi_poll;
 #[cfg(not(feature = "wasi_poll"))]
 mod wasi_poll;
+pub use dns::{nslookup_with_dns_server, resolve, resolve_with_deadline, ToQType};
+pub use errno::WasiErrno;
+pub use hosts::Hosts;
+pub use resolver::Resolver;
 pub use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr};
 use std::{
     io::{self, Read, Write},
     net::{SocketAddrV4, SocketAddrV6},
-    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd},
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, RawFd},
 };
 
 #[derive(Debug)]
@@ -86,10 +110,19 @@ impl TcpStream {
     /// Create TCP socket and connect to the given address.
     ///
     /// If multiple address is given, the first successful socket is
-    /// returned.
+    /// returned. On WasmEdge, IPv6 addresses with a scope id, such as
+    /// `[fe80::1%2]:80`, fail with `Unsupported`, as the host ignores it.
     pub fn connect<A: ToSocketAddrs>(addrs: A) -> io::Result<TcpStream> {
+        Self::connect_addrs(addrs.to_socket_addrs()?)
+    }
+
+    /// Like [`TcpStream::connect`], but look host names up with `resolver`.
+    pub fn connect_with<A: ToSocketAddrs>(addrs: A, resolver: &Resolver) -> io::Result<TcpStream> {
+        Self::connect_addrs(addrs.to_socket_addrs_with(resolver)?)
+    }
+
+    fn connect_addrs<I: Iterator<Item = SocketAddr>>(addrs: I) -> io::Result<TcpStream> {
         let mut last_error = io::Error::from(io::ErrorKind::ConnectionRefused);
-        let addrs = addrs.to_socket_addrs()?;
 
         let connect = |addrs| {
             let addr_family = socket::AddressFamily::from(&addrs);
@@ -104,7 +137,7 @@ impl TcpStream {
                 Err(e) => last_error = e,
             }
         }
-        return Err(last_error);
+        Err(last_error)
     }
 
     pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
@@ -125,25 +158,67 @@ impl TcpStream {
         self.s.set_nonblocking(nonblocking)
     }
 
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        self.s.set_ttl(ttl)
+    }
+
+    pub fn ttl(&self) -> io::Result<u32> {
+        self.s.ttl()
+    }
+
+    /// Disable Nagle's algorithm, so that small writes are sent at once.
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.s.set_nodelay(nodelay)
+    }
+
+    pub fn nodelay(&self) -> io::Result<bool> {
+        self.s.nodelay()
+    }
+
+    /// Probe the peer when the connection is idle, with the given settings,
+    /// or stop probing with `None`.
+    pub fn set_keepalive(&self, keepalive: Option<&socket::TcpKeepalive>) -> io::Result<()> {
+        match keepalive {
+            Some(keepalive) => self.s.set_tcp_keepalive(keepalive),
+            None => self.s.set_keepalive(false),
+        }
+    }
+
+    /// The keepalive settings in effect, or `None` if keepalive is disabled.
+    /// On hosts without the TCP level options, the probe times and count
+    /// are `None`.
+    pub fn keepalive(&self) -> io::Result<Option<socket::TcpKeepalive>> {
+        if !self.s.keepalive()? {
+            return Ok(None);
+        }
+        match self.s.tcp_keepalive() {
+            Ok(keepalive) => Ok(Some(keepalive)),
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                Ok(Some(socket::TcpKeepalive::new()))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     pub fn new(s: socket::Socket) -> Self {
         Self { s }
     }
 }
 
 impl AsRawFd for TcpStream {
-    fn as_raw_fd(&self) -> std::os::wasi::prelude::RawFd {
+    fn as_raw_fd(&self) -> RawFd {
         self.s.as_raw_fd()
     }
 }
 
 impl IntoRawFd for TcpStream {
-    fn into_raw_fd(self) -> std::os::wasi::prelude::RawFd {
+    fn into_raw_fd(self) -> RawFd {
         self.s.into_raw_fd()
     }
 }
 
 impl FromRawFd for TcpStream {
-    unsafe fn from_raw_fd(fd: std::os::wasi::prelude::RawFd) -> Self {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
         Self {
             s: socket::Socket::from_raw_fd(fd),
         }
@@ -222,7 +297,7 @@ impl TcpListener {
             }
         }
 
-        return Err(last_error);
+        Err(last_error)
     }
 
     /// Accept incoming connections with given file descriptor flags.
@@ -244,19 +319,19 @@ impl TcpListener {
 }
 
 impl AsRawFd for TcpListener {
-    fn as_raw_fd(&self) -> std::os::wasi::prelude::RawFd {
+    fn as_raw_fd(&self) -> RawFd {
         self.s.as_raw_fd()
     }
 }
 
 impl IntoRawFd for TcpListener {
-    fn into_raw_fd(self) -> std::os::wasi::prelude::RawFd {
+    fn into_raw_fd(self) -> RawFd {
         self.s.into_raw_fd()
     }
 }
 
 impl FromRawFd for TcpListener {
-    unsafe fn from_raw_fd(fd: std::os::wasi::prelude::RawFd) -> Self {
+    unsafe fn from_raw_fd(fd: RawFd) -> Self {
         let s: socket::Socket = FromRawFd::from_raw_fd(fd);
         match s.get_local() {
             Ok(address) => {
@@ -315,7 +390,7 @@ impl UdpSocket {
             }
         }
 
-        return Err(last_error);
+        Err(last_error)
     }
     pub fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
         self.s.recv_from(buf)
@@ -328,175 +403,194 @@ impl UdpSocket {
 
         self.s.send_to(buf, addr)
     }
-}
 
-impl AsRawFd for UdpSocket {
-    fn as_raw_fd(&self) -> std::os::wasi::prelude::RawFd {
-        self.s.as_raw_fd()
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        self.s.set_ttl(ttl)
     }
-}
 
-pub fn nslookup(node: &str, service: &str) -> std::io::Result<Vec<SocketAddr>> {
-    let dns_server = std::env::var("DNS_SERVER");
-    if let Ok(dns_server) = dns_server {
-        nslookup_with_dns_server(&dns_server, node, service)
-    } else {
-        nslookup_with_host(node, service)
+    pub fn ttl(&self) -> io::Result<u32> {
+        self.s.ttl()
     }
-}
-
-pub fn nslookup_with_host(node: &str, service: &str) -> std::io::Result<Vec<SocketAddr>> {
-    use socket::WasiAddrinfo;
-    let hints: WasiAddrinfo = WasiAddrinfo::default();
-    let mut sockaddrs = Vec::new();
-    let mut sockbuffs = Vec::new();
-    let mut ai_canonnames = Vec::new();
-    let addrinfos = WasiAddrinfo::get_addrinfo(
-        &node,
-        &service,
-        &hints,
-        10,
-        &mut sockaddrs,
-        &mut sockbuffs,
-        &mut ai_canonnames,
-    )?;
 
-    let mut r_addrs = vec![];
-    for i in 0..addrinfos.len() {
-        let addrinfo = &addrinfos[i];
-        let sockaddr = &sockaddrs[i];
-        let sockbuff = &sockbuffs[i];
-
-        if addrinfo.ai_addrlen == 0 {
-            continue;
-        }
-
-        let addr = match sockaddr.family {
-            socket::AddressFamily::Unspec => {
-                //unimplemented!("not support unspec")
-                continue;
-            }
-            socket::AddressFamily::Inet4 => {
-                let port_buf = [sockbuff[0], sockbuff[1]];
-                let port = u16::from_be_bytes(port_buf);
-                let ip = Ipv4Addr::new(sockbuff[2], sockbuff[3], sockbuff[4], sockbuff[5]);
-                SocketAddr::V4(SocketAddrV4::new(ip, port))
-            }
-            socket::AddressFamily::Inet6 => {
-                //unimplemented!("not support IPv6")
-                continue;
-            }
+    /// Receive datagrams sent to the IPv4 multicast group `multiaddr` on the
+    /// interface with address `interface`, or on one the host picks if it is
+    /// `0.0.0.0`.
+    pub fn join_multicast_v4(&self, multiaddr: &Ipv4Addr, interface: &Ipv4Addr) -> io::Result<()> {
+        let mreq = socket::IpMreq {
+            multiaddr: multiaddr.octets(),
+            interface: interface.octets(),
         };
-
-        r_addrs.push(addr);
+        self.s.setsockopt(
+            socket::SocketOptLevel::IpProtoIp,
+            socket::SocketOptName::IpAddMembership,
+            mreq,
+        )
     }
-    Ok(r_addrs)
-}
 
-pub fn nslookup_with_dns_server(
-    dns_server: &str,
-    node: &str,
-    _service: &str,
-) -> std::io::Result<Vec<SocketAddr>> {
-    let mut conn = TcpStream::connect(dns_server)?;
-    let timeout = std::time::Duration::from_secs(5);
-    let _ignore = conn.as_mut().set_send_timeout(Some(timeout));
-    let _ignore = conn.as_mut().set_recv_timeout(Some(timeout));
+    pub fn leave_multicast_v4(&self, multiaddr: &Ipv4Addr, interface: &Ipv4Addr) -> io::Result<()> {
+        let mreq = socket::IpMreq {
+            multiaddr: multiaddr.octets(),
+            interface: interface.octets(),
+        };
+        self.s.setsockopt(
+            socket::SocketOptLevel::IpProtoIp,
+            socket::SocketOptName::IpDropMembership,
+            mreq,
+        )
+    }
+
+    /// Receive datagrams sent to the IPv6 multicast group `multiaddr` on the
+    /// interface with index `interface`, or on one the host picks if it is 0.
+    pub fn join_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32) -> io::Result<()> {
+        let mreq = socket::Ipv6Mreq {
+            multiaddr: multiaddr.octets(),
+            interface,
+        };
+        self.s.setsockopt(
+            socket::SocketOptLevel::IpProtoIpv6,
+            socket::SocketOptName::Ipv6JoinGroup,
+            mreq,
+        )
+    }
 
-    if node == "localhost" {
-        return ("127.0.0.1", 0u16).to_socket_addrs().map(|v| v.collect());
+    pub fn leave_multicast_v6(&self, multiaddr: &Ipv6Addr, interface: u32) -> io::Result<()> {
+        let mreq = socket::Ipv6Mreq {
+            multiaddr: multiaddr.octets(),
+            interface,
+        };
+        self.s.setsockopt(
+            socket::SocketOptLevel::IpProtoIpv6,
+            socket::SocketOptName::Ipv6LeaveGroup,
+            mreq,
+        )
+    }
+
+    /// How many routers multicast datagrams may cross; 1, the local network
+    /// only, by default.
+    pub fn set_multicast_ttl_v4(&self, ttl: u32) -> io::Result<()> {
+        if ttl > 255 {
+            return Err(WasiErrno::Inval.into());
+        }
+        self.s.setsockopt(
+            socket::SocketOptLevel::IpProtoIp,
+            socket::SocketOptName::IpMulticastTtl,
+            ttl as i32,
+        )
     }
-    let r = resolve::<_, Ipv4Addr>(&mut conn, node)
-        .unwrap_or_default()
-        .into_iter()
-        .map(|addr| (addr, 0).into())
-        .collect::<Vec<SocketAddr>>();
-    if r.is_empty() {
-        // __WASI_ERRNO_AINODATA
-        Err(std::io::Error::from_raw_os_error(83))
-    } else {
-        Ok(r)
+
+    pub fn multicast_ttl_v4(&self) -> io::Result<u32> {
+        let ttl: i32 = self.s.getsockopt(
+            socket::SocketOptLevel::IpProtoIp,
+            socket::SocketOptName::IpMulticastTtl,
+        )?;
+        Ok(ttl as u32)
     }
-}
 
-pub trait ToQType: Sized {
-    fn q_type() -> dns_parser::QueryType;
+    pub fn set_multicast_hops_v6(&self, hops: u32) -> io::Result<()> {
+        if hops > 255 {
+            return Err(WasiErrno::Inval.into());
+        }
+        self.s.setsockopt(
+            socket::SocketOptLevel::IpProtoIpv6,
+            socket::SocketOptName::Ipv6MulticastHops,
+            hops as i32,
+        )
+    }
 
-    fn from_rr(rr: dns_parser::RData) -> Option<Self>;
-}
+    pub fn multicast_hops_v6(&self) -> io::Result<u32> {
+        let hops: i32 = self.s.getsockopt(
+            socket::SocketOptLevel::IpProtoIpv6,
+            socket::SocketOptName::Ipv6MulticastHops,
+        )?;
+        Ok(hops as u32)
+    }
 
-impl ToQType for Ipv4Addr {
-    fn q_type() -> dns_parser::QueryType {
-        dns_parser::QueryType::A
+    /// Whether multicast datagrams sent from this socket are delivered back
+    /// to sockets on the same host that joined the group.
+    pub fn set_multicast_loop_v4(&self, on: bool) -> io::Result<()> {
+        self.s.setsockopt(
+            socket::SocketOptLevel::IpProtoIp,
+            socket::SocketOptName::IpMulticastLoop,
+            on as i32,
+        )
     }
 
-    fn from_rr(rr: dns_parser::RData) -> Option<Self> {
-        if let dns_parser::RData::A(ip) = rr {
-            Some(ip.0)
-        } else {
-            None
-        }
+    pub fn multicast_loop_v4(&self) -> io::Result<bool> {
+        let on: i32 = self.s.getsockopt(
+            socket::SocketOptLevel::IpProtoIp,
+            socket::SocketOptName::IpMulticastLoop,
+        )?;
+        Ok(on != 0)
     }
-}
 
-impl ToQType for Ipv6Addr {
-    fn q_type() -> dns_parser::QueryType {
-        dns_parser::QueryType::AAAA
+    pub fn set_multicast_loop_v6(&self, on: bool) -> io::Result<()> {
+        self.s.setsockopt(
+            socket::SocketOptLevel::IpProtoIpv6,
+            socket::SocketOptName::Ipv6MulticastLoop,
+            on as i32,
+        )
     }
 
-    fn from_rr(rr: dns_parser::RData) -> Option<Self> {
-        if let dns_parser::RData::AAAA(ip) = rr {
-            Some(ip.0)
-        } else {
-            None
-        }
+    pub fn multicast_loop_v6(&self) -> io::Result<bool> {
+        let on: i32 = self.s.getsockopt(
+            socket::SocketOptLevel::IpProtoIpv6,
+            socket::SocketOptName::Ipv6MulticastLoop,
+        )?;
+        Ok(on != 0)
     }
 }
 
-pub fn resolve<S: Write + Read, T: ToQType>(
-    conn: &mut S,
-    name: &str,
-) -> Result<Vec<T>, Box<dyn std::error::Error>> {
-    use dns_parser::QueryClass;
-    use dns_parser::{Builder, Packet, ResponseCode};
-    let id = rand::random();
-    let mut builder = Builder::new_query(id, true);
-    builder.add_question(name, false, T::q_type(), QueryClass::IN);
-    let packet = builder.build().map_err(|_| "truncated packet")?;
-    let mut psize = (packet.len() as u16).to_be_bytes();
-
-    conn.write_all(&psize[..])?;
-    conn.write_all(&packet)?;
-
-    let n = conn.read(&mut psize)?;
-    if n < 2 {
-        return Err("Partial packet received".into());
+impl From<socket::Socket> for UdpSocket {
+    fn from(s: socket::Socket) -> Self {
+        UdpSocket { s }
     }
+}
 
-    let psize = u16::from_be_bytes(psize) as usize;
-    let mut buf = vec![0u8; psize];
-    let n = conn.read(&mut buf[0..psize])?;
-    if n != psize {
-        return Err("Partial packet received".into());
+impl AsRawFd for UdpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.s.as_raw_fd()
     }
+}
 
-    let pkt = Packet::parse(&buf)?;
+/// The addresses of `node` with the port of `service`, using the shared
+/// [`Resolver::env`].
+pub fn nslookup(node: &str, service: &str) -> std::io::Result<Vec<SocketAddr>> {
+    Resolver::env()?.lookup(node, service)
+}
 
-    if pkt.header.id != id {
-        return Err("Illegal id".into());
-    }
+/// The names `ip` maps back to, such as the peer address of an accepted
+/// connection, using the shared [`Resolver::env`].
+pub fn nslookup_addr(ip: IpAddr) -> std::io::Result<Vec<String>> {
+    Resolver::env()?.lookup_addr(ip)
+}
 
-    if pkt.header.response_code != ResponseCode::NoError {
-        return Err(pkt.header.response_code.into());
-    }
+pub fn nslookup_with_host(node: &str, service: &str) -> std::io::Result<Vec<SocketAddr>> {
+    nslookup_with_protocol(node, service, socket::AiProtocol::IPProtoTCP)
+}
 
-    let mut address = vec![];
-    for ans in pkt.answers {
-        if let Some(addr) = T::from_rr(ans.data) {
-            address.push(addr);
+/// Ask the host for the addresses of `node`, with the port of `service` over
+/// `protocol`. `IPProtoIP` asks for any protocol.
+pub fn nslookup_with_protocol(
+    node: &str,
+    service: &str,
+    protocol: socket::AiProtocol,
+) -> std::io::Result<Vec<SocketAddr>> {
+    use socket::{AiProtocol, Hints, SocketType};
+    let hints = Hints::new()
+        .with_socktype(match protocol {
+            AiProtocol::IPProtoTCP => SocketType::Stream,
+            AiProtocol::IPProtoUDP => SocketType::Datagram,
+            AiProtocol::IPProtoIP => SocketType::Any,
+        })
+        .with_protocol(protocol);
+    let mut r_addrs = vec![];
+    for info in socket::getaddrinfo(node, service, &hints)? {
+        // the same address comes back once per socket type when any is asked for
+        if !r_addrs.contains(&info.addr) {
+            r_addrs.push(info.addr);
         }
     }
-    Ok(address)
+    Ok(r_addrs)
 }
 
 /*
@@ -506,6 +600,11 @@ pub trait ToSocketAddrs {
     type Iter: Iterator<Item = SocketAddr>;
 
     fn to_socket_addrs(&self) -> std::io::Result<Self::Iter>;
+
+    /// Like `to_socket_addrs`, but look host names up with `resolver`.
+    fn to_socket_addrs_with(&self, _resolver: &Resolver) -> std::io::Result<Self::Iter> {
+        self.to_socket_addrs()
+    }
 }
 
 impl ToSocketAddrs for SocketAddr {
@@ -560,24 +659,15 @@ impl ToSocketAddrs for (&str, u16) {
     type Iter = std::vec::IntoIter<SocketAddr>;
     fn to_socket_addrs(&self) -> io::Result<std::vec::IntoIter<SocketAddr>> {
         let (host, port) = *self;
+        lookup_host(host, &port.to_string(), None)
+    }
 
-        // try to parse the host as a regular IP address first
-        if let Ok(addr) = host.parse::<std::net::Ipv4Addr>() {
-            let addr = std::net::SocketAddrV4::new(addr, port);
-            return Ok(vec![SocketAddr::V4(addr)].into_iter());
-        }
-        if let Ok(addr) = host.parse::<std::net::Ipv6Addr>() {
-            let addr = std::net::SocketAddrV6::new(addr, port, 0, 0);
-            return Ok(vec![SocketAddr::V6(addr)].into_iter());
-        }
-        let v: Vec<_> = nslookup(host, "http")?
-            .into_iter()
-            .map(|mut a| {
-                a.set_port(port);
-                a
-            })
-            .collect();
-        Ok(v.into_iter())
+    fn to_socket_addrs_with(
+        &self,
+        resolver: &Resolver,
+    ) -> io::Result<std::vec::IntoIter<SocketAddr>> {
+        let (host, port) = *self;
+        lookup_host(host, &port.to_string(), Some(resolver))
     }
 }
 
@@ -586,40 +676,86 @@ impl ToSocketAddrs for (String, u16) {
     fn to_socket_addrs(&self) -> io::Result<std::vec::IntoIter<SocketAddr>> {
         (&*self.0, self.1).to_socket_addrs()
     }
+
+    fn to_socket_addrs_with(
+        &self,
+        resolver: &Resolver,
+    ) -> io::Result<std::vec::IntoIter<SocketAddr>> {
+        (&*self.0, self.1).to_socket_addrs_with(resolver)
+    }
 }
 
 // accepts strings like 'localhost:12345'
 impl ToSocketAddrs for str {
     type Iter = std::vec::IntoIter<SocketAddr>;
     fn to_socket_addrs(&self) -> io::Result<std::vec::IntoIter<SocketAddr>> {
-        // try to parse as a regular SocketAddr first
-        if let Ok(addr) = self.parse() {
-            return Ok(vec![addr].into_iter());
-        }
+        split_host_port(self, None)
+    }
 
-        let host_and_port = self.split(":").collect::<Vec<&str>>();
-        if host_and_port.len() != 2 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "invalid socket address",
-            ));
-        }
-        let host = host_and_port[0];
-        let port = str::parse::<u16>(host_and_port[1]).map_err(|_e| {
-            std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid port value")
-        })?;
-        let mut addrs = nslookup(host, "http")?;
-        for addr in addrs.iter_mut() {
-            addr.set_port(port);
-        }
-        Ok(addrs.into_iter())
+    fn to_socket_addrs_with(
+        &self,
+        resolver: &Resolver,
+    ) -> io::Result<std::vec::IntoIter<SocketAddr>> {
+        split_host_port(self, Some(resolver))
     }
 }
 
+fn split_host_port(
+    s: &str,
+    resolver: Option<&Resolver>,
+) -> io::Result<std::vec::IntoIter<SocketAddr>> {
+    // try to parse as a regular SocketAddr first
+    if let Ok(addr) = s.parse() {
+        return Ok(vec![addr].into_iter());
+    }
+
+    let authority = authority::Authority::parse(s)?;
+    if authority.port.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("missing port in {:?}", s),
+        ));
+    }
+    Ok(authority.resolve(0, resolver)?.into_iter())
+}
+
+/// Resolve `host` and the TCP port of `service` with `resolver`, or with
+/// [`Resolver::env`] if none is given, unless they are an IP address and
+/// a port number.
+fn lookup_host(
+    host: &str,
+    service: &str,
+    resolver: Option<&Resolver>,
+) -> io::Result<std::vec::IntoIter<SocketAddr>> {
+    // try to parse the host as a regular IP address first
+    let ip = host.parse::<IpAddr>().ok();
+    if let (Some(ip), Ok(port)) = (ip, service.parse::<u16>()) {
+        return Ok(vec![SocketAddr::new(ip, port)].into_iter());
+    }
+    let resolver = match resolver {
+        Some(resolver) => resolver,
+        None => Resolver::env()?,
+    };
+    if let Some(ip) = ip {
+        let port = resolver
+            .services()
+            .resolve(service, socket::AiProtocol::IPProtoTCP)?;
+        return Ok(vec![SocketAddr::new(ip, port)].into_iter());
+    }
+    Ok(resolver.lookup(host, service)?.into_iter())
+}
+
 impl ToSocketAddrs for String {
     type Iter = std::vec::IntoIter<SocketAddr>;
     fn to_socket_addrs(&self) -> io::Result<std::vec::IntoIter<SocketAddr>> {
-        (&**self).to_socket_addrs()
+        (**self).to_socket_addrs()
+    }
+
+    fn to_socket_addrs_with(
+        &self,
+        resolver: &Resolver,
+    ) -> io::Result<std::vec::IntoIter<SocketAddr>> {
+        (**self).to_socket_addrs_with(resolver)
     }
 }
 
@@ -636,4 +772,8 @@ impl<T: ToSocketAddrs + ?Sized> ToSocketAddrs for &T {
     fn to_socket_addrs(&self) -> io::Result<T::Iter> {
         (**self).to_socket_addrs()
     }
+
+    fn to_socket_addrs_with(&self, resolver: &Resolver) -> io::Result<T::Iter> {
+        (**self).to_socket_addrs_with(resolver)
+    }
 }
@@ -0,0 +1,649 @@
+//! Multicast DNS (RFC 6762) and DNS-based service discovery (RFC 6763), for
+//! finding hosts and services by their `.local` names on the local link.
+//!
+//! A [`Responder`] answers for a host name and the services on it, and a
+//! [`Querier`] asks for them:
+//!
+//! ```ignore
+//! let mut responder = Responder::new("edge-1.local", vec![ip])?;
+//! responder.add_service(&Service::new("edge-1", "_http._tcp", 8080).with_txt("path=/api"))?;
+//! responder.announce()?;
+//! std::thread::spawn(move || responder.serve());
+//!
+//! let querier = Querier::new()?;
+//! let ips = querier.resolve("edge-1.local", Duration::from_secs(1))?;
+//! let services = querier.browse("_http._tcp", Duration::from_secs(1))?;
+//! ```
+//!
+//! On WasmEdge, the host must support the multicast socket options. Hosts
+//! that only know the `SolSocket` level reject them, and [`Querier::new`] and
+//! [`Responder::new`] then fail with `Unsupported`.
+
+use crate::dns::{self, ToQType, CLASS_IN};
+use crate::poll::{poll, Subscription};
+use crate::records::{self, Ptr, Record, RecordData, Srv, Txt};
+use crate::socket::{AddressFamily, Socket, SocketOptLevel, SocketOptName, SocketType};
+use crate::{UdpSocket, WasiErrno};
+use dns_parser::{Packet, RData};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::time::{Duration, SystemTime};
+
+pub const MDNS_PORT: u16 = 5353;
+pub const MDNS_V4: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const MDNS_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 0xfb);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+
+// set in the class of records no other responder has, so that caches drop
+// what they had for them (RFC 6762 section 10.2)
+const CACHE_FLUSH: u16 = 0x8000;
+// the TTL of answers to queries that do not come from port 5353
+const LEGACY_TTL: u32 = 10;
+// the TTLs RFC 6762 recommends for records with host names, and for others
+const HOST_TTL: u32 = 120;
+const OTHER_TTL: u32 = 4500;
+// a message may fill an Ethernet jumbo frame
+const MAX_MESSAGE: usize = 9000;
+
+/// The name under which service types are listed (RFC 6763 section 9).
+pub const SERVICE_TYPES: &str = "_services._dns-sd._udp.local";
+
+/// A service a [`Responder`] announces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Service {
+    /// The name people see, such as `Living Room`.
+    pub instance: String,
+    /// The service and its protocol, such as `_http._tcp`.
+    pub service_type: String,
+    pub port: u16,
+    /// `key=value` entries of the TXT record.
+    pub txt: Vec<String>,
+}
+
+impl Service {
+    pub fn new(instance: &str, service_type: &str, port: u16) -> Self {
+        Service {
+            instance: instance.to_string(),
+            service_type: service_type.to_string(),
+            port,
+            txt: vec![],
+        }
+    }
+
+    pub fn with_txt(mut self, entry: &str) -> Self {
+        self.txt.push(entry.to_string());
+        self
+    }
+
+    /// The name browsing for the service type finds, such as
+    /// `_http._tcp.local`.
+    pub fn type_name(&self) -> String {
+        format!("{}.local", self.service_type.trim_end_matches('.'))
+    }
+
+    /// The name of the instance, such as `Living Room._http._tcp.local`.
+    pub fn full_name(&self) -> String {
+        format!("{}.{}", self.instance, self.type_name())
+    }
+}
+
+/// A service instance found by [`Querier::browse`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceInstance {
+    /// The name of the instance, such as `Living Room._http._tcp.local`.
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub txt: Txt,
+    pub addrs: Vec<IpAddr>,
+}
+
+impl ServiceInstance {
+    pub fn socket_addrs(&self) -> Vec<SocketAddr> {
+        self.addrs
+            .iter()
+            .map(|&ip| SocketAddr::new(ip, self.port))
+            .collect()
+    }
+}
+
+/// A socket for multicast DNS on `interface`, and the group to send to. The
+/// socket of a responder joins the group on port 5353.
+fn open(interface: Interface, responder: bool) -> io::Result<(UdpSocket, SocketAddr)> {
+    let (family, bind, group) = match interface {
+        Interface::V4(_) => (
+            AddressFamily::Inet4,
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V4(SocketAddrV4::new(MDNS_V4, MDNS_PORT)),
+        ),
+        Interface::V6(index) => (
+            AddressFamily::Inet6,
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(SocketAddrV6::new(MDNS_V6, MDNS_PORT, 0, index)),
+        ),
+    };
+    let s = Socket::new(family, SocketType::Datagram)?;
+    if responder {
+        // other responders on the host listen on the same port
+        s.setsockopt(SocketOptLevel::SolSocket, SocketOptName::SoReuseaddr, 1i32)?;
+        s.bind(&SocketAddr::new(bind.ip(), MDNS_PORT))?;
+    } else {
+        s.bind(&bind)?;
+    }
+    s.set_nonblocking(true)?;
+    let socket = UdpSocket::from(s);
+    // queriers on the same host must hear the messages too, and receivers
+    // check that they were not routed (RFC 6762 section 11)
+    match interface {
+        Interface::V4(ip) => {
+            if responder {
+                socket.join_multicast_v4(&MDNS_V4, &ip)?;
+            }
+            socket.set_multicast_loop_v4(true)?;
+            socket.set_multicast_ttl_v4(255)?;
+        }
+        Interface::V6(index) => {
+            if responder {
+                socket.join_multicast_v6(&MDNS_V6, index)?;
+            }
+            socket.set_multicast_loop_v6(true)?;
+            socket.set_multicast_hops_v6(255)?;
+        }
+    }
+    Ok((socket, group))
+}
+
+#[derive(Copy, Clone)]
+enum Interface {
+    V4(Ipv4Addr),
+    V6(u32),
+}
+
+/// Asks the link about `.local` names.
+///
+/// Queries are sent from a port other than 5353, so responders answer them
+/// directly rather than to the group. Each query waits for its whole
+/// timeout, since any number of responders may answer.
+pub struct Querier {
+    socket: UdpSocket,
+    group: SocketAddr,
+}
+
+impl Querier {
+    /// Ask on the IPv4 link.
+    pub fn new() -> io::Result<Self> {
+        let (socket, group) = open(Interface::V4(Ipv4Addr::UNSPECIFIED), false)?;
+        Ok(Querier { socket, group })
+    }
+
+    /// Ask on the IPv6 link of the interface with index `interface`.
+    pub fn new_v6(interface: u32) -> io::Result<Self> {
+        let (socket, group) = open(Interface::V6(interface), false)?;
+        Ok(Querier { socket, group })
+    }
+
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// The records of type `T` of `name` in the answers that arrive within
+    /// `timeout`.
+    pub fn query<T: ToQType + PartialEq>(
+        &self,
+        name: &str,
+        timeout: Duration,
+    ) -> io::Result<Vec<T>> {
+        let responses = self.ask(&[(name, T::type_code())], timeout)?;
+        Ok(records(&responses, name))
+    }
+
+    /// The IPv4 and IPv6 addresses of `name`, such as `printer.local`.
+    pub fn resolve(&self, name: &str, timeout: Duration) -> io::Result<Vec<IpAddr>> {
+        let responses = self.ask(&[(name, TYPE_A), (name, TYPE_AAAA)], timeout)?;
+        let ips = addresses(&responses, name);
+        if ips.is_empty() {
+            return Err(WasiErrno::AiNoname.into());
+        }
+        Ok(ips)
+    }
+
+    /// The service types announced on the link, such as `_http._tcp.local`.
+    pub fn service_types(&self, timeout: Duration) -> io::Result<Vec<String>> {
+        let ptrs: Vec<Ptr> = self.query(SERVICE_TYPES, timeout)?;
+        Ok(ptrs.into_iter().map(|p| p.0).collect())
+    }
+
+    /// The instances of `service_type`, such as `_http._tcp`, with their
+    /// hosts, ports, TXT records and addresses.
+    pub fn browse(
+        &self,
+        service_type: &str,
+        timeout: Duration,
+    ) -> io::Result<Vec<ServiceInstance>> {
+        let type_name = format!("{}.local", service_type.trim_end_matches('.'));
+        let mut responses = self.ask(&[(&type_name, TYPE_PTR)], timeout)?;
+        let names: Vec<Ptr> = records(&responses, &type_name);
+
+        // responders put the SRV, TXT and address records in the answers to
+        // PTR queries; ask again for any that are missing
+        let mut missing = vec![];
+        for Ptr(name) in &names {
+            if records::<Srv>(&responses, name).is_empty() {
+                missing.push((name.as_str(), TYPE_SRV));
+                missing.push((name.as_str(), TYPE_TXT));
+            }
+        }
+        if !missing.is_empty() {
+            responses.extend(self.ask(&missing, timeout)?);
+        }
+        let mut hosts = vec![];
+        for Ptr(name) in &names {
+            for srv in records::<Srv>(&responses, name) {
+                if addresses(&responses, &srv.target).is_empty() && !hosts.contains(&srv.target) {
+                    hosts.push(srv.target);
+                }
+            }
+        }
+        let questions: Vec<(&str, u16)> = hosts
+            .iter()
+            .flat_map(|host| [(host.as_str(), TYPE_A), (host.as_str(), TYPE_AAAA)])
+            .collect();
+        if !questions.is_empty() {
+            responses.extend(self.ask(&questions, timeout)?);
+        }
+
+        let mut instances = vec![];
+        for Ptr(name) in names {
+            let srv = match records::<Srv>(&responses, &name).into_iter().next() {
+                Some(srv) => srv,
+                None => continue,
+            };
+            let txt = records::<Txt>(&responses, &name)
+                .into_iter()
+                .next()
+                .unwrap_or(Txt(vec![]));
+            instances.push(ServiceInstance {
+                addrs: addresses(&responses, &srv.target),
+                name,
+                host: srv.target,
+                port: srv.port,
+                txt,
+            });
+        }
+        Ok(instances)
+    }
+
+    /// Send one query with `questions`, and gather the responses that
+    /// arrive within `timeout`.
+    fn ask(&self, questions: &[(&str, u16)], timeout: Duration) -> io::Result<Vec<Vec<u8>>> {
+        let id = rand::random();
+        let (first, qtype) = questions[0];
+        let mut query = dns::build_query(id, first, qtype)?;
+        // one-shot queries do not ask for recursion
+        query[2] &= !0x01;
+        for &(name, qtype) in &questions[1..] {
+            dns::encode_name(&mut query, name)?;
+            query.extend_from_slice(&qtype.to_be_bytes());
+            query.extend_from_slice(&CLASS_IN.to_be_bytes());
+        }
+        query[4..6].copy_from_slice(&(questions.len() as u16).to_be_bytes());
+
+        // drop answers left over from an earlier query
+        let mut buf = [0u8; MAX_MESSAGE];
+        while self.socket.recv_from(&mut buf).is_ok() {}
+        self.socket.send_to(&query, self.group)?;
+
+        let deadline = SystemTime::now() + timeout;
+        let mut responses = vec![];
+        while SystemTime::now() < deadline {
+            poll(&[Subscription::io(
+                0,
+                &self.socket,
+                true,
+                false,
+                Some(deadline),
+            )])?;
+            loop {
+                let n = match self.socket.recv_from(&mut buf) {
+                    Ok((n, _)) => n,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(e),
+                };
+                let response = &buf[..n];
+                // a response, to this query or to the group (RFC 6762
+                // section 18.1)
+                let id_matches =
+                    n >= 2 && (response[..2] == id.to_be_bytes() || response[..2] == [0, 0]);
+                if n > 3 && id_matches && response[2] & 0x80 != 0 && response[3] & 0x0f == 0 {
+                    if let Ok((masked, _)) = dns::masked(response) {
+                        responses.push(masked);
+                    }
+                }
+            }
+        }
+        Ok(responses)
+    }
+}
+
+/// The records of type `T` owned by `name` in the answer and additional
+/// sections of `responses`, without repeats.
+fn records<T: ToQType + PartialEq>(responses: &[Vec<u8>], name: &str) -> Vec<T> {
+    let mut out = vec![];
+    for response in responses {
+        let pkt = match Packet::parse(response) {
+            Ok(pkt) => pkt,
+            Err(_) => continue,
+        };
+        for rr in pkt.answers.into_iter().chain(pkt.additional) {
+            if !same_name(&rr.name.to_string(), name) || rr.ttl == 0 {
+                continue;
+            }
+            if let Some(record) = T::from_rr(rr.data) {
+                if !out.contains(&record) {
+                    out.push(record);
+                }
+            }
+        }
+    }
+    out
+}
+
+fn addresses(responses: &[Vec<u8>], name: &str) -> Vec<IpAddr> {
+    let v4 = records::<Ipv4Addr>(responses, name)
+        .into_iter()
+        .map(IpAddr::V4);
+    let v6 = records::<Ipv6Addr>(responses, name)
+        .into_iter()
+        .map(IpAddr::V6);
+    v4.chain(v6).collect()
+}
+
+/// Answers queries for a host name and its services on the link.
+///
+/// The socket is bound to port 5353 with address reuse, so that it can run
+/// next to other responders on the same host.
+pub struct Responder {
+    socket: UdpSocket,
+    group: SocketAddr,
+    host: String,
+    // each record, and whether no other responder has it
+    records: Vec<(Record, bool)>,
+}
+
+impl Responder {
+    /// Answer for `host`, such as `edge-1.local`, with `addrs` on the IPv4
+    /// link.
+    pub fn new(host: &str, addrs: Vec<IpAddr>) -> io::Result<Self> {
+        Responder::open(host, addrs, Interface::V4(Ipv4Addr::UNSPECIFIED))
+    }
+
+    /// Answer on the IPv6 link of the interface with index `interface`.
+    pub fn new_v6(host: &str, addrs: Vec<IpAddr>, interface: u32) -> io::Result<Self> {
+        Responder::open(host, addrs, Interface::V6(interface))
+    }
+
+    fn open(host: &str, addrs: Vec<IpAddr>, interface: Interface) -> io::Result<Self> {
+        dns::encode_name(&mut vec![], host)?;
+        let (socket, group) = open(interface, true)?;
+        let host = host.trim_end_matches('.').to_string();
+        let records = addrs
+            .into_iter()
+            .map(|ip| (record(&host, HOST_TTL, ip.into()), true))
+            .collect();
+        Ok(Responder {
+            socket,
+            group,
+            host,
+            records,
+        })
+    }
+
+    /// Answer browsing for the type of `service` with it. The service is
+    /// refused if its instance or type does not make a valid DNS name, such as
+    /// an instance longer than 63 octets.
+    pub fn add_service(&mut self, service: &Service) -> io::Result<()> {
+        let full_name = service.full_name();
+        let type_name = service.type_name();
+        let srv = Srv {
+            priority: 0,
+            weight: 0,
+            port: service.port,
+            target: self.host.clone(),
+        };
+        // a TXT record holds at least one string, which may be empty
+        let mut txt: Vec<Vec<u8>> = service.txt.iter().map(|e| e.as_bytes().to_vec()).collect();
+        if txt.is_empty() {
+            txt.push(vec![]);
+        }
+        let types = (
+            record(SERVICE_TYPES, OTHER_TTL, Ptr(type_name.clone()).into()),
+            false,
+        );
+        let added = [
+            (
+                record(&type_name, OTHER_TTL, Ptr(full_name.clone()).into()),
+                false,
+            ),
+            (record(&full_name, HOST_TTL, srv.into()), true),
+            (record(&full_name, OTHER_TTL, Txt(txt).into()), true),
+        ];
+        for (record, _) in &added {
+            records::encode_record(&mut vec![], record, CLASS_IN)?;
+        }
+        if !self.records.contains(&types) {
+            self.records.push(types);
+        }
+        self.records.extend(added);
+        Ok(())
+    }
+
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Send every record to the group, as a responder does when it starts
+    /// or its addresses change. RFC 6762 asks for a second announcement a
+    /// second later.
+    pub fn announce(&self) -> io::Result<()> {
+        let all: Vec<&(Record, bool)> = self.records.iter().collect();
+        let message = self.response(0, &[], &all, &[], None)?;
+        self.socket.send_to(&message, self.group)?;
+        Ok(())
+    }
+
+    /// Tell the link to forget the records, before the responder stops.
+    pub fn goodbye(&self) -> io::Result<()> {
+        let all: Vec<&(Record, bool)> = self.records.iter().collect();
+        let message = self.response(0, &[], &all, &[], Some(0))?;
+        self.socket.send_to(&message, self.group)?;
+        Ok(())
+    }
+
+    /// Answer queries until an error.
+    pub fn serve(&self) -> io::Result<()> {
+        loop {
+            self.step(None)?;
+        }
+    }
+
+    /// Wait up to `timeout` for queries, and answer those that have arrived.
+    pub fn step(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let deadline = timeout.map(|t| SystemTime::now() + t);
+        poll(&[Subscription::io(0, &self.socket, true, false, deadline)])?;
+        let mut buf = [0u8; MAX_MESSAGE];
+        loop {
+            let (n, from) = match self.socket.recv_from(&mut buf) {
+                Ok(r) => r,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            if let Some((message, to)) = self.answer(&buf[..n], from) {
+                let _ = self.socket.send_to(&message, to);
+            }
+        }
+    }
+
+    /// The response to `query` from `from`, and where to send it.
+    fn answer(&self, query: &[u8], from: SocketAddr) -> Option<(Vec<u8>, SocketAddr)> {
+        let (masked, _) = dns::masked(query).ok()?;
+        let pkt = Packet::parse(&masked).ok()?;
+        if !pkt.header.query || pkt.questions.is_empty() {
+            return None;
+        }
+        // a querier that is not on port 5353 is not a full mDNS querier, and
+        // gets a unicast answer that looks like a unicast DNS answer (RFC
+        // 6762 section 6.7)
+        let legacy = from.port() != MDNS_PORT;
+        let mut unicast = legacy;
+        let mut answers: Vec<&(Record, bool)> = vec![];
+        let mut questions = vec![];
+        for q in &pkt.questions {
+            let qname = q.qname.to_string();
+            let qtype = q.qtype as u16;
+            unicast |= q.prefer_unicast;
+            questions.push((qname.clone(), qtype));
+            for entry in &self.records {
+                let (record, _) = entry;
+                if same_name(&record.name, &qname)
+                    && (qtype == TYPE_ANY || record.data.type_code() == qtype)
+                    && !answers.contains(&entry)
+                    // the querier already knows it (RFC 6762 section 7.1)
+                    && !pkt.answers.iter().any(|known| {
+                        known.ttl >= record.ttl / 2
+                            && same_name(&known.name.to_string(), &record.name)
+                            && same_data(&record.data, &known.data)
+                    })
+                {
+                    answers.push(entry);
+                }
+            }
+        }
+        if answers.is_empty() {
+            return None;
+        }
+
+        // what the querier will want next (RFC 6763 section 12)
+        let mut additional: Vec<&(Record, bool)> = vec![];
+        let mut targets: Vec<&str> = vec![];
+        for (record, _) in &answers {
+            match &record.data {
+                RecordData::Ptr(Ptr(name)) => targets.push(name),
+                RecordData::Srv(srv) => targets.push(&srv.target),
+                _ => {}
+            }
+        }
+        while let Some(target) = targets.pop() {
+            for entry in &self.records {
+                let (record, _) = entry;
+                let wanted = matches!(
+                    record.data,
+                    RecordData::Srv(_)
+                        | RecordData::Txt(_)
+                        | RecordData::A(_)
+                        | RecordData::Aaaa(_)
+                );
+                if wanted
+                    && same_name(&record.name, target)
+                    && !answers.contains(&entry)
+                    && !additional.contains(&entry)
+                {
+                    if let RecordData::Srv(srv) = &record.data {
+                        targets.push(&srv.target);
+                    }
+                    additional.push(entry);
+                }
+            }
+        }
+
+        let to = if unicast { from } else { self.group };
+        let message = if legacy {
+            self.response(
+                pkt.header.id,
+                &questions,
+                &answers,
+                &additional,
+                Some(LEGACY_TTL),
+            )
+        } else {
+            self.response(0, &[], &answers, &additional, None)
+        }
+        .ok()?;
+        Some((message, to))
+    }
+
+    /// A response with `id`, repeating `questions`, with the TTLs of the
+    /// records capped at `max_ttl`. The cache flush flag is only set in
+    /// multicast responses.
+    fn response(
+        &self,
+        id: u16,
+        questions: &[(String, u16)],
+        answers: &[&(Record, bool)],
+        additional: &[&(Record, bool)],
+        max_ttl: Option<u32>,
+    ) -> io::Result<Vec<u8>> {
+        let mut out = id.to_be_bytes().to_vec();
+        // a response with authoritative answers
+        out.extend_from_slice(&[0x84, 0]);
+        out.extend_from_slice(&(questions.len() as u16).to_be_bytes());
+        out.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(&(additional.len() as u16).to_be_bytes());
+        for (name, qtype) in questions {
+            dns::encode_name(&mut out, name)?;
+            out.extend_from_slice(&qtype.to_be_bytes());
+            out.extend_from_slice(&CLASS_IN.to_be_bytes());
+        }
+        for (record, unique) in answers.iter().chain(additional) {
+            let class = if *unique && questions.is_empty() {
+                CLASS_IN | CACHE_FLUSH
+            } else {
+                CLASS_IN
+            };
+            match max_ttl {
+                Some(ttl) if record.ttl > ttl => {
+                    let record = Record {
+                        ttl,
+                        ..record.clone()
+                    };
+                    records::encode_record(&mut out, &record, class)?;
+                }
+                _ => records::encode_record(&mut out, record, class)?,
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn record(name: &str, ttl: u32, data: RecordData) -> Record {
+    Record {
+        name: name.to_string(),
+        ttl,
+        data,
+    }
+}
+
+/// Whether a record received has the data of one of ours.
+fn same_data(ours: &RecordData, theirs: &RData) -> bool {
+    match (ours, theirs) {
+        (RecordData::A(a), RData::A(b)) => *a == b.0,
+        (RecordData::Aaaa(a), RData::AAAA(b)) => *a == b.0,
+        (RecordData::Ptr(Ptr(a)), RData::PTR(b)) => same_name(a, &b.0.to_string()),
+        (RecordData::Srv(a), RData::SRV(b)) => {
+            a.port == b.port && same_name(&a.target, &b.target.to_string())
+        }
+        (RecordData::Txt(a), RData::TXT(b)) => a.0.iter().map(Vec::as_slice).eq(b.iter()),
+        _ => false,
+    }
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.')
+        .eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
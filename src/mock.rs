@@ -0,0 +1,826 @@
+//! In-process stand-in for the WasmEdge socket host.
+//!
+//! When built with `--cfg wasmedge_socket_mock` in `RUSTFLAGS`, every
+//! `sock_*`, `poll_oneoff` and fd import used by this crate is served by this
+//! module instead of the host, on any target. It is a cfg flag rather than a
+//! feature so that no dependency can switch a build to the mock by accident. Replies are scripted per import with [`push`], and every
+//! call is recorded with its arguments, see [`calls`]. An import without a
+//! scripted reply succeeds with a neutral default: sockets get fresh file
+//! descriptors, sends write everything, receives report end of stream and
+//! every polled subscription is ready.
+//!
+//! The state is kept per thread, so tests running in parallel do not see
+//! each other's replies or calls.
+//!
+//! ```ignore
+//! use wasmedge_wasi_socket::mock::{self, Import, Reply};
+//! use wasmedge_wasi_socket::WasiErrno;
+//!
+//! mock::push(Import::SockSend, Reply::Sent(2));
+//! mock::push(Import::SockSend, Reply::Errno(WasiErrno::Again));
+//! let mut stream = TcpStream::connect("127.0.0.1:80")?;
+//! assert_eq!(stream.write(b"hello")?, 2);
+//! assert!(stream.write(b"llo").is_err());
+//! ```
+
+use crate::errno::WasiErrno;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+
+/// A host import served by the mock.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Import {
+    SockOpen,
+    SockBind,
+    SockListen,
+    SockAccept,
+    SockConnect,
+    SockRecv,
+    SockRecvFrom,
+    SockSend,
+    SockSendTo,
+    SockShutdown,
+    SockGetPeerAddr,
+    SockGetLocalAddr,
+    SockGetSockOpt,
+    SockSetSockOpt,
+    SockGetAddrInfo,
+    PollOneoff,
+    FdFdstatGet,
+    FdFdstatSetFlags,
+    FdClose,
+}
+
+/// A scripted reply to the next call of an [`Import`].
+#[derive(Clone, Debug)]
+pub enum Reply {
+    /// Fail with the given errno.
+    Errno(WasiErrno),
+    /// Succeed with the default behaviour of the import.
+    Ok,
+    /// `sock_open` and `sock_accept`: succeed with this file descriptor.
+    Fd(u32),
+    /// `sock_send` and `sock_send_to`: accept at most this many bytes.
+    Sent(usize),
+    /// `sock_recv`: deliver these bytes. The truncated flag is reported when
+    /// they do not fit in the receive buffers.
+    Data(Vec<u8>),
+    /// `sock_recv_from`: deliver bytes from a peer. The family and the
+    /// octets after it are written as is, so that unknown address families
+    /// can be reported.
+    DataFrom {
+        data: Vec<u8>,
+        family: u16,
+        octets: Vec<u8>,
+        port: u16,
+    },
+    /// `sock_getlocaladdr` and `sock_getpeeraddr`: report this address. The
+    /// address type is written as is.
+    Address {
+        addr_type: u32,
+        octets: Vec<u8>,
+        port: u16,
+    },
+    /// `sock_getsockopt`: the raw option value.
+    Opt(Vec<u8>),
+    /// `sock_getaddrinfo`: the resolved addresses, in order.
+    AddrInfo(Vec<SocketAddr>),
+    /// `poll_oneoff`: the events to report.
+    Events(Vec<PollEvent>),
+    /// `fd_fdstat_get`: the fd flags.
+    Flags(u16),
+}
+
+impl Reply {
+    /// `sock_recv_from` reply with data sent by `peer`.
+    pub fn data_from(data: &[u8], peer: SocketAddr) -> Reply {
+        let (family, mut octets) = family_and_octets(&peer);
+        if let SocketAddr::V6(a) = peer {
+            octets.extend_from_slice(&a.flowinfo().to_le_bytes());
+            octets.extend_from_slice(&a.scope_id().to_le_bytes());
+        }
+        Reply::DataFrom {
+            data: data.to_vec(),
+            family: family as u16,
+            octets,
+            port: peer.port(),
+        }
+    }
+
+    /// `sock_getlocaladdr` or `sock_getpeeraddr` reply reporting `addr`.
+    pub fn address(addr: SocketAddr) -> Reply {
+        let (family, octets) = family_and_octets(&addr);
+        Reply::Address {
+            addr_type: family as u32,
+            octets,
+            port: addr.port(),
+        }
+    }
+}
+
+/// An event reported by a scripted `poll_oneoff`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PollEvent {
+    pub userdata: u64,
+    /// One of the `EVENTTYPE_*` values of `wasi_poll`.
+    pub type_: u8,
+    pub error: u16,
+    pub hangup: bool,
+}
+
+/// A subscription passed to `poll_oneoff`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PollSubscription {
+    Clock {
+        userdata: u64,
+        timeout: u64,
+        flags: u16,
+    },
+    FdRead {
+        userdata: u64,
+        fd: u32,
+    },
+    FdWrite {
+        userdata: u64,
+        fd: u32,
+    },
+}
+
+/// A recorded call to the host, with its arguments.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Call {
+    SockOpen {
+        addr_family: u8,
+        sock_type: u8,
+    },
+    SockBind {
+        fd: u32,
+        addr: Vec<u8>,
+        port: u32,
+    },
+    SockListen {
+        fd: u32,
+        backlog: u32,
+    },
+    SockAccept {
+        fd: u32,
+    },
+    SockConnect {
+        fd: u32,
+        addr: Vec<u8>,
+        port: u32,
+    },
+    SockRecv {
+        fd: u32,
+        len: usize,
+        flags: u16,
+    },
+    SockRecvFrom {
+        fd: u32,
+        len: usize,
+        flags: u16,
+    },
+    SockSend {
+        fd: u32,
+        data: Vec<u8>,
+        flags: u16,
+    },
+    SockSendTo {
+        fd: u32,
+        data: Vec<u8>,
+        addr: Vec<u8>,
+        port: u32,
+        flags: u16,
+    },
+    SockShutdown {
+        fd: u32,
+        how: u8,
+    },
+    SockGetPeerAddr {
+        fd: u32,
+    },
+    SockGetLocalAddr {
+        fd: u32,
+    },
+    SockGetSockOpt {
+        fd: u32,
+        level: i32,
+        name: i32,
+        len: u32,
+    },
+    SockSetSockOpt {
+        fd: u32,
+        level: i32,
+        name: i32,
+        value: Vec<u8>,
+    },
+    SockGetAddrInfo {
+        node: String,
+        service: String,
+        flags: u16,
+        family: u8,
+        socktype: u8,
+        protocol: u8,
+        max_len: u32,
+    },
+    PollOneoff {
+        subscriptions: Vec<PollSubscription>,
+    },
+    FdFdstatGet {
+        fd: u32,
+    },
+    FdFdstatSetFlags {
+        fd: u32,
+        flags: u16,
+    },
+    FdClose {
+        fd: u32,
+    },
+}
+
+struct Host {
+    replies: HashMap<Import, VecDeque<Reply>>,
+    calls: Vec<Call>,
+    next_fd: u32,
+    fd_flags: HashMap<u32, u16>,
+}
+
+impl Host {
+    fn new() -> Host {
+        Host {
+            replies: HashMap::new(),
+            calls: vec![],
+            next_fd: FIRST_FD,
+            fd_flags: HashMap::new(),
+        }
+    }
+}
+
+const FIRST_FD: u32 = 3;
+
+thread_local! {
+    static HOST: RefCell<Host> = RefCell::new(Host::new());
+}
+
+/// Queue `reply` for the next call of `import` on this thread.
+pub fn push(import: Import, reply: Reply) {
+    HOST.with(|h| {
+        h.borrow_mut()
+            .replies
+            .entry(import)
+            .or_default()
+            .push_back(reply)
+    });
+}
+
+/// The calls made on this thread so far, oldest first.
+pub fn calls() -> Vec<Call> {
+    HOST.with(|h| h.borrow().calls.clone())
+}
+
+/// Return and forget the calls made on this thread so far.
+pub fn take_calls() -> Vec<Call> {
+    HOST.with(|h| std::mem::take(&mut h.borrow_mut().calls))
+}
+
+/// Drop all scripted replies and recorded calls of this thread.
+pub fn reset() {
+    HOST.with(|h| *h.borrow_mut() = Host::new());
+}
+
+fn record(call: Call, import: Import) -> Reply {
+    HOST.with(|h| {
+        let mut h = h.borrow_mut();
+        h.calls.push(call);
+        h.replies
+            .get_mut(&import)
+            .and_then(|q| q.pop_front())
+            .unwrap_or(Reply::Ok)
+    })
+}
+
+fn new_fd() -> u32 {
+    HOST.with(|h| {
+        let mut h = h.borrow_mut();
+        let fd = h.next_fd;
+        h.next_fd += 1;
+        fd
+    })
+}
+
+fn family_and_octets(addr: &SocketAddr) -> (crate::socket::AddressFamily, Vec<u8>) {
+    match addr {
+        SocketAddr::V4(a) => (
+            crate::socket::AddressFamily::Inet4,
+            a.ip().octets().to_vec(),
+        ),
+        SocketAddr::V6(a) => (
+            crate::socket::AddressFamily::Inet6,
+            a.ip().octets().to_vec(),
+        ),
+    }
+}
+
+fn unexpected(import: Import, reply: Reply) -> ! {
+    panic!("mock: reply {:?} does not apply to {:?}", reply, import)
+}
+
+/// The mocked imports, with the signatures of their host counterparts.
+pub(crate) mod host {
+    use super::*;
+    use crate::socket::{AddressFamily, Fdstat, IovecRead, IovecWrite, WasiAddress, WasiAddrinfo};
+    use crate::wasi_poll::{
+        Event, EventFdReadwrite, Subscription, EVENTRWFLAGS_FD_READWRITE_HANGUP, EVENTTYPE_CLOCK,
+        EVENTTYPE_FD_READ, EVENTTYPE_FD_WRITE,
+    };
+    use std::ptr::{addr_of, addr_of_mut};
+
+    unsafe fn address_bytes(addr: *const WasiAddress) -> Vec<u8> {
+        let addr = &*addr;
+        std::slice::from_raw_parts(addr.buf, addr.size).to_vec()
+    }
+
+    unsafe fn gather(buf: *const IovecWrite, buf_len: u32) -> Vec<u8> {
+        let mut data = vec![];
+        for v in std::slice::from_raw_parts(buf, buf_len as usize) {
+            data.extend_from_slice(std::slice::from_raw_parts(v.buf, v.size));
+        }
+        data
+    }
+
+    unsafe fn capacity(buf: *const IovecRead, buf_len: usize) -> usize {
+        std::slice::from_raw_parts(buf, buf_len)
+            .iter()
+            .map(|v| v.size)
+            .sum()
+    }
+
+    /// Copy `data` into the buffers, returning the copied length and whether
+    /// it was truncated.
+    unsafe fn scatter(buf: *mut IovecRead, buf_len: usize, data: &[u8]) -> (usize, bool) {
+        let mut copied = 0;
+        for v in std::slice::from_raw_parts_mut(buf, buf_len) {
+            let n = v.size.min(data.len() - copied);
+            std::ptr::copy_nonoverlapping(data[copied..].as_ptr(), v.buf, n);
+            copied += n;
+        }
+        (copied, copied < data.len())
+    }
+
+    pub unsafe fn sock_open(addr_family: u8, sock_type: u8, fd: *mut u32) -> u32 {
+        let call = Call::SockOpen {
+            addr_family,
+            sock_type,
+        };
+        match record(call, Import::SockOpen) {
+            Reply::Ok => *fd = new_fd(),
+            Reply::Fd(new) => *fd = new,
+            Reply::Errno(e) => return e as u32,
+            r => unexpected(Import::SockOpen, r),
+        }
+        0
+    }
+
+    pub unsafe fn sock_bind(fd: u32, addr: *mut WasiAddress, port: u32) -> u32 {
+        let addr = address_bytes(addr);
+        match record(Call::SockBind { fd, addr, port }, Import::SockBind) {
+            Reply::Ok => 0,
+            Reply::Errno(e) => e as u32,
+            r => unexpected(Import::SockBind, r),
+        }
+    }
+
+    pub unsafe fn sock_listen(fd: u32, backlog: u32) -> u32 {
+        match record(Call::SockListen { fd, backlog }, Import::SockListen) {
+            Reply::Ok => 0,
+            Reply::Errno(e) => e as u32,
+            r => unexpected(Import::SockListen, r),
+        }
+    }
+
+    pub unsafe fn sock_accept(fd: u32, new_fd: *mut u32) -> u32 {
+        match record(Call::SockAccept { fd }, Import::SockAccept) {
+            Reply::Ok => *new_fd = super::new_fd(),
+            Reply::Fd(new) => *new_fd = new,
+            Reply::Errno(e) => return e as u32,
+            r => unexpected(Import::SockAccept, r),
+        }
+        0
+    }
+
+    pub unsafe fn sock_connect(fd: u32, addr: *mut WasiAddress, port: u32) -> u32 {
+        let addr = address_bytes(addr);
+        match record(Call::SockConnect { fd, addr, port }, Import::SockConnect) {
+            Reply::Ok => 0,
+            Reply::Errno(e) => e as u32,
+            r => unexpected(Import::SockConnect, r),
+        }
+    }
+
+    pub unsafe fn sock_recv(
+        fd: u32,
+        buf: *mut IovecRead,
+        buf_len: usize,
+        flags: u16,
+        recv_len: *mut usize,
+        oflags: *mut usize,
+    ) -> u32 {
+        let len = capacity(buf, buf_len);
+        let data = match record(Call::SockRecv { fd, len, flags }, Import::SockRecv) {
+            Reply::Ok => vec![],
+            Reply::Data(data) => data,
+            Reply::Errno(e) => return e as u32,
+            r => unexpected(Import::SockRecv, r),
+        };
+        let (n, truncated) = scatter(buf, buf_len, &data);
+        *recv_len = n;
+        *oflags = truncated as usize;
+        0
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn sock_recv_from(
+        fd: u32,
+        buf: *mut IovecRead,
+        buf_len: u32,
+        addr: *mut u8,
+        flags: u16,
+        port: *mut u32,
+        recv_len: *mut usize,
+        oflags: *mut usize,
+    ) -> u32 {
+        let len = capacity(buf, buf_len as usize);
+        let (data, family, octets, from_port) =
+            match record(Call::SockRecvFrom { fd, len, flags }, Import::SockRecvFrom) {
+                Reply::Ok => (vec![], AddressFamily::Inet4 as u16, vec![0; 4], 0),
+                Reply::DataFrom {
+                    data,
+                    family,
+                    octets,
+                    port,
+                } => (data, family, octets, port),
+                Reply::Errno(e) => return e as u32,
+                r => unexpected(Import::SockRecvFrom, r),
+            };
+        let addr = &*(addr as *const WasiAddress);
+        let out = std::slice::from_raw_parts_mut(addr.buf as *mut u8, addr.size);
+        out[0..2].copy_from_slice(&family.to_le_bytes());
+        out[2..2 + octets.len()].copy_from_slice(&octets);
+        *port = from_port as u32;
+        let (n, truncated) = scatter(buf, buf_len as usize, &data);
+        *recv_len = n;
+        *oflags = truncated as usize;
+        0
+    }
+
+    pub unsafe fn sock_send(
+        fd: u32,
+        buf: *const IovecWrite,
+        buf_len: u32,
+        flags: u16,
+        send_len: *mut u32,
+    ) -> u32 {
+        let data = gather(buf, buf_len);
+        let total = data.len();
+        match record(Call::SockSend { fd, data, flags }, Import::SockSend) {
+            Reply::Ok => *send_len = total as u32,
+            Reply::Sent(n) => *send_len = n.min(total) as u32,
+            Reply::Errno(e) => return e as u32,
+            r => unexpected(Import::SockSend, r),
+        }
+        0
+    }
+
+    pub unsafe fn sock_send_to(
+        fd: u32,
+        buf: *const IovecWrite,
+        buf_len: u32,
+        addr: *const u8,
+        port: u32,
+        flags: u16,
+        send_len: *mut u32,
+    ) -> u32 {
+        let data = gather(buf, buf_len);
+        let total = data.len();
+        let addr = address_bytes(addr as *const WasiAddress);
+        let call = Call::SockSendTo {
+            fd,
+            data,
+            addr,
+            port,
+            flags,
+        };
+        match record(call, Import::SockSendTo) {
+            Reply::Ok => *send_len = total as u32,
+            Reply::Sent(n) => *send_len = n.min(total) as u32,
+            Reply::Errno(e) => return e as u32,
+            r => unexpected(Import::SockSendTo, r),
+        }
+        0
+    }
+
+    pub unsafe fn sock_shutdown(fd: u32, how: u8) -> u32 {
+        match record(Call::SockShutdown { fd, how }, Import::SockShutdown) {
+            Reply::Ok => 0,
+            Reply::Errno(e) => e as u32,
+            r => unexpected(Import::SockShutdown, r),
+        }
+    }
+
+    unsafe fn write_address(
+        import: Import,
+        reply: Reply,
+        addr: *mut WasiAddress,
+        addr_type: *mut u32,
+        port: *mut u32,
+    ) -> u32 {
+        let (family, octets, from_port) = match reply {
+            Reply::Ok => (AddressFamily::Inet4 as u32, vec![0; 4], 0),
+            Reply::Address {
+                addr_type,
+                octets,
+                port,
+            } => (addr_type, octets, port),
+            Reply::Errno(e) => return e as u32,
+            r => unexpected(import, r),
+        };
+        let addr = &*addr;
+        let out = std::slice::from_raw_parts_mut(addr.buf as *mut u8, addr.size);
+        out[..octets.len()].copy_from_slice(&octets);
+        *addr_type = family;
+        *port = from_port as u32;
+        0
+    }
+
+    pub unsafe fn sock_getpeeraddr(
+        fd: u32,
+        addr: *mut WasiAddress,
+        addr_type: *mut u32,
+        port: *mut u32,
+    ) -> u32 {
+        let reply = record(Call::SockGetPeerAddr { fd }, Import::SockGetPeerAddr);
+        write_address(Import::SockGetPeerAddr, reply, addr, addr_type, port)
+    }
+
+    pub unsafe fn sock_getlocaladdr(
+        fd: u32,
+        addr: *mut WasiAddress,
+        addr_type: *mut u32,
+        port: *mut u32,
+    ) -> u32 {
+        let reply = record(Call::SockGetLocalAddr { fd }, Import::SockGetLocalAddr);
+        write_address(Import::SockGetLocalAddr, reply, addr, addr_type, port)
+    }
+
+    pub unsafe fn sock_getsockopt(
+        fd: u32,
+        level: i32,
+        name: i32,
+        flag: *mut i32,
+        flag_size: *mut u32,
+    ) -> u32 {
+        let len = *flag_size;
+        let call = Call::SockGetSockOpt {
+            fd,
+            level,
+            name,
+            len,
+        };
+        let value = match record(call, Import::SockGetSockOpt) {
+            Reply::Ok => vec![0; len as usize],
+            Reply::Opt(value) => value,
+            Reply::Errno(e) => return e as u32,
+            r => unexpected(Import::SockGetSockOpt, r),
+        };
+        let n = value.len().min(len as usize);
+        std::ptr::copy_nonoverlapping(value.as_ptr(), flag as *mut u8, n);
+        *flag_size = n as u32;
+        0
+    }
+
+    pub unsafe fn sock_setsockopt(
+        fd: u32,
+        level: i32,
+        name: i32,
+        flag: *const i32,
+        flag_size: u32,
+    ) -> u32 {
+        let value = if flag.is_null() {
+            vec![]
+        } else {
+            std::slice::from_raw_parts(flag as *const u8, flag_size as usize).to_vec()
+        };
+        let call = Call::SockSetSockOpt {
+            fd,
+            level,
+            name,
+            value,
+        };
+        match record(call, Import::SockSetSockOpt) {
+            Reply::Ok => 0,
+            Reply::Errno(e) => e as u32,
+            r => unexpected(Import::SockSetSockOpt, r),
+        }
+    }
+
+    unsafe fn c_str(s: *const u8, len: u32) -> String {
+        let s = std::slice::from_raw_parts(s, len as usize);
+        let s = s.strip_suffix(&[0]).unwrap_or(s);
+        String::from_utf8_lossy(s).into_owned()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn sock_getaddrinfo(
+        node: *const u8,
+        node_len: u32,
+        server: *const u8,
+        server_len: u32,
+        hint: *const WasiAddrinfo,
+        res: *mut *mut WasiAddrinfo,
+        max_len: u32,
+        res_len: *mut u32,
+    ) -> u32 {
+        let call = Call::SockGetAddrInfo {
+            node: c_str(node, node_len),
+            service: c_str(server, server_len),
+            flags: (addr_of!((*hint).ai_flags) as *const u16).read_unaligned(),
+            family: (addr_of!((*hint).ai_family) as *const u8).read_unaligned(),
+            socktype: (addr_of!((*hint).ai_socktype) as *const u8).read_unaligned(),
+            protocol: (addr_of!((*hint).ai_protocol) as *const u8).read_unaligned(),
+            max_len,
+        };
+        let addrs = match record(call, Import::SockGetAddrInfo) {
+            Reply::Ok => vec![],
+            Reply::AddrInfo(addrs) => addrs,
+            Reply::Errno(e) => return e as u32,
+            r => unexpected(Import::SockGetAddrInfo, r),
+        };
+
+        let mut count = 0;
+        let mut entry = *res;
+        for addr in addrs.iter().take(max_len as usize) {
+            if entry.is_null() {
+                break;
+            }
+            // sa_data is laid out like a native sockaddr without its family.
+            let (family, mut sa_data) = match addr {
+                SocketAddr::V4(a) => {
+                    let mut d = a.port().to_be_bytes().to_vec();
+                    d.extend_from_slice(&a.ip().octets());
+                    (AddressFamily::Inet4, d)
+                }
+                SocketAddr::V6(a) => {
+                    let mut d = a.port().to_be_bytes().to_vec();
+                    d.extend_from_slice(&a.flowinfo().to_le_bytes());
+                    d.extend_from_slice(&a.ip().octets());
+                    d.extend_from_slice(&a.scope_id().to_le_bytes());
+                    (AddressFamily::Inet6, d)
+                }
+            };
+            addr_of_mut!((*entry).ai_family).write_unaligned(family);
+            addr_of_mut!((*entry).ai_addrlen).write_unaligned(sa_data.len() as u32 + 2);
+            let sockaddr = addr_of!((*entry).ai_addr).read_unaligned();
+            if !sockaddr.is_null() {
+                let sockaddr = &mut *sockaddr;
+                sockaddr.family = family;
+                sa_data.truncate(sockaddr.sa_data_len as usize);
+                std::ptr::copy_nonoverlapping(sa_data.as_ptr(), sockaddr.sa_data, sa_data.len());
+            }
+            count += 1;
+            entry = addr_of!((*entry).ai_next).read_unaligned();
+        }
+        *res_len = count;
+        0
+    }
+
+    pub unsafe fn fd_fdstat_get(fd: u32, stat: *mut Fdstat) -> u32 {
+        let flags = match record(Call::FdFdstatGet { fd }, Import::FdFdstatGet) {
+            Reply::Ok => HOST.with(|h| h.borrow().fd_flags.get(&fd).copied().unwrap_or(0)),
+            Reply::Flags(flags) => flags,
+            Reply::Errno(e) => return e as u32,
+            r => unexpected(Import::FdFdstatGet, r),
+        };
+        *stat = Fdstat::default();
+        (*stat).fs_flags = flags;
+        0
+    }
+
+    pub unsafe fn fd_fdstat_set_flags(fd: u32, flags: u16) -> u32 {
+        match record(
+            Call::FdFdstatSetFlags { fd, flags },
+            Import::FdFdstatSetFlags,
+        ) {
+            Reply::Ok => {
+                HOST.with(|h| h.borrow_mut().fd_flags.insert(fd, flags));
+                0
+            }
+            Reply::Errno(e) => e as u32,
+            r => unexpected(Import::FdFdstatSetFlags, r),
+        }
+    }
+
+    pub unsafe fn fd_close(fd: u32) -> u32 {
+        match record(Call::FdClose { fd }, Import::FdClose) {
+            Reply::Ok => {
+                HOST.with(|h| h.borrow_mut().fd_flags.remove(&fd));
+                0
+            }
+            Reply::Errno(e) => e as u32,
+            r => unexpected(Import::FdClose, r),
+        }
+    }
+
+    pub unsafe fn poll_oneoff(
+        in_: *const Subscription,
+        out: *mut Event,
+        nsubscriptions: usize,
+        nevents: *mut usize,
+    ) -> i32 {
+        let subs = std::slice::from_raw_parts(in_, nsubscriptions);
+        let subscriptions = subs
+            .iter()
+            .map(|s| match s.u.tag {
+                EVENTTYPE_CLOCK => PollSubscription::Clock {
+                    userdata: s.userdata,
+                    timeout: s.u.u.clock.timeout,
+                    flags: s.u.u.clock.flags,
+                },
+                EVENTTYPE_FD_READ => PollSubscription::FdRead {
+                    userdata: s.userdata,
+                    fd: s.u.u.fd_read.file_descriptor,
+                },
+                _ => PollSubscription::FdWrite {
+                    userdata: s.userdata,
+                    fd: s.u.u.fd_write.file_descriptor,
+                },
+            })
+            .collect::<Vec<_>>();
+
+        let events = match record(
+            Call::PollOneoff {
+                subscriptions: subscriptions.clone(),
+            },
+            Import::PollOneoff,
+        ) {
+            Reply::Ok => {
+                // every fd is ready; clocks only fire when nothing else does
+                let mut ready = subscriptions
+                    .iter()
+                    .filter_map(|s| match *s {
+                        PollSubscription::FdRead { userdata, .. } => {
+                            Some((userdata, EVENTTYPE_FD_READ))
+                        }
+                        PollSubscription::FdWrite { userdata, .. } => {
+                            Some((userdata, EVENTTYPE_FD_WRITE))
+                        }
+                        PollSubscription::Clock { .. } => None,
+                    })
+                    .collect::<Vec<_>>();
+                if ready.is_empty() {
+                    ready = subscriptions
+                        .iter()
+                        .filter_map(|s| match *s {
+                            PollSubscription::Clock { userdata, .. } => {
+                                Some((userdata, EVENTTYPE_CLOCK))
+                            }
+                            _ => None,
+                        })
+                        .collect();
+                }
+                ready
+                    .into_iter()
+                    .map(|(userdata, type_)| PollEvent {
+                        userdata,
+                        type_,
+                        error: 0,
+                        hangup: false,
+                    })
+                    .collect()
+            }
+            Reply::Events(events) => events,
+            Reply::Errno(e) => return e as i32,
+            r => unexpected(Import::PollOneoff, r),
+        };
+
+        let mut n = 0;
+        for e in events.into_iter().take(nsubscriptions) {
+            let mut event = Event::empty();
+            event.userdata = e.userdata;
+            event.type_ = e.type_;
+            event.error = e.error;
+            event.fd_readwrite = EventFdReadwrite {
+                nbytes: 0,
+                flags: if e.hangup {
+                    EVENTRWFLAGS_FD_READWRITE_HANGUP
+                } else {
+                    0
+                },
+            };
+            *out.add(n) = event;
+            n += 1;
+        }
+        *nevents = n;
+        0
+    }
+}
@@ -0,0 +1,857 @@
+//! Native backend for targets other than `wasm32-wasi`.
+//!
+//! Implements the `wasi_snapshot_preview1` socket and poll functions used by
+//! this crate on top of libc sockets and `poll(2)`, so that the same code can
+//! be built and tested with a plain `cargo test` on Linux. Every function
+//! keeps the signature of the WasmEdge import it replaces and returns `0` on
+//! success or a WASI errno value on failure, translating the host's errno
+//! values so that errors look the same as on WasmEdge.
+
+use crate::errno::WasiErrno;
+use crate::socket::{
+    AddressFamily, Fdstat, IovecRead, IovecWrite, SocketOptLevel, SocketOptName, SocketType,
+    WasiAddress, WasiAddrinfo, WasiSockaddr, FDFLAGS_NONBLOCK,
+};
+use crate::wasi_poll::{
+    Clockid, Event, EventFdReadwrite, Subscription, SubscriptionClock, CLOCKID_MONOTONIC,
+    CLOCKID_PROCESS_CPUTIME_ID, CLOCKID_REALTIME, CLOCKID_THREAD_CPUTIME_ID,
+    EVENTRWFLAGS_FD_READWRITE_HANGUP, EVENTTYPE_CLOCK, EVENTTYPE_FD_READ, EVENTTYPE_FD_WRITE,
+    SUBCLOCKFLAGS_SUBSCRIPTION_CLOCK_ABSTIME,
+};
+use std::mem::{size_of, zeroed};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::ptr::{addr_of, addr_of_mut};
+
+const INET4: u8 = AddressFamily::Inet4 as u8;
+const INET6: u8 = AddressFamily::Inet6 as u8;
+
+const RIFLAGS_RECV_PEEK: u16 = 1;
+const RIFLAGS_RECV_WAITALL: u16 = 2;
+const ROFLAGS_RECV_DATA_TRUNCATED: usize = 1;
+
+const SDFLAGS_RD: u8 = 1;
+const SDFLAGS_WR: u8 = 2;
+
+fn errno() -> u32 {
+    let code = std::io::Error::last_os_error()
+        .raw_os_error()
+        .unwrap_or(libc::EIO);
+    wasi_errno(code) as u32
+}
+
+fn wasi_errno(code: libc::c_int) -> WasiErrno {
+    match code {
+        0 => WasiErrno::Success,
+        libc::E2BIG => WasiErrno::Toobig,
+        libc::EACCES => WasiErrno::Acces,
+        libc::EADDRINUSE => WasiErrno::Addrinuse,
+        libc::EADDRNOTAVAIL => WasiErrno::Addrnotavail,
+        libc::EAFNOSUPPORT => WasiErrno::Afnosupport,
+        libc::EAGAIN => WasiErrno::Again,
+        libc::EALREADY => WasiErrno::Already,
+        libc::EBADF => WasiErrno::Badf,
+        libc::EBADMSG => WasiErrno::Badmsg,
+        libc::EBUSY => WasiErrno::Busy,
+        libc::ECANCELED => WasiErrno::Canceled,
+        libc::ECHILD => WasiErrno::Child,
+        libc::ECONNABORTED => WasiErrno::Connaborted,
+        libc::ECONNREFUSED => WasiErrno::Connrefused,
+        libc::ECONNRESET => WasiErrno::Connreset,
+        libc::EDEADLK => WasiErrno::Deadlk,
+        libc::EDESTADDRREQ => WasiErrno::Destaddrreq,
+        libc::EDOM => WasiErrno::Dom,
+        libc::EDQUOT => WasiErrno::Dquot,
+        libc::EEXIST => WasiErrno::Exist,
+        libc::EFAULT => WasiErrno::Fault,
+        libc::EFBIG => WasiErrno::Fbig,
+        libc::EHOSTUNREACH => WasiErrno::Hostunreach,
+        libc::EIDRM => WasiErrno::Idrm,
+        libc::EILSEQ => WasiErrno::Ilseq,
+        libc::EINPROGRESS => WasiErrno::Inprogress,
+        libc::EINTR => WasiErrno::Intr,
+        libc::EINVAL => WasiErrno::Inval,
+        libc::EIO => WasiErrno::Io,
+        libc::EISCONN => WasiErrno::Isconn,
+        libc::EISDIR => WasiErrno::Isdir,
+        libc::ELOOP => WasiErrno::Loop,
+        libc::EMFILE => WasiErrno::Mfile,
+        libc::EMLINK => WasiErrno::Mlink,
+        libc::EMSGSIZE => WasiErrno::Msgsize,
+        libc::EMULTIHOP => WasiErrno::Multihop,
+        libc::ENAMETOOLONG => WasiErrno::Nametoolong,
+        libc::ENETDOWN => WasiErrno::Netdown,
+        libc::ENETRESET => WasiErrno::Netreset,
+        libc::ENETUNREACH => WasiErrno::Netunreach,
+        libc::ENFILE => WasiErrno::Nfile,
+        libc::ENOBUFS => WasiErrno::Nobufs,
+        libc::ENODEV => WasiErrno::Nodev,
+        libc::ENOENT => WasiErrno::Noent,
+        libc::ENOEXEC => WasiErrno::Noexec,
+        libc::ENOLCK => WasiErrno::Nolck,
+        libc::ENOLINK => WasiErrno::Nolink,
+        libc::ENOMEM => WasiErrno::Nomem,
+        libc::ENOMSG => WasiErrno::Nomsg,
+        libc::ENOPROTOOPT => WasiErrno::Noprotoopt,
+        libc::ENOSPC => WasiErrno::Nospc,
+        libc::ENOSYS => WasiErrno::Nosys,
+        libc::ENOTCONN => WasiErrno::Notconn,
+        libc::ENOTDIR => WasiErrno::Notdir,
+        libc::ENOTEMPTY => WasiErrno::Notempty,
+        libc::ENOTRECOVERABLE => WasiErrno::Notrecoverable,
+        libc::ENOTSOCK => WasiErrno::Notsock,
+        libc::ENOTSUP => WasiErrno::Notsup,
+        libc::ENOTTY => WasiErrno::Notty,
+        libc::ENXIO => WasiErrno::Nxio,
+        libc::EOVERFLOW => WasiErrno::Overflow,
+        libc::EOWNERDEAD => WasiErrno::Ownerdead,
+        libc::EPERM => WasiErrno::Perm,
+        libc::EPIPE => WasiErrno::Pipe,
+        libc::EPROTO => WasiErrno::Proto,
+        libc::EPROTONOSUPPORT => WasiErrno::Protonosupport,
+        libc::EPROTOTYPE => WasiErrno::Prototype,
+        libc::ERANGE => WasiErrno::Range,
+        libc::EROFS => WasiErrno::Rofs,
+        libc::ESPIPE => WasiErrno::Spipe,
+        libc::ESRCH => WasiErrno::Srch,
+        libc::ESTALE => WasiErrno::Stale,
+        libc::ETIMEDOUT => WasiErrno::Timedout,
+        libc::ETXTBSY => WasiErrno::Txtbsy,
+        libc::EXDEV => WasiErrno::Xdev,
+        _ => WasiErrno::Io,
+    }
+}
+
+macro_rules! check {
+    ($e: expr) => {{
+        let res = $e;
+        if res == -1 {
+            return errno();
+        }
+        res
+    }};
+}
+
+/// Convert the address buffer and port passed to `sock_bind`, `sock_connect`
+/// and `sock_send_to` into a native socket address.
+unsafe fn to_sockaddr(
+    addr: &WasiAddress,
+    port: u32,
+) -> Result<(libc::sockaddr_storage, libc::socklen_t), u32> {
+    let buf = std::slice::from_raw_parts(addr.buf, addr.size);
+    let sockaddr = match addr.size {
+        4 => SocketAddr::from((Ipv4Addr::new(buf[0], buf[1], buf[2], buf[3]), port as u16)),
+        16 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(buf);
+            SocketAddr::from((Ipv6Addr::from(octets), port as u16))
+        }
+        128 => match u16::from_le_bytes([buf[0], buf[1]]) as u8 {
+            INET4 => SocketAddr::from((Ipv4Addr::new(buf[2], buf[3], buf[4], buf[5]), port as u16)),
+            INET6 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(&buf[2..18]);
+                let flowinfo = u32::from_le_bytes([buf[18], buf[19], buf[20], buf[21]]);
+                let scope_id = u32::from_le_bytes([buf[22], buf[23], buf[24], buf[25]]);
+                SocketAddr::V6(SocketAddrV6::new(
+                    Ipv6Addr::from(octets),
+                    port as u16,
+                    flowinfo,
+                    scope_id,
+                ))
+            }
+            _ => return Err(WasiErrno::Afnosupport as u32),
+        },
+        _ => return Err(WasiErrno::Inval as u32),
+    };
+
+    let mut storage: libc::sockaddr_storage = zeroed();
+    let len = match sockaddr {
+        SocketAddr::V4(addr) => {
+            let sin = &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in);
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = addr.port().to_be();
+            sin.sin_addr.s_addr = u32::from_ne_bytes(addr.ip().octets());
+            size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            let sin6 = &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6);
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = addr.port().to_be();
+            sin6.sin6_flowinfo = addr.flowinfo();
+            sin6.sin6_addr.s6_addr = addr.ip().octets();
+            sin6.sin6_scope_id = addr.scope_id();
+            size_of::<libc::sockaddr_in6>()
+        }
+    };
+    Ok((storage, len as libc::socklen_t))
+}
+
+unsafe fn from_sockaddr(storage: &libc::sockaddr_storage) -> Option<SocketAddr> {
+    match storage.ss_family as i32 {
+        libc::AF_INET => {
+            let sin = &*(storage as *const _ as *const libc::sockaddr_in);
+            let ip = Ipv4Addr::from(sin.sin_addr.s_addr.to_ne_bytes());
+            Some(SocketAddr::V4(SocketAddrV4::new(
+                ip,
+                u16::from_be(sin.sin_port),
+            )))
+        }
+        libc::AF_INET6 => {
+            let sin6 = &*(storage as *const _ as *const libc::sockaddr_in6);
+            Some(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(sin6.sin6_addr.s6_addr),
+                u16::from_be(sin6.sin6_port),
+                sin6.sin6_flowinfo,
+                sin6.sin6_scope_id,
+            )))
+        }
+        _ => None,
+    }
+}
+
+/// Write the raw octets of `sockaddr`, as returned by `sock_getlocaladdr`
+/// and `sock_getpeeraddr`.
+unsafe fn write_octets(
+    sockaddr: SocketAddr,
+    addr: *mut WasiAddress,
+    addr_type: *mut u32,
+    port: *mut u32,
+) -> u32 {
+    let addr = &*addr;
+    let out = std::slice::from_raw_parts_mut(addr.buf as *mut u8, addr.size);
+    match sockaddr {
+        SocketAddr::V4(a) if out.len() >= 4 => {
+            out[0..4].copy_from_slice(&a.ip().octets());
+            *addr_type = 4;
+        }
+        SocketAddr::V6(a) if out.len() >= 16 => {
+            out[0..16].copy_from_slice(&a.ip().octets());
+            *addr_type = 6;
+        }
+        _ => return WasiErrno::Nobufs as u32,
+    }
+    *port = sockaddr.port() as u32;
+    0
+}
+
+/// Map a WASI socket option to its native level and name.
+fn sockopt(level: i32, name: i32) -> Option<(libc::c_int, libc::c_int)> {
+    let name = SocketOptName::try_from(name).ok()?;
+    let level = match SocketOptLevel::try_from(level).ok()? {
+        level if level != name.level() => return None,
+        SocketOptLevel::SolSocket => libc::SOL_SOCKET,
+        SocketOptLevel::IpProtoIp => libc::IPPROTO_IP,
+        SocketOptLevel::IpProtoIpv6 => libc::IPPROTO_IPV6,
+        SocketOptLevel::IpProtoTcp => libc::IPPROTO_TCP,
+    };
+    let name = match name {
+        SocketOptName::SoReuseaddr => libc::SO_REUSEADDR,
+        SocketOptName::SoType => libc::SO_TYPE,
+        SocketOptName::SoError => libc::SO_ERROR,
+        SocketOptName::SoDontroute => libc::SO_DONTROUTE,
+        SocketOptName::SoBroadcast => libc::SO_BROADCAST,
+        SocketOptName::SoSndbuf => libc::SO_SNDBUF,
+        SocketOptName::SoRcvbuf => libc::SO_RCVBUF,
+        SocketOptName::SoKeepalive => libc::SO_KEEPALIVE,
+        SocketOptName::SoOobinline => libc::SO_OOBINLINE,
+        SocketOptName::SoLinger => libc::SO_LINGER,
+        SocketOptName::SoRcvlowat => libc::SO_RCVLOWAT,
+        SocketOptName::SoRcvtimeo => libc::SO_RCVTIMEO,
+        SocketOptName::SoSndtimeo => libc::SO_SNDTIMEO,
+        SocketOptName::SoAcceptconn => libc::SO_ACCEPTCONN,
+        SocketOptName::SoBindToDevice => libc::SO_BINDTODEVICE,
+        SocketOptName::IpAddMembership => libc::IP_ADD_MEMBERSHIP,
+        SocketOptName::IpDropMembership => libc::IP_DROP_MEMBERSHIP,
+        SocketOptName::IpMulticastTtl => libc::IP_MULTICAST_TTL,
+        SocketOptName::IpMulticastLoop => libc::IP_MULTICAST_LOOP,
+        SocketOptName::Ipv6JoinGroup => libc::IPV6_ADD_MEMBERSHIP,
+        SocketOptName::Ipv6LeaveGroup => libc::IPV6_DROP_MEMBERSHIP,
+        SocketOptName::Ipv6MulticastHops => libc::IPV6_MULTICAST_HOPS,
+        SocketOptName::Ipv6MulticastLoop => libc::IPV6_MULTICAST_LOOP,
+        SocketOptName::IpTtl => libc::IP_TTL,
+        SocketOptName::IpTos => libc::IP_TOS,
+        SocketOptName::Ipv6V6only => libc::IPV6_V6ONLY,
+        SocketOptName::Ipv6UnicastHops => libc::IPV6_UNICAST_HOPS,
+        SocketOptName::TcpNodelay => libc::TCP_NODELAY,
+        SocketOptName::TcpKeepidle => libc::TCP_KEEPIDLE,
+        SocketOptName::TcpKeepintvl => libc::TCP_KEEPINTVL,
+        SocketOptName::TcpKeepcnt => libc::TCP_KEEPCNT,
+    };
+    Some((level, name))
+}
+
+fn recv_flags(flags: u16) -> libc::c_int {
+    let mut native = 0;
+    if flags & RIFLAGS_RECV_PEEK != 0 {
+        native |= libc::MSG_PEEK;
+    }
+    if flags & RIFLAGS_RECV_WAITALL != 0 {
+        native |= libc::MSG_WAITALL;
+    }
+    native
+}
+
+fn recv_oflags(msg_flags: libc::c_int) -> usize {
+    if msg_flags & libc::MSG_TRUNC != 0 {
+        ROFLAGS_RECV_DATA_TRUNCATED
+    } else {
+        0
+    }
+}
+
+pub unsafe fn sock_open(addr_family: u8, sock_type: u8, fd: *mut u32) -> u32 {
+    let domain = match addr_family {
+        INET4 => libc::AF_INET,
+        INET6 => libc::AF_INET6,
+        _ => return WasiErrno::Afnosupport as u32,
+    };
+    let ty = match sock_type {
+        t if t == SocketType::Datagram as u8 => libc::SOCK_DGRAM,
+        t if t == SocketType::Stream as u8 => libc::SOCK_STREAM,
+        _ => return WasiErrno::Prototype as u32,
+    };
+    let s = check!(libc::socket(domain, ty | libc::SOCK_CLOEXEC, 0));
+    *fd = s as u32;
+    0
+}
+
+pub unsafe fn sock_bind(fd: u32, addr: *mut WasiAddress, port: u32) -> u32 {
+    let (storage, len) = match to_sockaddr(&*addr, port) {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
+    check!(libc::bind(
+        fd as i32,
+        &storage as *const _ as *const libc::sockaddr,
+        len
+    ));
+    0
+}
+
+pub unsafe fn sock_listen(fd: u32, backlog: u32) -> u32 {
+    check!(libc::listen(fd as i32, backlog as i32));
+    0
+}
+
+pub unsafe fn sock_accept(fd: u32, new_fd: *mut u32) -> u32 {
+    let s = check!(libc::accept4(
+        fd as i32,
+        std::ptr::null_mut(),
+        std::ptr::null_mut(),
+        libc::SOCK_CLOEXEC,
+    ));
+    *new_fd = s as u32;
+    0
+}
+
+pub unsafe fn sock_connect(fd: u32, addr: *mut WasiAddress, port: u32) -> u32 {
+    let (storage, len) = match to_sockaddr(&*addr, port) {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
+    check!(libc::connect(
+        fd as i32,
+        &storage as *const _ as *const libc::sockaddr,
+        len
+    ));
+    0
+}
+
+pub unsafe fn sock_recv(
+    fd: u32,
+    buf: *mut IovecRead,
+    buf_len: usize,
+    flags: u16,
+    recv_len: *mut usize,
+    oflags: *mut usize,
+) -> u32 {
+    let mut msg: libc::msghdr = zeroed();
+    msg.msg_iov = buf.cast();
+    msg.msg_iovlen = buf_len as _;
+    let n = check!(libc::recvmsg(fd as i32, &mut msg, recv_flags(flags)));
+    *recv_len = n as usize;
+    *oflags = recv_oflags(msg.msg_flags);
+    0
+}
+
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn sock_recv_from(
+    fd: u32,
+    buf: *mut IovecRead,
+    buf_len: u32,
+    addr: *mut u8,
+    flags: u16,
+    port: *mut u32,
+    recv_len: *mut usize,
+    oflags: *mut usize,
+) -> u32 {
+    let mut storage: libc::sockaddr_storage = zeroed();
+    let mut msg: libc::msghdr = zeroed();
+    msg.msg_name = &mut storage as *mut _ as *mut libc::c_void;
+    msg.msg_namelen = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    msg.msg_iov = buf.cast();
+    msg.msg_iovlen = buf_len as _;
+    let n = check!(libc::recvmsg(fd as i32, &mut msg, recv_flags(flags)));
+
+    // The peer is reported in the 128 byte form: family, the octets, then
+    // for IPv6 the flow info and the scope id.
+    let addr = &*(addr as *const WasiAddress);
+    let out = std::slice::from_raw_parts_mut(addr.buf as *mut u8, addr.size);
+    match from_sockaddr(&storage) {
+        Some(SocketAddr::V4(a)) if out.len() >= 6 => {
+            out[0..2].copy_from_slice(&(INET4 as u16).to_le_bytes());
+            out[2..6].copy_from_slice(&a.ip().octets());
+            *port = a.port() as u32;
+        }
+        Some(SocketAddr::V6(a)) if out.len() >= 26 => {
+            out[0..2].copy_from_slice(&(INET6 as u16).to_le_bytes());
+            out[2..18].copy_from_slice(&a.ip().octets());
+            out[18..22].copy_from_slice(&a.flowinfo().to_le_bytes());
+            out[22..26].copy_from_slice(&a.scope_id().to_le_bytes());
+            *port = a.port() as u32;
+        }
+        _ => return WasiErrno::Afnosupport as u32,
+    }
+    *recv_len = n as usize;
+    *oflags = recv_oflags(msg.msg_flags);
+    0
+}
+
+pub unsafe fn sock_send(
+    fd: u32,
+    buf: *const IovecWrite,
+    buf_len: u32,
+    _flags: u16,
+    send_len: *mut u32,
+) -> u32 {
+    let mut msg: libc::msghdr = zeroed();
+    msg.msg_iov = buf as *mut libc::iovec;
+    msg.msg_iovlen = buf_len as _;
+    let n = check!(libc::sendmsg(fd as i32, &msg, libc::MSG_NOSIGNAL));
+    *send_len = n as u32;
+    0
+}
+
+pub unsafe fn sock_send_to(
+    fd: u32,
+    buf: *const IovecWrite,
+    buf_len: u32,
+    addr: *const u8,
+    port: u32,
+    _flags: u16,
+    send_len: *mut u32,
+) -> u32 {
+    let (mut storage, len) = match to_sockaddr(&*(addr as *const WasiAddress), port) {
+        Ok(v) => v,
+        Err(e) => return e,
+    };
+    let mut msg: libc::msghdr = zeroed();
+    msg.msg_name = &mut storage as *mut _ as *mut libc::c_void;
+    msg.msg_namelen = len;
+    msg.msg_iov = buf as *mut libc::iovec;
+    msg.msg_iovlen = buf_len as _;
+    let n = check!(libc::sendmsg(fd as i32, &msg, libc::MSG_NOSIGNAL));
+    *send_len = n as u32;
+    0
+}
+
+pub unsafe fn sock_shutdown(fd: u32, flags: u8) -> u32 {
+    let how = match flags {
+        SDFLAGS_RD => libc::SHUT_RD,
+        SDFLAGS_WR => libc::SHUT_WR,
+        f if f == SDFLAGS_RD | SDFLAGS_WR => libc::SHUT_RDWR,
+        _ => return WasiErrno::Inval as u32,
+    };
+    check!(libc::shutdown(fd as i32, how));
+    0
+}
+
+pub unsafe fn sock_getpeeraddr(
+    fd: u32,
+    addr: *mut WasiAddress,
+    addr_type: *mut u32,
+    port: *mut u32,
+) -> u32 {
+    let mut storage: libc::sockaddr_storage = zeroed();
+    let mut len = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    check!(libc::getpeername(
+        fd as i32,
+        &mut storage as *mut _ as *mut libc::sockaddr,
+        &mut len
+    ));
+    match from_sockaddr(&storage) {
+        Some(sockaddr) => write_octets(sockaddr, addr, addr_type, port),
+        None => WasiErrno::Afnosupport as u32,
+    }
+}
+
+pub unsafe fn sock_getlocaladdr(
+    fd: u32,
+    addr: *mut WasiAddress,
+    addr_type: *mut u32,
+    port: *mut u32,
+) -> u32 {
+    let mut storage: libc::sockaddr_storage = zeroed();
+    let mut len = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    check!(libc::getsockname(
+        fd as i32,
+        &mut storage as *mut _ as *mut libc::sockaddr,
+        &mut len
+    ));
+    match from_sockaddr(&storage) {
+        Some(sockaddr) => write_octets(sockaddr, addr, addr_type, port),
+        None => WasiErrno::Afnosupport as u32,
+    }
+}
+
+pub unsafe fn sock_getsockopt(
+    fd: u32,
+    level: i32,
+    name: i32,
+    flag: *mut i32,
+    flag_size: *mut u32,
+) -> u32 {
+    let (level, name) = match sockopt(level, name) {
+        Some(v) => v,
+        None => return WasiErrno::Noprotoopt as u32,
+    };
+    let mut len = *flag_size as libc::socklen_t;
+    check!(libc::getsockopt(
+        fd as i32,
+        level,
+        name,
+        flag.cast(),
+        &mut len
+    ));
+    *flag_size = len as u32;
+    if level == libc::SOL_SOCKET && name == libc::SO_TYPE {
+        *flag = match *flag {
+            libc::SOCK_DGRAM => SocketType::Datagram as i32,
+            libc::SOCK_STREAM => SocketType::Stream as i32,
+            _ => SocketType::Any as i32,
+        };
+    }
+    if level == libc::SOL_SOCKET && name == libc::SO_ERROR {
+        *flag = wasi_errno(*flag) as i32;
+    }
+    0
+}
+
+pub unsafe fn sock_setsockopt(
+    fd: u32,
+    level: i32,
+    name: i32,
+    flag: *const i32,
+    flag_size: u32,
+) -> u32 {
+    let (level, name) = match sockopt(level, name) {
+        Some(v) => v,
+        None => return WasiErrno::Noprotoopt as u32,
+    };
+    check!(libc::setsockopt(
+        fd as i32,
+        level,
+        name,
+        flag.cast(),
+        flag_size as libc::socklen_t
+    ));
+    0
+}
+
+pub unsafe fn fd_fdstat_get(fd: u32, stat: *mut Fdstat) -> u32 {
+    let flags = check!(libc::fcntl(fd as i32, libc::F_GETFL));
+    *stat = Fdstat::default();
+    if flags & libc::O_NONBLOCK != 0 {
+        (*stat).fs_flags |= FDFLAGS_NONBLOCK;
+    }
+    0
+}
+
+pub unsafe fn fd_fdstat_set_flags(fd: u32, flags: u16) -> u32 {
+    let previous = check!(libc::fcntl(fd as i32, libc::F_GETFL));
+    let new = if flags & FDFLAGS_NONBLOCK != 0 {
+        previous | libc::O_NONBLOCK
+    } else {
+        previous & !libc::O_NONBLOCK
+    };
+    check!(libc::fcntl(fd as i32, libc::F_SETFL, new));
+    0
+}
+
+pub unsafe fn fd_close(fd: u32) -> u32 {
+    check!(libc::close(fd as i32));
+    0
+}
+
+/// getaddrinfo failures, numbered like WasmEdge's `__WASI_ERRNO_AI*` values.
+fn eai_errno(code: libc::c_int) -> u32 {
+    let errno = match code {
+        libc::EAI_AGAIN => WasiErrno::AiAgain,
+        libc::EAI_BADFLAGS => WasiErrno::AiBadflag,
+        libc::EAI_FAIL => WasiErrno::AiFail,
+        libc::EAI_FAMILY => WasiErrno::AiFamily,
+        libc::EAI_MEMORY => WasiErrno::AiMemory,
+        libc::EAI_NODATA => WasiErrno::AiNodata,
+        libc::EAI_NONAME => WasiErrno::AiNoname,
+        libc::EAI_SERVICE => WasiErrno::AiService,
+        libc::EAI_SOCKTYPE => WasiErrno::AiSocktype,
+        _ => WasiErrno::AiSystem,
+    };
+    errno as u32
+}
+
+/// getaddrinfo hint flags, in the bit order of WasmEdge's `__wasi_aiflags_t`.
+const AI_FLAGS: [libc::c_int; 7] = [
+    libc::AI_PASSIVE,
+    libc::AI_CANONNAME,
+    libc::AI_NUMERICHOST,
+    libc::AI_NUMERICSERV,
+    libc::AI_V4MAPPED,
+    libc::AI_ALL,
+    libc::AI_ADDRCONFIG,
+];
+
+fn c_str_arg(s: *const u8, len: u32) -> Vec<u8> {
+    let mut s = unsafe { std::slice::from_raw_parts(s, len as usize) }.to_vec();
+    if s.last() != Some(&0) {
+        s.push(0);
+    }
+    s
+}
+
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn sock_getaddrinfo(
+    node: *const u8,
+    node_len: u32,
+    server: *const u8,
+    server_len: u32,
+    hint: *const WasiAddrinfo,
+    res: *mut *mut WasiAddrinfo,
+    max_len: u32,
+    res_len: *mut u32,
+) -> u32 {
+    let node = c_str_arg(node, node_len);
+    let service = c_str_arg(server, server_len);
+
+    let mut hints: libc::addrinfo = zeroed();
+    let flags = (addr_of!((*hint).ai_flags) as *const u16).read_unaligned();
+    for (bit, flag) in AI_FLAGS.iter().enumerate() {
+        if flags & (1 << bit) != 0 {
+            hints.ai_flags |= flag;
+        }
+    }
+    hints.ai_family = match (addr_of!((*hint).ai_family) as *const u8).read_unaligned() {
+        INET4 => libc::AF_INET,
+        INET6 => libc::AF_INET6,
+        _ => libc::AF_UNSPEC,
+    };
+    hints.ai_socktype = match (addr_of!((*hint).ai_socktype) as *const u8).read_unaligned() {
+        t if t == SocketType::Datagram as u8 => libc::SOCK_DGRAM,
+        t if t == SocketType::Stream as u8 => libc::SOCK_STREAM,
+        _ => 0,
+    };
+    hints.ai_protocol = match (addr_of!((*hint).ai_protocol) as *const u8).read_unaligned() {
+        1 => libc::IPPROTO_TCP,
+        2 => libc::IPPROTO_UDP,
+        _ => 0,
+    };
+
+    let mut list: *mut libc::addrinfo = std::ptr::null_mut();
+    let code = libc::getaddrinfo(
+        if node.len() > 1 {
+            node.as_ptr().cast()
+        } else {
+            std::ptr::null()
+        },
+        if service.len() > 1 {
+            service.as_ptr().cast()
+        } else {
+            std::ptr::null()
+        },
+        &hints,
+        &mut list,
+    );
+    if code != 0 {
+        return eai_errno(code);
+    }
+
+    let mut count = 0;
+    let mut entry = *res;
+    let mut cur = list;
+    while !cur.is_null() && !entry.is_null() && count < max_len {
+        let ai = &*cur;
+        let family = match ai.ai_family {
+            libc::AF_INET => AddressFamily::Inet4,
+            libc::AF_INET6 => AddressFamily::Inet6,
+            _ => AddressFamily::Unspec,
+        };
+        let socktype = match ai.ai_socktype {
+            libc::SOCK_DGRAM => SocketType::Datagram,
+            libc::SOCK_STREAM => SocketType::Stream,
+            _ => SocketType::Any,
+        };
+        addr_of_mut!((*entry).ai_family).write_unaligned(family);
+        addr_of_mut!((*entry).ai_socktype).write_unaligned(socktype);
+        (addr_of_mut!((*entry).ai_protocol) as *mut u8).write_unaligned(match ai.ai_protocol {
+            libc::IPPROTO_TCP => 1,
+            libc::IPPROTO_UDP => 2,
+            _ => 0,
+        });
+        addr_of_mut!((*entry).ai_addrlen).write_unaligned(ai.ai_addrlen);
+
+        // sa_data is the native sockaddr without its leading family field.
+        let sockaddr: *mut WasiSockaddr = addr_of!((*entry).ai_addr).read_unaligned();
+        if !sockaddr.is_null() && !ai.ai_addr.is_null() {
+            let sockaddr = &mut *sockaddr;
+            sockaddr.family = family;
+            let src = (ai.ai_addr as *const u8).add(2);
+            let len = (ai.ai_addrlen as usize)
+                .saturating_sub(2)
+                .min(sockaddr.sa_data_len as usize);
+            std::ptr::copy_nonoverlapping(src, sockaddr.sa_data, len);
+        }
+
+        let canonname: *mut u8 = addr_of!((*entry).ai_canonname).read_unaligned();
+        let capacity = addr_of!((*entry).ai_canonnamelen).read_unaligned() as usize;
+        if !canonname.is_null() && !ai.ai_canonname.is_null() && capacity > 0 {
+            let name = std::ffi::CStr::from_ptr(ai.ai_canonname).to_bytes();
+            let len = name.len().min(capacity);
+            std::ptr::copy_nonoverlapping(name.as_ptr(), canonname, len);
+            addr_of_mut!((*entry).ai_canonnamelen).write_unaligned(len as u32);
+        }
+
+        count += 1;
+        entry = addr_of!((*entry).ai_next).read_unaligned();
+        cur = ai.ai_next;
+    }
+    libc::freeaddrinfo(list);
+    *res_len = count;
+    0
+}
+
+fn now_nanos() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// The current time of a WASI clock, in nanoseconds.
+fn clock_now(id: Clockid) -> u64 {
+    let native = match id {
+        CLOCKID_MONOTONIC => libc::CLOCK_MONOTONIC,
+        CLOCKID_PROCESS_CPUTIME_ID => libc::CLOCK_PROCESS_CPUTIME_ID,
+        CLOCKID_THREAD_CPUTIME_ID => libc::CLOCK_THREAD_CPUTIME_ID,
+        _ => return now_nanos(),
+    };
+    let mut ts: libc::timespec = unsafe { zeroed() };
+    if unsafe { libc::clock_gettime(native, &mut ts) } != 0 {
+        return now_nanos();
+    }
+    (ts.tv_sec as u64)
+        .saturating_mul(1_000_000_000)
+        .saturating_add(ts.tv_nsec as u64)
+}
+
+/// Absolute realtime deadline of a clock subscription, in nanoseconds. An
+/// absolute timeout on another clock is turned into the time left on that
+/// clock.
+fn clock_deadline(clock: &SubscriptionClock, start: u64) -> u64 {
+    if clock.flags & SUBCLOCKFLAGS_SUBSCRIPTION_CLOCK_ABSTIME == 0 {
+        start.saturating_add(clock.timeout)
+    } else if clock.id == CLOCKID_REALTIME {
+        clock.timeout
+    } else {
+        let left = clock.timeout.saturating_sub(clock_now(clock.id));
+        start.saturating_add(left)
+    }
+}
+
+/// `poll_oneoff` on top of `poll(2)`.
+///
+/// Clock subscriptions become the poll timeout and are only reported when
+/// no file descriptor is ready.
+pub unsafe fn poll_oneoff(
+    in_: *const Subscription,
+    out: *mut Event,
+    nsubscriptions: usize,
+    nevents: *mut usize,
+) -> i32 {
+    let subs = std::slice::from_raw_parts(in_, nsubscriptions);
+    let mut fds = vec![];
+    let mut fd_subs = vec![];
+    let mut deadline: Option<u64> = None;
+    let start = now_nanos();
+
+    for (i, s) in subs.iter().enumerate() {
+        match s.u.tag {
+            EVENTTYPE_CLOCK => {
+                let at = clock_deadline(&s.u.u.clock, start);
+                deadline = Some(deadline.map_or(at, |d| d.min(at)));
+            }
+            EVENTTYPE_FD_READ | EVENTTYPE_FD_WRITE => {
+                let events = if s.u.tag == EVENTTYPE_FD_READ {
+                    libc::POLLIN
+                } else {
+                    libc::POLLOUT
+                };
+                fds.push(libc::pollfd {
+                    fd: s.u.u.fd_read.file_descriptor as i32,
+                    events,
+                    revents: 0,
+                });
+                fd_subs.push(i);
+            }
+            _ => return WasiErrno::Inval as i32,
+        }
+    }
+
+    let timeout = match deadline {
+        // round up so that the clock has expired once poll returns
+        Some(at) => (at.saturating_sub(start).saturating_add(999_999) / 1_000_000)
+            .min(i32::MAX as u64) as i32,
+        None => -1,
+    };
+    if libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) == -1 {
+        return errno() as i32;
+    }
+
+    let mut n = 0;
+    for (pfd, &i) in fds.iter().zip(fd_subs.iter()) {
+        if pfd.revents == 0 {
+            continue;
+        }
+        let mut event = Event::empty();
+        event.userdata = subs[i].userdata;
+        event.type_ = subs[i].u.tag;
+        if pfd.revents & libc::POLLNVAL != 0 {
+            event.error = WasiErrno::Badf as u16;
+        }
+        event.fd_readwrite = EventFdReadwrite {
+            nbytes: 0,
+            flags: if pfd.revents & libc::POLLHUP != 0 {
+                EVENTRWFLAGS_FD_READWRITE_HANGUP
+            } else {
+                0
+            },
+        };
+        *out.add(n) = event;
+        n += 1;
+    }
+
+    if n == 0 {
+        let now = now_nanos();
+        for s in subs.iter().filter(|s| s.u.tag == EVENTTYPE_CLOCK) {
+            if clock_deadline(&s.u.u.clock, start) <= now {
+                let mut event = Event::empty();
+                event.userdata = s.userdata;
+                event.type_ = EVENTTYPE_CLOCK;
+                *out.add(n) = event;
+                n += 1;
+            }
+        }
+    }
+
+    *nevents = n;
+    0
+}
@@ -0,0 +1,566 @@
+//! Name resolution that does not block, driven by [`poll`].
+
+use crate::dns::{self, Answers, Response, Transport};
+use crate::poll::{poll, Subscription};
+use crate::resolver::{clone_error, Resolver};
+use crate::socket::{AddressFamily, AiProtocol, Socket, SocketType};
+use crate::{TcpStream, UdpSocket, WasiErrno};
+use dns_parser::QueryType;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::SystemTime;
+
+/// A lookup started by [`Resolver::start_lookup`].
+///
+/// The queries go out on a nonblocking UDP socket, and nothing waits for the
+/// answers. Add [`subscription`](Self::subscription) to the subscriptions of
+/// each [`poll`] call, and call [`try_wait`](Self::try_wait) when an event
+/// comes back with its userdata: the answers have arrived or the timeout has
+/// passed. The socket changes as the lookup moves from server to server, so
+/// ask for the subscription again before every poll.
+///
+/// Search domains, retries, server failover, CNAME chains and the cache work
+/// as they do for [`Resolver::lookup`]. A few things still block: a resolver
+/// without DNS servers asks the host, and a resolver whose transport is not
+/// [`Transport::Udp`] sends its queries over TCP, so `start_lookup` resolves
+/// the name before it returns.
+///
+/// Truncated answers are fetched again over a nonblocking TCP connection to
+/// the same server. While it is open, the subscription is for that
+/// connection, with the TCP timeout as its deadline.
+///
+/// ```ignore
+/// let mut lookup = resolver.start_lookup("db.internal", "5432")?;
+/// loop {
+///     let subs = vec![
+///         Subscription::io(LISTENER, &listener, true, false, None),
+///         lookup.subscription(LOOKUP),
+///     ];
+///     for event in poll(&subs)? {
+///         match event.userdata {
+///             LOOKUP => if let Some(addrs) = lookup.try_wait()? { ... },
+///             LISTENER => { ... }
+///         }
+///     }
+/// }
+/// ```
+pub struct PendingLookup {
+    resolver: Resolver,
+    port: u16,
+    candidates: Vec<String>,
+    candidate: usize,
+    // the error of the candidates tried so far
+    error: Option<io::Error>,
+    queries: Vec<Query>,
+    // the (attempt, server) pair the queries are sent to, counting from 0
+    step: usize,
+    socket: Option<UdpSocket>,
+    tcp: Option<TcpRetry>,
+    deadline: SystemTime,
+    result: Option<io::Result<Vec<SocketAddr>>>,
+}
+
+// A connection that truncated answers are fetched again over.
+struct TcpRetry {
+    stream: TcpStream,
+    // the framed queries that have yet to be written
+    out: Vec<u8>,
+    // what has been read of the answers
+    buf: Vec<u8>,
+}
+
+// The query for one record type of the candidate name.
+struct Query {
+    qtype: QueryType,
+    // the name asked for, which moves down the CNAME chain
+    name: String,
+    hops: usize,
+    id: u16,
+    packet: Vec<u8>,
+    // whether the server of this step has yet to answer
+    waiting: bool,
+    // whether the answer is expected over TCP
+    over_tcp: bool,
+    // the last answer that did not settle the question
+    response: Option<io::Result<Response>>,
+    found: Option<io::Result<Vec<IpAddr>>>,
+}
+
+impl PendingLookup {
+    pub(crate) fn start(resolver: &Resolver, node: &str, service: &str) -> io::Result<Self> {
+        let mut lookup = PendingLookup {
+            resolver: resolver.clone(),
+            port: 0,
+            candidates: vec![],
+            candidate: 0,
+            error: None,
+            queries: vec![],
+            step: 0,
+            socket: None,
+            tcp: None,
+            deadline: SystemTime::now(),
+            result: None,
+        };
+        if resolver.nameservers().is_empty()
+            || resolver.transport() != Transport::Udp
+            || resolver.pinned(node).is_some()
+            || node == "localhost"
+        {
+            lookup.result = Some(resolver.lookup(node, service));
+            return Ok(lookup);
+        }
+
+        lookup.port = resolver
+            .services()
+            .resolve(service, AiProtocol::IPProtoTCP)?;
+        if let Ok(ip) = node.parse::<IpAddr>() {
+            lookup.result = Some(Ok(vec![SocketAddr::new(ip, lookup.port)]));
+            return Ok(lookup);
+        }
+        lookup.candidates = resolver.candidates(node);
+        lookup.start_candidate();
+        lookup.advance();
+        Ok(lookup)
+    }
+
+    /// The subscription to wait for this lookup with. Once the lookup is
+    /// over, it is a timeout that has already passed.
+    pub fn subscription(&self, userdata: u64) -> Subscription {
+        if let (Some(tcp), None) = (&self.tcp, &self.result) {
+            let write = !tcp.out.is_empty();
+            return Subscription::io(userdata, &tcp.stream, true, write, Some(self.deadline));
+        }
+        match &self.socket {
+            Some(socket) if self.result.is_none() => {
+                Subscription::io(userdata, socket, true, false, Some(self.deadline))
+            }
+            _ => Subscription::timeout(userdata, SystemTime::now()),
+        }
+    }
+
+    /// When the lookup moves on if no more answers arrive.
+    pub fn deadline(&self) -> SystemTime {
+        self.deadline
+    }
+
+    /// Read the answers that have arrived and send the queries that are due.
+    /// Returns `Ok(None)` while the lookup goes on, and its result once it
+    /// is over, as often as it is called.
+    pub fn try_wait(&mut self) -> io::Result<Option<Vec<SocketAddr>>> {
+        if self.result.is_none() {
+            self.receive();
+            self.exchange_tcp();
+            let answered = self.queries.iter().all(|q| !q.waiting);
+            if answered || SystemTime::now() >= self.deadline {
+                // on to the next server
+                self.socket = None;
+                self.tcp = None;
+                self.step += 1;
+            }
+            self.advance();
+        }
+        match &self.result {
+            None => Ok(None),
+            Some(Ok(addrs)) => Ok(Some(addrs.clone())),
+            Some(Err(e)) => Err(clone_error(e)),
+        }
+    }
+
+    /// Poll for this lookup alone until it is over.
+    pub fn wait(mut self) -> io::Result<Vec<SocketAddr>> {
+        loop {
+            if let Some(addrs) = self.try_wait()? {
+                return Ok(addrs);
+            }
+            poll(&[self.subscription(0)])?;
+        }
+    }
+
+    fn start_candidate(&mut self) {
+        let name = self.candidates[self.candidate].clone();
+        self.queries = self
+            .resolver
+            .ip_preference()
+            .qtypes()
+            .iter()
+            .map(|&qtype| Query {
+                qtype,
+                name: name.clone(),
+                hops: 0,
+                id: 0,
+                packet: vec![],
+                waiting: false,
+                over_tcp: false,
+                response: None,
+                found: None,
+            })
+            .collect();
+        self.step = 0;
+        self.socket = None;
+        self.tcp = None;
+        for i in 0..self.queries.len() {
+            self.use_cache(i);
+        }
+    }
+
+    // Send what is due until there is something to wait for or the lookup
+    // is over.
+    fn advance(&mut self) {
+        while self.result.is_none() {
+            if self.queries.iter().all(|q| q.found.is_some()) {
+                self.finish_candidate();
+                continue;
+            }
+            if self.socket.is_some() {
+                return;
+            }
+            if self.step >= self.steps() {
+                self.give_up();
+                continue;
+            }
+            if let Err(e) = self.send() {
+                for query in self.queries.iter_mut().filter(|q| q.found.is_none()) {
+                    query.response = Some(Err(clone_error(&e)));
+                }
+                self.socket = None;
+                self.tcp = None;
+                self.step += 1;
+            }
+        }
+    }
+
+    fn steps(&self) -> usize {
+        self.resolver.options().attempts.max(1) as usize * self.resolver.nameservers().len()
+    }
+
+    fn server(&self) -> SocketAddr {
+        let servers = self.resolver.nameservers();
+        servers[self.step % servers.len()]
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        let server = self.server();
+        let socket = dns::udp_socket_for(server)?;
+        for query in self.queries.iter_mut().filter(|q| q.found.is_none()) {
+            query.send(&socket, server)?;
+        }
+        self.deadline = SystemTime::now() + self.resolver.options().timeout;
+        self.socket = Some(socket);
+        Ok(())
+    }
+
+    fn receive(&mut self) {
+        let socket = match &self.socket {
+            Some(socket) => socket,
+            None => return,
+        };
+        let server = self.server();
+        let mut datagrams = vec![];
+        let mut buf = vec![0u8; dns::UDP_BUFFER_LEN];
+        loop {
+            match socket.recv_from(&mut buf) {
+                Ok((n, from)) => {
+                    if let Some(id) = dns::response_id(&buf[..n], from, server) {
+                        datagrams.push((buf[..n].to_vec(), id));
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    for query in self.queries.iter_mut().filter(|q| q.waiting) {
+                        query.waiting = false;
+                        query.response = Some(Err(clone_error(&e)));
+                    }
+                    break;
+                }
+            }
+        }
+
+        for (datagram, id) in datagrams {
+            // ignore answers to queries that are no longer asked
+            let i = match self
+                .queries
+                .iter()
+                .position(|q| q.waiting && !q.over_tcp && q.id == id)
+            {
+                Some(i) => i,
+                None => continue,
+            };
+            if dns::is_truncated(&datagram) {
+                if let Err(e) = self.retry_over_tcp(i, server) {
+                    self.queries[i].waiting = false;
+                    self.queries[i].response = Some(Err(e));
+                }
+                continue;
+            }
+            self.queries[i].waiting = false;
+            self.answer(i, Ok((datagram, id)));
+        }
+    }
+
+    // Ask for the answer to query `i` again over TCP. The connection is
+    // opened for the first truncated answer of the step, and the answers
+    // are read as they arrive.
+    fn retry_over_tcp(&mut self, i: usize, server: SocketAddr) -> io::Result<()> {
+        if self.tcp.is_none() {
+            self.tcp = Some(TcpRetry::connect(server)?);
+            self.deadline = SystemTime::now() + self.resolver.options().tcp_timeout;
+        }
+        let query = &mut self.queries[i];
+        let tcp = self.tcp.as_mut().unwrap();
+        tcp.out.extend_from_slice(&dns::framed(&query.packet));
+        query.over_tcp = true;
+        Ok(())
+    }
+
+    // Write the queries and read the answers on the TCP connection, as far
+    // as it goes without blocking.
+    fn exchange_tcp(&mut self) {
+        let tcp = match &mut self.tcp {
+            Some(tcp) => tcp,
+            None => return,
+        };
+        let (messages, error) = tcp.exchange();
+        for message in messages {
+            let id = u16::from_be_bytes([message[0], message[1]]);
+            let i = match self
+                .queries
+                .iter()
+                .position(|q| q.waiting && q.over_tcp && q.id == id)
+            {
+                Some(i) => i,
+                None => continue,
+            };
+            self.queries[i].waiting = false;
+            self.queries[i].over_tcp = false;
+            self.answer(i, Ok((message, id)));
+        }
+        let expecting = self.queries.iter().any(|q| q.waiting && q.over_tcp);
+        if !expecting {
+            self.tcp = None;
+        } else if let Some(e) = error {
+            self.tcp = None;
+            for query in self.queries.iter_mut().filter(|q| q.over_tcp) {
+                query.waiting = false;
+                query.over_tcp = false;
+                query.response = Some(Err(clone_error(&e)));
+            }
+        }
+    }
+
+    fn answer(&mut self, i: usize, response: io::Result<Response>) {
+        let query = &self.queries[i];
+        let response = response.and_then(|(response, id)| {
+            dns::check_response(&response, id, &query.name, query.qtype as u16)?;
+            Ok((response, id))
+        });
+        match response {
+            Ok((response, id)) if dns::is_final(&response) => {
+                if let Some(cache) = self.resolver.cache() {
+                    let query = &self.queries[i];
+                    cache.insert(&query.name, query.qtype as u16, &response, id);
+                }
+                self.settle(i, (response, id));
+            }
+            response => self.queries[i].response = Some(response),
+        }
+    }
+
+    // Use the cached response for query `i`, if there is one.
+    fn use_cache(&mut self, i: usize) {
+        let query = &self.queries[i];
+        let cached = self
+            .resolver
+            .cache()
+            .and_then(|cache| cache.get(&query.name, query.qtype as u16));
+        if let Some(response) = cached {
+            self.settle(i, response);
+        }
+    }
+
+    // Take the records of a response that settles query `i`, or follow its
+    // CNAME chain.
+    fn settle(&mut self, i: usize, response: Response) {
+        let query = &mut self.queries[i];
+        let answers = match parse(query.qtype, &response, &query.name) {
+            Ok(answers) => answers,
+            Err(e) => {
+                query.found = Some(Err(e));
+                return;
+            }
+        };
+        if let Err(e) = answers.check_chain(query.hops) {
+            query.found = Some(Err(e));
+            return;
+        }
+        let alias = match answers.alias {
+            Some(alias) => alias,
+            None => {
+                query.found = Some(Ok(answers.records));
+                return;
+            }
+        };
+        query.hops += answers.hops;
+        query.name = alias;
+        query.response = None;
+        query.waiting = false;
+        self.use_cache(i);
+
+        // ask the server of this step for the alias; without a socket, it is
+        // asked when the queries are next sent
+        let server = self.server();
+        let query = &mut self.queries[i];
+        if let (None, Some(socket)) = (&query.found, &self.socket) {
+            if let Err(e) = query.send(socket, server) {
+                query.found = Some(Err(e));
+            }
+        }
+    }
+
+    // The servers have all been tried: each query ends with the last answer
+    // it got, if any.
+    fn give_up(&mut self) {
+        for query in self.queries.iter_mut().filter(|q| q.found.is_none()) {
+            query.found = Some(match query.response.take() {
+                Some(Ok(response)) => parse(query.qtype, &response, &query.name).map(|a| a.records),
+                Some(Err(e)) => Err(e),
+                None => Err(WasiErrno::Timedout.into()),
+            });
+        }
+    }
+
+    // Combine the answers for the candidate name, as `Resolver::lookup_ip`
+    // does, and move on to the next candidate if the name has no addresses.
+    fn finish_candidate(&mut self) {
+        let mut addrs = vec![];
+        let mut noname = None;
+        let mut error = None;
+        for query in self.queries.drain(..) {
+            match query.found.unwrap() {
+                Ok(found) => addrs.extend(found),
+                Err(e) if WasiErrno::from_io_error(&e) == Some(WasiErrno::AiNoname) => {
+                    noname = noname.or(Some(e))
+                }
+                Err(e) => error = error.or(Some(e)),
+            }
+        }
+        let e = match noname {
+            None if !addrs.is_empty() => {
+                self.resolver.order_ips(&mut addrs);
+                let port = self.port;
+                self.result = Some(Ok(addrs
+                    .into_iter()
+                    .map(|ip| SocketAddr::new(ip, port))
+                    .collect()));
+                return;
+            }
+            None => error.unwrap_or_else(|| WasiErrno::AiNodata.into()),
+            Some(e) => e,
+        };
+
+        match WasiErrno::from_io_error(&e) {
+            // "no records" says more than "no such name"
+            Some(WasiErrno::AiNodata) => self.error = Some(e),
+            Some(WasiErrno::AiNoname) => self.error = self.error.take().or(Some(e)),
+            _ => {
+                self.result = Some(Err(e));
+                return;
+            }
+        }
+        self.candidate += 1;
+        if self.candidate < self.candidates.len() {
+            self.start_candidate();
+        } else {
+            let e = self
+                .error
+                .take()
+                .unwrap_or_else(|| WasiErrno::AiNoname.into());
+            self.result = Some(Err(e));
+        }
+    }
+}
+
+impl Query {
+    fn send(&mut self, socket: &UdpSocket, server: SocketAddr) -> io::Result<()> {
+        self.id = rand::random();
+        self.packet = dns::build_query(self.id, &self.name, self.qtype as u16)?;
+        socket.send_to(&self.packet, server)?;
+        self.waiting = true;
+        self.over_tcp = false;
+        Ok(())
+    }
+}
+
+impl TcpRetry {
+    fn connect(server: SocketAddr) -> io::Result<Self> {
+        let socket = Socket::new(AddressFamily::from(&server), SocketType::Stream)?;
+        socket.set_nonblocking(true)?;
+        match socket.connect(&server) {
+            Err(e) if e.kind() != io::ErrorKind::WouldBlock => return Err(e),
+            _ => {}
+        }
+        Ok(TcpRetry {
+            stream: TcpStream::new(socket),
+            out: vec![],
+            buf: vec![],
+        })
+    }
+
+    // The answers read so far, and the error that ended the exchange, if
+    // any. Until the connection is made, reads and writes would block.
+    fn exchange(&mut self) -> (Vec<Vec<u8>>, Option<io::Error>) {
+        let mut messages = vec![];
+        let error = self.write().and_then(|()| self.read()).err();
+        loop {
+            match dns::take_framed(&mut self.buf) {
+                Ok(Some(message)) => messages.push(message),
+                Ok(None) => return (messages, error),
+                Err(e) => return (messages, Some(e)),
+            }
+        }
+    }
+
+    fn write(&mut self) -> io::Result<()> {
+        while !self.out.is_empty() {
+            match self.stream.write(&self.out) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.out.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => self.buf.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+fn parse(qtype: QueryType, response: &Response, name: &str) -> io::Result<Answers<IpAddr>> {
+    let (response, id) = response;
+    match qtype {
+        QueryType::A => dns::parse_answers::<Ipv4Addr>(response, *id, name).map(|a| Answers {
+            records: a.records.into_iter().map(IpAddr::V4).collect(),
+            alias: a.alias,
+            hops: a.hops,
+        }),
+        _ => dns::parse_answers::<Ipv6Addr>(response, *id, name).map(|a| Answers {
+            records: a.records.into_iter().map(IpAddr::V6).collect(),
+            alias: a.alias,
+            hops: a.hops,
+        }),
+    }
+}
@@ -1,5 +1,5 @@
 use crate::wasi_poll as poll;
-use std::os::wasi::prelude::*;
+use std::os::fd::{AsRawFd, RawFd};
 
 #[derive(Clone)]
 pub enum Subscription {
@@ -199,8 +199,7 @@ pub fn poll(subs: &[Subscription]) -> std::io::Result<Vec<Event>> {
 
         let mut events = vec![];
 
-        for i in 0..n {
-            let event = revent[i];
+        for &event in revent.iter().take(n) {
             match event.type_ {
                 poll::EVENTTYPE_CLOCK => {
                     events.push({
@@ -212,7 +211,7 @@ pub fn poll(subs: &[Subscription]) -> std::io::Result<Vec<Event>> {
                 }
                 poll::EVENTTYPE_FD_READ | poll::EVENTTYPE_FD_WRITE => {
                     if event.error > 0 {
-                        let e = io::Error::from_raw_os_error(event.error as i32);
+                        let e = crate::errno::io_error(event.error as u32);
                         events.push(Event {
                             event_type: EventType::Error(e),
                             userdata: event.userdata,
@@ -222,7 +221,7 @@ pub fn poll(subs: &[Subscription]) -> std::io::Result<Vec<Event>> {
 
                     if event.type_ == poll::EVENTTYPE_FD_READ {
                         events.push(Event {
-                            event_type:EventType::Read,
+                            event_type: EventType::Read,
                             userdata: event.userdata,
                         });
                     } else {
@@ -232,9 +231,9 @@ pub fn poll(subs: &[Subscription]) -> std::io::Result<Vec<Event>> {
                                 event_type: EventType::Error(e),
                                 userdata: event.userdata,
                             });
-                        }else{
+                        } else {
                             events.push(Event {
-                                event_type:EventType::Write,
+                                event_type: EventType::Write,
                                 userdata: event.userdata,
                             });
                         }
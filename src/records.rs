@@ -0,0 +1,340 @@
+//! Typed DNS records for [`Resolver::query`](crate::Resolver::query) and
+//! [`resolve`](crate::resolve).
+
+use crate::dns::{encode_name, ToQType};
+use dns_parser::{QueryType, RData};
+use rand::Rng;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// The location of a service, from an SRV record (RFC 2782).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Srv {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+impl Srv {
+    /// Whether the record says the service is not available at this domain,
+    /// with a target of `.`.
+    pub fn is_unavailable(&self) -> bool {
+        self.target.is_empty() || self.target == "."
+    }
+}
+
+/// A mail exchanger, from an MX record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mx {
+    pub preference: u16,
+    pub exchange: String,
+}
+
+/// The character strings of a TXT record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Txt(pub Vec<Vec<u8>>);
+
+impl Txt {
+    /// The strings joined together, as used by SPF and DKIM records. Invalid
+    /// UTF-8 is replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.concat()).into_owned()
+    }
+}
+
+/// The name an address maps back to, from a PTR record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ptr(pub String);
+
+/// A certification authority authorization, from a CAA record (RFC 8659).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Caa {
+    pub flags: u8,
+    /// The property, such as `issue`, `issuewild` or `iodef`.
+    pub tag: String,
+    pub value: Vec<u8>,
+}
+
+impl Caa {
+    /// Whether a CA that does not understand the tag must not issue.
+    pub fn is_critical(&self) -> bool {
+        self.flags & 0x80 != 0
+    }
+}
+
+const TYPE_CAA: u16 = 257;
+
+impl ToQType for Srv {
+    fn q_type() -> QueryType {
+        QueryType::SRV
+    }
+
+    fn from_rr(rr: RData) -> Option<Self> {
+        match rr {
+            RData::SRV(srv) => Some(Srv {
+                priority: srv.priority,
+                weight: srv.weight,
+                port: srv.port,
+                target: srv.target.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl ToQType for Mx {
+    fn q_type() -> QueryType {
+        QueryType::MX
+    }
+
+    fn from_rr(rr: RData) -> Option<Self> {
+        match rr {
+            RData::MX(mx) => Some(Mx {
+                preference: mx.preference,
+                exchange: mx.exchange.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl ToQType for Txt {
+    fn q_type() -> QueryType {
+        QueryType::TXT
+    }
+
+    fn from_rr(rr: RData) -> Option<Self> {
+        match rr {
+            RData::TXT(txt) => Some(Txt(txt.iter().map(<[u8]>::to_vec).collect())),
+            _ => None,
+        }
+    }
+}
+
+impl ToQType for Ptr {
+    fn q_type() -> QueryType {
+        QueryType::PTR
+    }
+
+    fn from_rr(rr: RData) -> Option<Self> {
+        match rr {
+            RData::PTR(ptr) => Some(Ptr(ptr.0.to_string())),
+            _ => None,
+        }
+    }
+}
+
+impl ToQType for Caa {
+    // dns_parser has no CAA type; the records reach `from_rr` as the data of
+    // NULL records
+    fn q_type() -> QueryType {
+        QueryType::NULL
+    }
+
+    fn type_code() -> u16 {
+        TYPE_CAA
+    }
+
+    fn from_rr(rr: RData) -> Option<Self> {
+        let data = match rr {
+            RData::Unknown(data) => data,
+            _ => return None,
+        };
+        let (&flags, rest) = data.split_first()?;
+        let (&tag_len, rest) = rest.split_first()?;
+        if tag_len == 0 || rest.len() < tag_len as usize {
+            return None;
+        }
+        let (tag, value) = rest.split_at(tag_len as usize);
+        Some(Caa {
+            flags,
+            tag: std::str::from_utf8(tag).ok()?.to_string(),
+            value: value.to_vec(),
+        })
+    }
+}
+
+/// Put `records` in the order a client should try them in: by priority, and
+/// within a priority in a random order weighted by the records' weights, as
+/// described in RFC 2782. Records saying the service is unavailable are
+/// dropped.
+pub fn order_srv(mut records: Vec<Srv>) -> Vec<Srv> {
+    records.retain(|r| !r.is_unavailable());
+    records.sort_by_key(|r| r.priority);
+    let mut rng = rand::thread_rng();
+    let mut ordered = Vec::with_capacity(records.len());
+    while !records.is_empty() {
+        let priority = records[0].priority;
+        let end = records
+            .iter()
+            .position(|r| r.priority != priority)
+            .unwrap_or(records.len());
+        let mut group: Vec<Srv> = records.drain(..end).collect();
+        // records of weight 0 go first, so that they have a small chance of
+        // being picked
+        group.sort_by_key(|r| r.weight != 0);
+        while !group.is_empty() {
+            let total: u32 = group.iter().map(|r| r.weight as u32).sum();
+            let pick = rng.gen_range(0..=total);
+            let mut sum = 0;
+            let i = group
+                .iter()
+                .position(|r| {
+                    sum += r.weight as u32;
+                    sum >= pick
+                })
+                .unwrap_or(0);
+            ordered.push(group.remove(i));
+        }
+    }
+    ordered
+}
+
+/// The data of a [`Record`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(String),
+    Ptr(Ptr),
+    Mx(Mx),
+    Srv(Srv),
+    Txt(Txt),
+    Caa(Caa),
+    /// A record of any other type, with its data as it is sent.
+    Other(u16, Vec<u8>),
+}
+
+impl RecordData {
+    pub fn type_code(&self) -> u16 {
+        match self {
+            RecordData::A(_) => QueryType::A as u16,
+            RecordData::Aaaa(_) => QueryType::AAAA as u16,
+            RecordData::Cname(_) => QueryType::CNAME as u16,
+            RecordData::Ptr(_) => QueryType::PTR as u16,
+            RecordData::Mx(_) => QueryType::MX as u16,
+            RecordData::Srv(_) => QueryType::SRV as u16,
+            RecordData::Txt(_) => QueryType::TXT as u16,
+            RecordData::Caa(_) => TYPE_CAA,
+            RecordData::Other(rtype, _) => *rtype,
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            RecordData::A(ip) => out.extend_from_slice(&ip.octets()),
+            RecordData::Aaaa(ip) => out.extend_from_slice(&ip.octets()),
+            RecordData::Cname(name) | RecordData::Ptr(Ptr(name)) => encode_name(out, name)?,
+            RecordData::Mx(mx) => {
+                out.extend_from_slice(&mx.preference.to_be_bytes());
+                encode_name(out, &mx.exchange)?;
+            }
+            RecordData::Srv(srv) => {
+                out.extend_from_slice(&srv.priority.to_be_bytes());
+                out.extend_from_slice(&srv.weight.to_be_bytes());
+                out.extend_from_slice(&srv.port.to_be_bytes());
+                encode_name(out, &srv.target)?;
+            }
+            RecordData::Txt(txt) => {
+                for s in &txt.0 {
+                    if s.is_empty() {
+                        out.push(0);
+                    }
+                    // a character string holds at most 255 bytes
+                    for chunk in s.chunks(255) {
+                        out.push(chunk.len() as u8);
+                        out.extend_from_slice(chunk);
+                    }
+                }
+            }
+            RecordData::Caa(caa) => {
+                out.push(caa.flags);
+                out.push(caa.tag.len() as u8);
+                out.extend_from_slice(caa.tag.as_bytes());
+                out.extend_from_slice(&caa.value);
+            }
+            RecordData::Other(_, data) => out.extend_from_slice(data),
+        }
+        Ok(())
+    }
+}
+
+impl From<Ipv4Addr> for RecordData {
+    fn from(ip: Ipv4Addr) -> Self {
+        RecordData::A(ip)
+    }
+}
+
+impl From<Ipv6Addr> for RecordData {
+    fn from(ip: Ipv6Addr) -> Self {
+        RecordData::Aaaa(ip)
+    }
+}
+
+impl From<IpAddr> for RecordData {
+    fn from(ip: IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(ip) => RecordData::A(ip),
+            IpAddr::V6(ip) => RecordData::Aaaa(ip),
+        }
+    }
+}
+
+impl From<Ptr> for RecordData {
+    fn from(ptr: Ptr) -> Self {
+        RecordData::Ptr(ptr)
+    }
+}
+
+impl From<Mx> for RecordData {
+    fn from(mx: Mx) -> Self {
+        RecordData::Mx(mx)
+    }
+}
+
+impl From<Srv> for RecordData {
+    fn from(srv: Srv) -> Self {
+        RecordData::Srv(srv)
+    }
+}
+
+impl From<Txt> for RecordData {
+    fn from(txt: Txt) -> Self {
+        RecordData::Txt(txt)
+    }
+}
+
+impl From<Caa> for RecordData {
+    fn from(caa: Caa) -> Self {
+        RecordData::Caa(caa)
+    }
+}
+
+/// A record to serve, as a stub zone or a multicast DNS responder does.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    pub name: String,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+/// Append `record` with the class field `class`, which multicast DNS adds a
+/// flag to. Names that are not valid DNS names are refused.
+pub(crate) fn encode_record(out: &mut Vec<u8>, record: &Record, class: u16) -> io::Result<()> {
+    encode_name(out, &record.name)?;
+    out.extend_from_slice(&record.data.type_code().to_be_bytes());
+    out.extend_from_slice(&class.to_be_bytes());
+    out.extend_from_slice(&record.ttl.to_be_bytes());
+    let at = out.len();
+    out.extend_from_slice(&[0, 0]);
+    record.data.encode(out)?;
+    let len = u16::try_from(out.len() - at - 2).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the data of the record for {:?} is too long", record.name),
+        )
+    })?;
+    out[at..at + 2].copy_from_slice(&len.to_be_bytes());
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Settings read from a `resolv.conf` file.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+
+// the limits glibc puts on the options
+const MAX_NDOTS: u32 = 15;
+const MAX_TIMEOUT: u64 = 30;
+const MAX_ATTEMPTS: u32 = 5;
+
+/// The parts of `resolv.conf(5)` that a [`Resolver`](crate::Resolver) uses.
+///
+/// `nameserver` lines give the servers, `search` and `domain` the domains
+/// appended to short names, and `options` sets `ndots`, `timeout` and
+/// `attempts`. The last `search` or `domain` line wins. Other lines and
+/// options are ignored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvConf {
+    pub nameservers: Vec<SocketAddr>,
+    pub search: Vec<String>,
+    /// Names with fewer dots than this are tried with the search domains
+    /// first.
+    pub ndots: u32,
+    pub timeout: Duration,
+    pub attempts: u32,
+}
+
+impl Default for ResolvConf {
+    fn default() -> Self {
+        ResolvConf {
+            nameservers: vec![],
+            search: vec![],
+            ndots: 1,
+            timeout: Duration::from_secs(5),
+            attempts: 2,
+        }
+    }
+}
+
+impl ResolvConf {
+    /// Parse the contents of a `resolv.conf` file.
+    pub fn parse(text: &str) -> Self {
+        let mut conf = ResolvConf::default();
+        for line in text.lines() {
+            let line = line.split(['#', ';']).next().unwrap_or("");
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("nameserver") => {
+                    let ip = fields
+                        .next()
+                        .and_then(|s| s.split('%').next())
+                        .and_then(|s| s.parse::<IpAddr>().ok());
+                    if let Some(ip) = ip {
+                        conf.nameservers.push((ip, 53).into());
+                    }
+                }
+                Some("domain") => {
+                    conf.search = fields.next().map(domain).into_iter().collect();
+                }
+                Some("search") => conf.search = fields.map(domain).collect(),
+                Some("options") => {
+                    for option in fields {
+                        conf.set_option(option);
+                    }
+                }
+                _ => {}
+            }
+        }
+        conf
+    }
+
+    /// Read a `resolv.conf` file. On WASI, its directory must be preopened.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(ResolvConf::parse(&std::fs::read_to_string(path)?))
+    }
+
+    fn set_option(&mut self, option: &str) {
+        let (name, value) = match option.split_once(':') {
+            Some((name, value)) => match value.parse::<u32>() {
+                Ok(value) => (name, value),
+                Err(_) => return,
+            },
+            None => return,
+        };
+        match name {
+            "ndots" => self.ndots = value.min(MAX_NDOTS),
+            "timeout" => self.timeout = Duration::from_secs((value as u64).clamp(1, MAX_TIMEOUT)),
+            "attempts" => self.attempts = value.clamp(1, MAX_ATTEMPTS),
+            _ => {}
+        }
+    }
+}
+
+fn domain(s: &str) -> String {
+    s.trim_end_matches('.').to_string()
+}
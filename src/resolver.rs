@@ -0,0 +1,637 @@
+//! Configurable name resolution.
+
+use crate::addr_select;
+use crate::cache::DnsCache;
+use crate::dns::{self, DnsOptions, Response, ToQType, Transport};
+use crate::hosts::Hosts;
+use crate::pending::PendingLookup;
+use crate::records::{self, Ptr, Srv};
+use crate::resolv_conf::ResolvConf;
+use crate::services::Services;
+use crate::socket::AiProtocol;
+#[cfg(feature = "tls")]
+use crate::tls::{self, TlsSettings};
+use crate::{nslookup_with_protocol, WasiErrno};
+use dns_parser::QueryType;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// Which address families a lookup returns, and in which order.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IpPreference {
+    /// Both families, in the order of RFC 6724 destination address selection.
+    Any,
+    Ipv4Only,
+    Ipv6Only,
+    /// Both families, IPv4 addresses first.
+    Ipv4First,
+    /// Both families, IPv6 addresses first.
+    Ipv6First,
+}
+
+impl IpPreference {
+    pub(crate) fn qtypes(self) -> &'static [QueryType] {
+        match self {
+            IpPreference::Ipv4Only => &[QueryType::A],
+            IpPreference::Ipv6Only => &[QueryType::AAAA],
+            IpPreference::Ipv6First => &[QueryType::AAAA, QueryType::A],
+            _ => &[QueryType::A, QueryType::AAAA],
+        }
+    }
+
+    fn apply(self, addrs: &mut Vec<SocketAddr>) {
+        match self {
+            IpPreference::Any => {}
+            IpPreference::Ipv4Only => addrs.retain(|a| a.is_ipv4()),
+            IpPreference::Ipv6Only => addrs.retain(|a| a.is_ipv6()),
+            IpPreference::Ipv4First => addrs.sort_by_key(|a| a.is_ipv6()),
+            IpPreference::Ipv6First => addrs.sort_by_key(|a| a.is_ipv4()),
+        }
+    }
+}
+
+/// Settings for turning host names into addresses.
+///
+/// A resolver either asks the WasmEdge host through `sock_getaddrinfo`, or
+/// sends queries to a list of DNS servers itself. The servers are tried in
+/// order, moving on when one times out or fails, and the whole list is tried
+/// up to `attempts` times. Answers from DNS servers are kept in the
+/// [`DnsCache::global`] cache unless the resolver is given another one.
+/// Names in the resolver's [`Hosts`] table are answered before either.
+///
+/// ```ignore
+/// let resolver = Resolver::new(vec!["1.1.1.1:53".parse()?, "8.8.8.8:53".parse()?])
+///     .with_timeout(Duration::from_secs(2))
+///     .with_ip_preference(IpPreference::Ipv6First);
+/// let stream = TcpStream::connect_with("example.com:80", &resolver)?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct Resolver {
+    nameservers: Vec<SocketAddr>,
+    options: DnsOptions,
+    transport: Transport,
+    ip_preference: IpPreference,
+    cache: Option<Arc<DnsCache>>,
+    hosts: Option<Arc<Hosts>>,
+    search: Vec<String>,
+    ndots: u32,
+    services: Arc<Services>,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<TlsSettings>>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Resolver::host()
+    }
+}
+
+impl Resolver {
+    /// Resolve names with the host's `getaddrinfo`.
+    pub fn host() -> Self {
+        Resolver::new(vec![])
+    }
+
+    /// Send queries to `nameservers` directly.
+    pub fn new(nameservers: Vec<SocketAddr>) -> Self {
+        Resolver {
+            nameservers,
+            options: DnsOptions::default(),
+            transport: Transport::Udp,
+            ip_preference: IpPreference::Any,
+            cache: Some(DnsCache::global()),
+            hosts: None,
+            search: vec![],
+            ndots: 1,
+            services: Services::standard(),
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+
+    /// Use the servers, search domains and options of a `resolv.conf` file.
+    /// When it lists no servers, the host resolves names.
+    pub fn from_resolv_conf(conf: &ResolvConf) -> Self {
+        Resolver::new(conf.nameservers.clone())
+            .with_timeout(conf.timeout)
+            .with_attempts(conf.attempts)
+            .with_search(conf.search.clone())
+            .with_ndots(conf.ndots)
+    }
+
+    /// A resolver configured from the environment, which
+    /// [`Resolver::env`] keeps for [`nslookup`](crate::nslookup) and
+    /// [`ToSocketAddrs`](crate::ToSocketAddrs).
+    ///
+    /// It is read from the `resolv.conf` file named by the `RESOLV_CONF`
+    /// environment variable, if any. The comma separated servers in
+    /// `DNS_SERVER` replace the servers of that file. Without either, the host
+    /// resolves names. The hosts file named by `HOSTS_FILE` and the services
+    /// file named by `SERVICES_FILE` are used if there are ones.
+    pub fn from_env() -> io::Result<Self> {
+        let mut resolver = match std::env::var("RESOLV_CONF") {
+            Ok(path) => Resolver::from_resolv_conf(&ResolvConf::from_file(path)?),
+            Err(_) => Resolver::host(),
+        };
+        if let Ok(servers) = std::env::var("DNS_SERVER") {
+            resolver.nameservers = servers
+                .split(',')
+                .map(|s| parse_nameserver(s.trim()))
+                .collect::<io::Result<Vec<_>>>()?;
+        }
+        if let Ok(path) = std::env::var("HOSTS_FILE") {
+            resolver = resolver.with_hosts(Hosts::from_file(path)?);
+        }
+        if let Ok(path) = std::env::var("SERVICES_FILE") {
+            resolver = resolver.with_services(Services::from_file(path)?);
+        }
+        Ok(resolver)
+    }
+
+    /// The resolver of [`from_env`](Resolver::from_env), built on first use
+    /// and shared by [`nslookup`](crate::nslookup),
+    /// [`nslookup_addr`](crate::nslookup_addr) and
+    /// [`ToSocketAddrs`](crate::ToSocketAddrs), so that they do not read
+    /// the environment and its files on every lookup. Changes to the
+    /// environment after that are not seen; call `from_env` for a fresh
+    /// resolver. A failure is not kept, so a later call tries again.
+    pub fn env() -> io::Result<&'static Resolver> {
+        static ENV: OnceLock<Resolver> = OnceLock::new();
+        if let Some(resolver) = ENV.get() {
+            return Ok(resolver);
+        }
+        let resolver = Resolver::from_env()?;
+        Ok(ENV.get_or_init(|| resolver))
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = timeout;
+        self
+    }
+
+    pub fn with_attempts(mut self, attempts: u32) -> Self {
+        self.options.attempts = attempts;
+        self
+    }
+
+    pub fn with_tcp_timeout(mut self, timeout: Duration) -> Self {
+        self.options.tcp_timeout = timeout;
+        self
+    }
+
+    pub fn with_options(mut self, options: DnsOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    pub fn with_ip_preference(mut self, ip_preference: IpPreference) -> Self {
+        self.ip_preference = ip_preference;
+        self
+    }
+
+    /// Keep answers in `cache`, or don't cache them with `None`.
+    pub fn with_cache(mut self, cache: Option<Arc<DnsCache>>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Answer the names in `hosts` without asking the host or DNS servers.
+    pub fn with_hosts(mut self, hosts: Hosts) -> Self {
+        self.hosts = Some(Arc::new(hosts));
+        self
+    }
+
+    /// Map service names to ports with `services` instead of
+    /// [`Services::standard`]. The host resolver falls back to its own table
+    /// for names that are not in it.
+    pub fn with_services(mut self, services: Services) -> Self {
+        self.services = Arc::new(services);
+        self
+    }
+
+    /// Try names with fewer than `ndots` dots in each of the `search` domains
+    /// first, and other names as they are first. Names ending with a dot are
+    /// never searched. The host resolver applies its own search domains.
+    pub fn with_search(mut self, search: Vec<String>) -> Self {
+        self.search = search;
+        self
+    }
+
+    pub fn with_ndots(mut self, ndots: u32) -> Self {
+        self.ndots = ndots;
+        self
+    }
+
+    /// Check the certificates of servers reached over [`Transport::Tls`] or
+    /// [`Transport::Https`] with `settings`.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, settings: TlsSettings) -> Self {
+        self.tls = Some(Arc::new(settings));
+        self
+    }
+
+    /// The DNS servers queried; empty when the host resolves names.
+    pub fn nameservers(&self) -> &[SocketAddr] {
+        &self.nameservers
+    }
+
+    pub fn options(&self) -> &DnsOptions {
+        &self.options
+    }
+
+    pub fn transport(&self) -> Transport {
+        self.transport
+    }
+
+    pub fn ip_preference(&self) -> IpPreference {
+        self.ip_preference
+    }
+
+    pub fn cache(&self) -> Option<&Arc<DnsCache>> {
+        self.cache.as_ref()
+    }
+
+    pub fn hosts(&self) -> Option<&Hosts> {
+        self.hosts.as_deref()
+    }
+
+    pub fn services(&self) -> &Services {
+        &self.services
+    }
+
+    pub fn search(&self) -> &[String] {
+        &self.search
+    }
+
+    pub fn ndots(&self) -> u32 {
+        self.ndots
+    }
+
+    #[cfg(feature = "tls")]
+    pub fn tls(&self) -> Option<&TlsSettings> {
+        self.tls.as_deref()
+    }
+
+    /// Look up the addresses of `node`, like [`nslookup`](crate::nslookup),
+    /// with the TCP port of `service`: a number, a name such as `https`, or
+    /// empty for port 0.
+    pub fn lookup(&self, node: &str, service: &str) -> io::Result<Vec<SocketAddr>> {
+        self.lookup_with_protocol(node, service, AiProtocol::IPProtoTCP)
+    }
+
+    /// Like [`lookup`](Self::lookup), with the port of `service` over
+    /// `protocol`.
+    pub fn lookup_with_protocol(
+        &self,
+        node: &str,
+        service: &str,
+        protocol: AiProtocol,
+    ) -> io::Result<Vec<SocketAddr>> {
+        if self.nameservers.is_empty() && self.pinned(node).is_none() {
+            // the host knows the names in its own table, and is given the
+            // numbers of the ones in ours
+            let service = match self.services.port(service, protocol) {
+                Some(port) => port.to_string(),
+                None => service.to_string(),
+            };
+            let mut addrs = nslookup_with_protocol(node, &service, protocol)?;
+            self.ip_preference.apply(&mut addrs);
+            if addrs.is_empty() {
+                return Err(WasiErrno::AiNodata.into());
+            }
+            return Ok(addrs);
+        }
+        let port = self.services.resolve(service, protocol)?;
+        Ok(self
+            .lookup_ip(node)?
+            .into_iter()
+            .map(|ip| (ip, port).into())
+            .collect())
+    }
+
+    /// Query the DNS servers for the A and AAAA records of `name`, following
+    /// the IP preference. Both queries are sent at once.
+    pub fn lookup_ip(&self, name: &str) -> io::Result<Vec<IpAddr>> {
+        let mut addrs = if let Some(ips) = self.pinned(name) {
+            ips.to_vec()
+        } else if name == "localhost" {
+            vec![Ipv4Addr::LOCALHOST.into(), Ipv6Addr::LOCALHOST.into()]
+        } else {
+            self.try_candidates(name, |name| self.query_ip(name))?
+        };
+        self.order_ips(&mut addrs);
+        Ok(addrs)
+    }
+
+    /// Start looking up the addresses of `node` without blocking, with the
+    /// TCP port of `service`. See [`PendingLookup`] for how to drive it.
+    pub fn start_lookup(&self, node: &str, service: &str) -> io::Result<PendingLookup> {
+        PendingLookup::start(self, node, service)
+    }
+
+    /// Sort `addrs` by RFC 6724 and apply the IP preference.
+    pub(crate) fn order_ips(&self, addrs: &mut Vec<IpAddr>) {
+        addr_select::sort(addrs);
+        match self.ip_preference {
+            IpPreference::Any => {}
+            IpPreference::Ipv4Only => addrs.retain(IpAddr::is_ipv4),
+            IpPreference::Ipv6Only => addrs.retain(IpAddr::is_ipv6),
+            IpPreference::Ipv4First => addrs.sort_by_key(IpAddr::is_ipv6),
+            IpPreference::Ipv6First => addrs.sort_by_key(IpAddr::is_ipv4),
+        }
+    }
+
+    pub(crate) fn pinned(&self, name: &str) -> Option<&[IpAddr]> {
+        self.hosts.as_ref()?.get(name)
+    }
+
+    /// Call `f` with each name `name` may stand for, in the order of the
+    /// search domains, until one of them exists and has records.
+    fn try_candidates<R>(
+        &self,
+        name: &str,
+        mut f: impl FnMut(&str) -> io::Result<R>,
+    ) -> io::Result<R> {
+        let mut error: Option<io::Error> = None;
+        for candidate in self.candidates(name) {
+            match f(&candidate) {
+                Err(e) => match WasiErrno::from_io_error(&e) {
+                    // "no records" says more than "no such name"
+                    Some(WasiErrno::AiNodata) => error = Some(e),
+                    Some(WasiErrno::AiNoname) => error = error.or(Some(e)),
+                    _ => return Err(e),
+                },
+                found => return found,
+            }
+        }
+        Err(error.unwrap_or_else(|| WasiErrno::AiNoname.into()))
+    }
+
+    pub(crate) fn candidates(&self, name: &str) -> Vec<String> {
+        if let Some(absolute) = name.strip_suffix('.') {
+            return vec![absolute.to_string()];
+        }
+        let mut candidates: Vec<String> = self
+            .search
+            .iter()
+            .map(|domain| format!("{}.{}", name, domain))
+            .collect();
+        if name.matches('.').count() as u32 >= self.ndots {
+            candidates.insert(0, name.to_string());
+        } else {
+            candidates.push(name.to_string());
+        }
+        candidates
+    }
+
+    fn query_ip(&self, name: &str) -> io::Result<Vec<IpAddr>> {
+        let qtypes = self.ip_preference.qtypes();
+        let mut addrs = vec![];
+        let mut error = None;
+        let codes: Vec<u16> = qtypes.iter().map(|&q| q as u16).collect();
+        for (&qtype, r) in qtypes.iter().zip(self.query_raw(name, &codes)?) {
+            let found = r.and_then(|response| match qtype {
+                QueryType::A => self
+                    .follow::<Ipv4Addr>(name, response, 0)
+                    .map(|v| v.into_iter().map(IpAddr::V4).collect::<Vec<_>>()),
+                _ => self
+                    .follow::<Ipv6Addr>(name, response, 0)
+                    .map(|v| v.into_iter().map(IpAddr::V6).collect::<Vec<_>>()),
+            });
+            match found {
+                Ok(found) => addrs.extend(found),
+                Err(e) if WasiErrno::from_io_error(&e) == Some(WasiErrno::AiNoname) => {
+                    return Err(e)
+                }
+                Err(e) => error = error.or(Some(e)),
+            }
+        }
+        if addrs.is_empty() {
+            Err(error.unwrap_or_else(|| WasiErrno::AiNodata.into()))
+        } else {
+            Ok(addrs)
+        }
+    }
+
+    /// Ask the DNS servers for the records of type `T` of `name`, following
+    /// CNAME records. Short names are looked for in the search domains.
+    pub fn query<T: ToQType>(&self, name: &str) -> io::Result<Vec<T>> {
+        let found = self.try_candidates(name, |name| {
+            let records = self.query_name::<T>(name)?;
+            if records.is_empty() {
+                return Err(WasiErrno::AiNodata.into());
+            }
+            Ok(records)
+        });
+        match found {
+            Err(e) if WasiErrno::from_io_error(&e) == Some(WasiErrno::AiNodata) => Ok(vec![]),
+            found => found,
+        }
+    }
+
+    fn query_name<T: ToQType>(&self, name: &str) -> io::Result<Vec<T>> {
+        let response = self.query_raw(name, &[T::type_code()])?.pop().unwrap()?;
+        self.follow(name, response, 0)
+    }
+
+    /// The names `ip` maps back to, from its PTR records. The host resolver
+    /// cannot do this, so the resolver needs DNS servers.
+    ///
+    /// Anyone who controls the reverse zone of an address can put any name
+    /// there; use [`lookup_addr_confirmed`](Self::lookup_addr_confirmed) when
+    /// the name is trusted for anything.
+    pub fn lookup_addr(&self, ip: IpAddr) -> io::Result<Vec<String>> {
+        if let Some(hosts) = &self.hosts {
+            let names = hosts.names(ip);
+            if !names.is_empty() {
+                return Ok(names.into_iter().map(String::from).collect());
+            }
+        }
+        let names: Vec<Ptr> = self.query_name(&dns::reverse_name(ip))?;
+        if names.is_empty() {
+            return Err(WasiErrno::AiNodata.into());
+        }
+        Ok(names
+            .into_iter()
+            .map(|Ptr(name)| name.trim_end_matches('.').to_string())
+            .collect())
+    }
+
+    /// Like [`lookup_addr`](Self::lookup_addr), but keep only the names
+    /// whose own addresses include `ip` (forward-confirmed reverse DNS).
+    pub fn lookup_addr_confirmed(&self, ip: IpAddr) -> io::Result<Vec<String>> {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            v4 => v4,
+        };
+        // look the names up in both families, whatever the preference says
+        let forward = self.clone().with_ip_preference(IpPreference::Any);
+        let names: Vec<String> = self
+            .lookup_addr(ip)?
+            .into_iter()
+            .filter(|name| {
+                forward
+                    .lookup_ip(&format!("{}.", name))
+                    .is_ok_and(|ips| ips.contains(&ip))
+            })
+            .collect();
+        if names.is_empty() {
+            return Err(WasiErrno::AiNoname.into());
+        }
+        Ok(names)
+    }
+
+    /// The SRV records of `name`, such as `_http._tcp.example.com`, in the
+    /// order they should be tried.
+    pub fn lookup_srv(&self, name: &str) -> io::Result<Vec<Srv>> {
+        Ok(records::order_srv(self.query(name)?))
+    }
+
+    /// The addresses of the servers in the SRV records of `name`, in the order
+    /// they should be tried. Targets that cannot be resolved are skipped.
+    pub fn lookup_srv_addrs(&self, name: &str) -> io::Result<Vec<SocketAddr>> {
+        let mut addrs = vec![];
+        let mut error = None;
+        for srv in self.lookup_srv(name)? {
+            match self.lookup_ip(&srv.target) {
+                Ok(ips) => addrs.extend(ips.into_iter().map(|ip| SocketAddr::new(ip, srv.port))),
+                Err(e) => error = error.or(Some(e)),
+            }
+        }
+        if addrs.is_empty() {
+            return Err(error.unwrap_or_else(|| WasiErrno::AiNodata.into()));
+        }
+        Ok(addrs)
+    }
+
+    /// Parse the response to the query for `name`, and query the end of its
+    /// CNAME chain when the response stops short of it.
+    fn follow<T: ToQType>(
+        &self,
+        name: &str,
+        response: Response,
+        hops: usize,
+    ) -> io::Result<Vec<T>> {
+        let (response, id) = response;
+        let answers = dns::parse_answers::<T>(&response, id, name)?;
+        answers.check_chain(hops)?;
+        match answers.alias {
+            Some(alias) => {
+                let response = self.query_raw(&alias, &[T::type_code()])?.pop().unwrap()?;
+                self.follow(&alias, response, hops + answers.hops)
+            }
+            None => Ok(answers.records),
+        }
+    }
+
+    /// Get the responses to the queries for `name` of each of `qtypes`, from
+    /// the cache or from the first server that settles them with an answer
+    /// or NXDOMAIN. Other responses are kept unless a later server settles
+    /// the query, and errors only until there is a response.
+    fn query_raw(&self, name: &str, qtypes: &[u16]) -> io::Result<Vec<io::Result<Response>>> {
+        if self.nameservers.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "no DNS servers to query",
+            ));
+        }
+        let mut results: Vec<Option<io::Result<Response>>> = qtypes
+            .iter()
+            .map(|&qtype| {
+                let cache = self.cache.as_ref()?;
+                cache.get(name, qtype).map(Ok)
+            })
+            .collect();
+
+        'rounds: for _ in 0..self.options.attempts.max(1) {
+            for server in &self.nameservers {
+                let pending: Vec<usize> = (0..qtypes.len())
+                    .filter(|&i| !is_settled(&results[i]))
+                    .collect();
+                if pending.is_empty() {
+                    break 'rounds;
+                }
+                let pending_qtypes: Vec<u16> = pending.iter().map(|&i| qtypes[i]).collect();
+                let answers = match self.exchange(*server, name, &pending_qtypes) {
+                    Ok(answers) => answers,
+                    Err(e) => pending.iter().map(|_| Err(clone_error(&e))).collect(),
+                };
+                for (i, answer) in pending.into_iter().zip(answers) {
+                    // a response to another question is no answer, and must
+                    // not reach the cache
+                    let answer = answer.and_then(|(response, id)| {
+                        dns::check_response(&response, id, name, qtypes[i])?;
+                        Ok((response, id))
+                    });
+                    if let (Ok((response, id)), Some(cache)) = (&answer, &self.cache) {
+                        if dns::is_final(response) {
+                            cache.insert(name, qtypes[i], response, *id);
+                        }
+                    }
+                    // a response, even a failure another server may better,
+                    // says more than a later error or timeout
+                    if answer.is_err() && matches!(results[i], Some(Ok(_))) {
+                        continue;
+                    }
+                    results[i] = Some(answer);
+                }
+            }
+        }
+        Ok(results
+            .into_iter()
+            .map(|r| r.unwrap_or_else(|| Err(WasiErrno::Timedout.into())))
+            .collect())
+    }
+
+    fn exchange(
+        &self,
+        server: SocketAddr,
+        name: &str,
+        qtypes: &[u16],
+    ) -> io::Result<Vec<io::Result<Response>>> {
+        #[cfg(feature = "tls")]
+        if matches!(self.transport, Transport::Tls | Transport::Https(_)) {
+            let tls = self.tls.as_deref().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "TLS transport without TLS settings",
+                )
+            })?;
+            return tls::exchange_many(server, name, qtypes, self.transport, tls, &self.options);
+        }
+        dns::exchange_many(server, name, qtypes, self.transport, &self.options)
+    }
+}
+
+// whether another server could give a better answer
+fn is_settled(result: &Option<io::Result<Response>>) -> bool {
+    matches!(result, Some(Ok((response, _))) if dns::is_final(response))
+}
+
+pub(crate) fn clone_error(e: &io::Error) -> io::Error {
+    match WasiErrno::from_io_error(e) {
+        Some(errno) => errno.into(),
+        None => io::Error::new(e.kind(), e.to_string()),
+    }
+}
+
+/// Parse `1.1.1.1`, `1.1.1.1:53`, `::1` or `[::1]:53`.
+fn parse_nameserver(s: &str) -> io::Result<SocketAddr> {
+    if let Ok(addr) = s.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    match s.parse::<IpAddr>() {
+        Ok(ip) => Ok((ip, 53).into()),
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid DNS server address {:?}", s),
+        )),
+    }
+}
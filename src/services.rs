@@ -0,0 +1,148 @@
+//! Service names, such as `https`, and the ports they stand for.
+
+use crate::socket::AiProtocol;
+use crate::WasiErrno;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+// (name, port, aliases) of well known services, each over both TCP and UDP
+// unless noted in `TCP_ONLY` or `UDP_ONLY`
+const WELL_KNOWN: &[(&str, u16, &[&str])] = &[
+    ("echo", 7, &[]),
+    ("ftp", 21, &[]),
+    ("ssh", 22, &[]),
+    ("telnet", 23, &[]),
+    ("smtp", 25, &["mail"]),
+    ("domain", 53, &["dns"]),
+    ("http", 80, &["www"]),
+    ("pop3", 110, &[]),
+    ("ntp", 123, &[]),
+    ("imap", 143, &["imap2"]),
+    ("snmp", 161, &[]),
+    ("ldap", 389, &[]),
+    ("https", 443, &[]),
+    ("submission", 587, &[]),
+    ("ldaps", 636, &[]),
+    ("imaps", 993, &[]),
+    ("pop3s", 995, &[]),
+    ("mqtt", 1883, &[]),
+    ("mysql", 3306, &[]),
+    ("postgresql", 5432, &["postgres"]),
+    ("amqp", 5672, &[]),
+    ("redis", 6379, &[]),
+    ("http-alt", 8080, &["webcache"]),
+    ("memcache", 11211, &["memcached"]),
+];
+const TCP_ONLY: &[&str] = &["ftp", "telnet", "submission", "imaps", "pop3s", "redis"];
+const UDP_ONLY: &[&str] = &["ntp", "snmp"];
+
+/// A table of service names, in the format of `/etc/services`.
+///
+/// Each line holds a name, a `port/protocol` pair and any aliases, and `#`
+/// starts a comment. Only the `tcp` and `udp` protocols are kept.
+#[derive(Clone, Debug, Default)]
+pub struct Services {
+    entries: Vec<(String, u16, AiProtocol)>,
+}
+
+impl Services {
+    /// An empty table.
+    pub fn new() -> Self {
+        Services::default()
+    }
+
+    /// The built-in table of well known services, used by every
+    /// [`Resolver`](crate::Resolver) that has not been given another one.
+    pub fn standard() -> Arc<Services> {
+        static STANDARD: OnceLock<Arc<Services>> = OnceLock::new();
+        STANDARD
+            .get_or_init(|| {
+                let mut services = Services::new();
+                for &(name, port, aliases) in WELL_KNOWN {
+                    for &protocol in &[AiProtocol::IPProtoTCP, AiProtocol::IPProtoUDP] {
+                        let excluded = match protocol {
+                            AiProtocol::IPProtoTCP => UDP_ONLY,
+                            _ => TCP_ONLY,
+                        };
+                        if excluded.contains(&name) {
+                            continue;
+                        }
+                        for name in std::iter::once(&name).chain(aliases) {
+                            services.insert(name, port, protocol);
+                        }
+                    }
+                }
+                Arc::new(services)
+            })
+            .clone()
+    }
+
+    /// Parse the contents of a services file.
+    pub fn parse(text: &str) -> Self {
+        let mut services = Services::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut fields = line.split_whitespace();
+            let (name, port) = match (fields.next(), fields.next()) {
+                (Some(name), Some(port)) => (name, port),
+                _ => continue,
+            };
+            let (port, protocol) = match port.split_once('/') {
+                Some((port, "tcp")) => (port, AiProtocol::IPProtoTCP),
+                Some((port, "udp")) => (port, AiProtocol::IPProtoUDP),
+                _ => continue,
+            };
+            let port = match port.parse::<u16>() {
+                Ok(port) => port,
+                Err(_) => continue,
+            };
+            for name in std::iter::once(name).chain(fields) {
+                services.insert(name, port, protocol);
+            }
+        }
+        services
+    }
+
+    /// Read a services file. On WASI, its directory must be preopened.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Services::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// Map `name` to `port` over `protocol`. Earlier entries win.
+    pub fn insert(&mut self, name: &str, port: u16, protocol: AiProtocol) {
+        self.entries
+            .push((name.to_ascii_lowercase(), port, protocol));
+    }
+
+    /// Like [`insert`](Self::insert), for building a table in code.
+    pub fn with(mut self, name: &str, port: u16, protocol: AiProtocol) -> Self {
+        self.insert(name, port, protocol);
+        self
+    }
+
+    /// The port of the service `name` over `protocol`. `IPProtoIP` matches
+    /// either protocol.
+    pub fn port(&self, name: &str, protocol: AiProtocol) -> Option<u16> {
+        self.entries
+            .iter()
+            .find(|(n, _, p)| {
+                n.eq_ignore_ascii_case(name)
+                    && (protocol == AiProtocol::IPProtoIP || *p == protocol)
+            })
+            .map(|&(_, port, _)| port)
+    }
+
+    /// The port `service` stands for: a number, or a name in the table.
+    /// An empty service is port 0.
+    pub fn resolve(&self, service: &str, protocol: AiProtocol) -> io::Result<u16> {
+        if service.is_empty() {
+            return Ok(0);
+        }
+        if let Ok(port) = service.parse::<u16>() {
+            return Ok(port);
+        }
+        self.port(service, protocol)
+            .ok_or_else(|| WasiErrno::AiService.into())
+    }
+}
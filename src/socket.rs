@@ -1,3 +1,4 @@
+use crate::errno::{io_error, WasiErrno};
 use std::io;
 use std::mem::MaybeUninit;
 use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, SocketAddrV4, SocketAddrV6};
@@ -36,7 +37,7 @@ impl AddressFamily {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(u8, align(1))]
 pub enum SocketType {
     Any,
@@ -53,19 +54,89 @@ pub struct WasiAddress {
 
 unsafe impl Send for WasiAddress {}
 
-#[derive(Copy, Clone, Debug)]
-#[repr(u16, align(2))]
-pub enum AiFlags {
-    AiPassive,
-    AiCanonname,
-    AiNumericHost,
-    AiNumericServ,
-    AiV4Mapped,
-    AiAll,
-    AiAddrConfig,
+/// The `ai_flags` of getaddrinfo hints: a set of flags, combined with `|`.
+///
+/// ```ignore
+/// let flags = AiFlags::PASSIVE | AiFlags::CANONNAME;
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct AiFlags(u16);
+
+impl AiFlags {
+    /// Addresses to bind a listening socket to, rather than to connect to.
+    pub const PASSIVE: AiFlags = AiFlags(1 << 0);
+    /// Return the canonical name of the host with the first result.
+    pub const CANONNAME: AiFlags = AiFlags(1 << 1);
+    /// The node is an address; never ask a name server.
+    pub const NUMERICHOST: AiFlags = AiFlags(1 << 2);
+    /// The service is a port number; never look it up.
+    pub const NUMERICSERV: AiFlags = AiFlags(1 << 3);
+    /// Return IPv4 addresses as IPv4-mapped IPv6 addresses when IPv6 is asked
+    /// for and there are none.
+    pub const V4MAPPED: AiFlags = AiFlags(1 << 4);
+    /// With `V4MAPPED`, return the mapped IPv4 addresses as well as the IPv6
+    /// ones.
+    pub const ALL: AiFlags = AiFlags(1 << 5);
+    /// Return addresses of a family only if the host has an address of it.
+    pub const ADDRCONFIG: AiFlags = AiFlags(1 << 6);
+
+    #[deprecated(since = "0.6.0", note = "use `AiFlags::PASSIVE`")]
+    #[allow(non_upper_case_globals)]
+    pub const AiPassive: AiFlags = AiFlags::PASSIVE;
+    #[deprecated(since = "0.6.0", note = "use `AiFlags::CANONNAME`")]
+    #[allow(non_upper_case_globals)]
+    pub const AiCanonname: AiFlags = AiFlags::CANONNAME;
+    #[deprecated(since = "0.6.0", note = "use `AiFlags::NUMERICHOST`")]
+    #[allow(non_upper_case_globals)]
+    pub const AiNumericHost: AiFlags = AiFlags::NUMERICHOST;
+    #[deprecated(since = "0.6.0", note = "use `AiFlags::NUMERICSERV`")]
+    #[allow(non_upper_case_globals)]
+    pub const AiNumericServ: AiFlags = AiFlags::NUMERICSERV;
+    #[deprecated(since = "0.6.0", note = "use `AiFlags::V4MAPPED`")]
+    #[allow(non_upper_case_globals)]
+    pub const AiV4Mapped: AiFlags = AiFlags::V4MAPPED;
+    #[deprecated(since = "0.6.0", note = "use `AiFlags::ALL`")]
+    #[allow(non_upper_case_globals)]
+    pub const AiAll: AiFlags = AiFlags::ALL;
+    #[deprecated(since = "0.6.0", note = "use `AiFlags::ADDRCONFIG`")]
+    #[allow(non_upper_case_globals)]
+    pub const AiAddrConfig: AiFlags = AiFlags::ADDRCONFIG;
+
+    pub const fn empty() -> AiFlags {
+        AiFlags(0)
+    }
+
+    /// The flags as the host reads them.
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every flag of `other` is set.
+    pub const fn contains(self, other: AiFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
 }
 
-#[derive(Copy, Clone, Debug)]
+impl std::ops::BitOr for AiFlags {
+    type Output = AiFlags;
+
+    fn bitor(self, rhs: AiFlags) -> AiFlags {
+        AiFlags(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for AiFlags {
+    fn bitor_assign(&mut self, rhs: AiFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(u8, align(1))]
 pub enum AiProtocol {
     IPProtoIP,
@@ -115,10 +186,10 @@ pub struct WasiAddrinfo {
     pub ai_next: *mut WasiAddrinfo,
 }
 
-impl WasiAddrinfo {
-    pub fn default() -> WasiAddrinfo {
+impl Default for WasiAddrinfo {
+    fn default() -> WasiAddrinfo {
         WasiAddrinfo {
-            ai_flags: AiFlags::AiPassive,
+            ai_flags: AiFlags::empty(),
             ai_family: AddressFamily::Inet4,
             ai_socktype: SocketType::Stream,
             ai_protocol: AiProtocol::IPProtoTCP,
@@ -129,10 +200,13 @@ impl WasiAddrinfo {
             ai_next: std::ptr::null_mut(),
         }
     }
+}
 
+impl WasiAddrinfo {
     /// Get Address Information
     ///
     /// As calling FFI, use buffer as parameter in order to avoid memory leak.
+    #[deprecated(note = "use `socket::getaddrinfo`, which returns owned values")]
     pub fn get_addrinfo(
         node: &str,
         service: &str,
@@ -142,19 +216,6 @@ impl WasiAddrinfo {
         sockbuff: &mut Vec<[u8; 26]>,
         ai_canonname: &mut Vec<String>,
     ) -> io::Result<Vec<WasiAddrinfo>> {
-        #[link(wasm_import_module = "wasi_snapshot_preview1")]
-        extern "C" {
-            pub fn sock_getaddrinfo(
-                node: *const u8,
-                node_len: u32,
-                server: *const u8,
-                server_len: u32,
-                hint: *const WasiAddrinfo,
-                res: *mut u32,
-                max_len: u32,
-                res_len: *mut u32,
-            ) -> u32;
-        }
         let mut node = node.to_string();
         let mut service = service.to_string();
 
@@ -174,13 +235,15 @@ impl WasiAddrinfo {
 
         for i in 0..max_reslen {
             sockaddr[i].sa_data = sockbuff[i].as_mut_ptr();
+            // large enough for the port, flowinfo, address and scope id of IPv6
+            sockaddr[i].sa_data_len = sockbuff[i].len() as u32;
             wasiaddrinfo_array[i].ai_addr = &mut sockaddr[i];
             wasiaddrinfo_array[i].ai_canonname = ai_canonname[i].as_mut_ptr();
             if i > 0 {
                 wasiaddrinfo_array[i - 1].ai_next = &mut wasiaddrinfo_array[i];
             }
         }
-        let mut res = wasiaddrinfo_array.as_mut_ptr() as u32;
+        let mut res = wasiaddrinfo_array.as_mut_ptr();
 
         unsafe {
             let return_code = sock_getaddrinfo(
@@ -196,12 +259,257 @@ impl WasiAddrinfo {
 
             match return_code {
                 0 => Ok(wasiaddrinfo_array[..res_len as usize].to_vec()),
-                e => Err(std::io::Error::from_raw_os_error(e as i32)),
+                e => Err(io_error(e)),
             }
         }
     }
 }
 
+// the results asked for at first, doubled while the host fills them all
+const ADDRINFO_INITIAL: usize = 16;
+const ADDRINFO_MAX: usize = 1024;
+// NI_MAXHOST
+const CANONNAME_LEN: usize = 1025;
+
+/// The hints of a [`getaddrinfo`] call.
+///
+/// By default any address family, socket type and protocol is returned and
+/// no flags are set.
+///
+/// ```ignore
+/// let hints = Hints::new()
+///     .with_flags(AiFlags::PASSIVE | AiFlags::ADDRCONFIG)
+///     .with_family(AddressFamily::Inet6)
+///     .with_socktype(SocketType::Datagram)
+///     .with_protocol(AiProtocol::IPProtoUDP);
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct Hints {
+    pub flags: AiFlags,
+    pub family: AddressFamily,
+    pub socktype: SocketType,
+    pub protocol: AiProtocol,
+}
+
+impl Default for Hints {
+    fn default() -> Self {
+        Hints {
+            flags: AiFlags::empty(),
+            family: AddressFamily::Unspec,
+            socktype: SocketType::Any,
+            protocol: AiProtocol::IPProtoIP,
+        }
+    }
+}
+
+impl Hints {
+    pub fn new() -> Self {
+        Hints::default()
+    }
+
+    /// Replace the flags.
+    pub fn with_flags(mut self, flags: AiFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn with_family(mut self, family: AddressFamily) -> Self {
+        self.family = family;
+        self
+    }
+
+    pub fn with_socktype(mut self, socktype: SocketType) -> Self {
+        self.socktype = socktype;
+        self
+    }
+
+    pub fn with_protocol(mut self, protocol: AiProtocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+}
+
+impl From<&Hints> for WasiAddrinfo {
+    fn from(hints: &Hints) -> Self {
+        WasiAddrinfo {
+            ai_flags: hints.flags,
+            ai_family: hints.family,
+            ai_socktype: hints.socktype,
+            ai_protocol: hints.protocol,
+            ..WasiAddrinfo::default()
+        }
+    }
+}
+
+/// One result of [`getaddrinfo`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AddrInfo {
+    pub addr: SocketAddr,
+    pub socktype: SocketType,
+    pub protocol: AiProtocol,
+    /// The canonical name of the host, when [`AiFlags::CANONNAME`] asked for
+    /// it. Hosts return it with the first result only.
+    pub canonname: Option<String>,
+}
+
+/// Translate `node` and `service` to socket addresses, as `getaddrinfo(3)`
+/// does.
+///
+/// Unlike [`WasiAddrinfo::get_addrinfo`], the buffers the host writes to are
+/// owned by this function and every result is returned, however many there
+/// are. Results without an IPv4 or IPv6 address are skipped.
+pub fn getaddrinfo(
+    node: &str,
+    service: &str,
+    hints: &Hints,
+) -> io::Result<std::vec::IntoIter<AddrInfo>> {
+    let hints = WasiAddrinfo::from(hints);
+    let node = format!("{}\0", node.trim_end_matches('\0'));
+    let service = format!("{}\0", service.trim_end_matches('\0'));
+
+    let mut capacity = ADDRINFO_INITIAL;
+    loop {
+        let results = getaddrinfo_into(&node, &service, &hints, capacity)?;
+        if results.len() < capacity || capacity >= ADDRINFO_MAX {
+            return Ok(results
+                .into_iter()
+                .filter_map(|r| r.decode())
+                .collect::<Vec<_>>()
+                .into_iter());
+        }
+        capacity *= 2;
+    }
+}
+
+// What the host wrote for one result, copied out of the call's buffers.
+struct RawAddrinfo {
+    info: WasiAddrinfo,
+    family: AddressFamily,
+    sa_data: [u8; 26],
+    canonname: Vec<u8>,
+}
+
+// One `sock_getaddrinfo` call with room for `capacity` results. `node` and
+// `service` are nul terminated.
+fn getaddrinfo_into(
+    node: &str,
+    service: &str,
+    hints: &WasiAddrinfo,
+    capacity: usize,
+) -> io::Result<Vec<RawAddrinfo>> {
+    // Allocated once and never resized, so the pointers into them stay
+    // valid until the host has returned.
+    let mut sa_data = vec![[0u8; 26]; capacity];
+    let mut canonnames = vec![0u8; capacity * CANONNAME_LEN];
+    let mut sockaddrs = vec![WasiSockaddr::default(); capacity];
+    let mut infos = vec![WasiAddrinfo::default(); capacity];
+
+    let sa_data_ptr = sa_data.as_mut_ptr();
+    let canonnames_ptr = canonnames.as_mut_ptr();
+    let sockaddrs_ptr = sockaddrs.as_mut_ptr();
+    let infos_ptr = infos.as_mut_ptr();
+    for i in 0..capacity {
+        unsafe {
+            let sockaddr = &mut *sockaddrs_ptr.add(i);
+            sockaddr.sa_data = sa_data_ptr.add(i).cast();
+            sockaddr.sa_data_len = 26;
+
+            let info = &mut *infos_ptr.add(i);
+            info.ai_addr = sockaddrs_ptr.add(i);
+            info.ai_canonname = canonnames_ptr.add(i * CANONNAME_LEN);
+            info.ai_canonnamelen = CANONNAME_LEN as u32;
+            if i + 1 < capacity {
+                info.ai_next = infos_ptr.add(i + 1);
+            }
+        }
+    }
+
+    let mut res = infos_ptr;
+    let mut res_len: u32 = 0;
+    let return_code = unsafe {
+        sock_getaddrinfo(
+            node.as_ptr(),
+            node.len() as u32,
+            service.as_ptr(),
+            service.len() as u32,
+            hints as *const WasiAddrinfo,
+            &mut res,
+            capacity as u32,
+            &mut res_len,
+        )
+    };
+    if return_code != 0 {
+        return Err(io_error(return_code));
+    }
+
+    let res_len = (res_len as usize).min(capacity);
+    Ok((0..res_len)
+        .map(|i| {
+            let info = infos[i].clone();
+            let name = &canonnames[i * CANONNAME_LEN..(i + 1) * CANONNAME_LEN];
+            // hosts that write a name set its length; the buffer was zeroed
+            // for those that do not
+            let len = (info.ai_canonnamelen as usize).min(CANONNAME_LEN);
+            let name = &name[..len];
+            let len = name.iter().position(|&b| b == 0).unwrap_or(len);
+            RawAddrinfo {
+                family: sockaddrs[i].family,
+                sa_data: sa_data[i],
+                canonname: name[..len].to_vec(),
+                info,
+            }
+        })
+        .collect())
+}
+
+impl RawAddrinfo {
+    fn decode(self) -> Option<AddrInfo> {
+        if self.info.ai_addrlen == 0 {
+            return None;
+        }
+        let addr = decode_sa_data(self.family, &self.sa_data)?;
+        let canonname = if self.canonname.is_empty() {
+            None
+        } else {
+            Some(String::from_utf8_lossy(&self.canonname).into_owned())
+        };
+        Some(AddrInfo {
+            addr,
+            socktype: self.info.ai_socktype,
+            protocol: self.info.ai_protocol,
+            canonname,
+        })
+    }
+}
+
+// `sa_data` is the native sockaddr without its family: the port in network
+// byte order, then the IPv4 address, or the flow info, the IPv6 address and
+// the scope id. It differs from the address buffer of the other imports, see
+// `encode_address`.
+fn decode_sa_data(family: AddressFamily, sa_data: &[u8; 26]) -> Option<SocketAddr> {
+    let port = u16::from_be_bytes([sa_data[0], sa_data[1]]);
+    match family {
+        AddressFamily::Unspec => None,
+        AddressFamily::Inet4 => {
+            let ip = Ipv4Addr::new(sa_data[2], sa_data[3], sa_data[4], sa_data[5]);
+            Some(SocketAddr::V4(SocketAddrV4::new(ip, port)))
+        }
+        AddressFamily::Inet6 => {
+            // port, flowinfo, address, scope id
+            let flowinfo = u32::from_le_bytes([sa_data[2], sa_data[3], sa_data[4], sa_data[5]]);
+            let mut ip = [0u8; 16];
+            ip.copy_from_slice(&sa_data[6..22]);
+            let scope_id = u32::from_le_bytes([sa_data[22], sa_data[23], sa_data[24], sa_data[25]]);
+            Some(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(ip),
+                port,
+                flowinfo,
+                scope_id,
+            )))
+        }
+    }
+}
+
 #[repr(C)]
 pub struct IovecRead {
     pub buf: *mut u8,
@@ -232,10 +540,13 @@ impl From<libc::iovec> for IovecWrite {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 #[repr(u8, align(1))]
 pub enum SocketOptLevel {
     SolSocket = 0,
+    IpProtoIp = 1,
+    IpProtoIpv6 = 2,
+    IpProtoTcp = 3,
 }
 
 impl TryFrom<i32> for SocketOptLevel {
@@ -244,7 +555,10 @@ impl TryFrom<i32> for SocketOptLevel {
     fn try_from(value: i32) -> Result<Self, Self::Error> {
         match value {
             0 => Ok(Self::SolSocket),
-            _ => Err(io::Error::from_raw_os_error(libc::EINVAL)),
+            1 => Ok(Self::IpProtoIp),
+            2 => Ok(Self::IpProtoIpv6),
+            3 => Ok(Self::IpProtoTcp),
+            _ => Err(WasiErrno::Inval.into()),
         }
     }
 }
@@ -267,6 +581,116 @@ pub enum SocketOptName {
     SoSndtimeo = 12,
     SoAcceptconn = 13,
     SoBindToDevice = 14,
+    /// Join an IPv4 multicast group, with an [`IpMreq`].
+    IpAddMembership = 15,
+    IpDropMembership = 16,
+    IpMulticastTtl = 17,
+    IpMulticastLoop = 18,
+    /// Join an IPv6 multicast group, with an [`Ipv6Mreq`].
+    Ipv6JoinGroup = 19,
+    Ipv6LeaveGroup = 20,
+    Ipv6MulticastHops = 21,
+    Ipv6MulticastLoop = 22,
+    IpTtl = 23,
+    IpTos = 24,
+    /// Whether an IPv6 socket is limited to IPv6, rather than also accepting
+    /// IPv4 peers as IPv4-mapped addresses. Set before `bind`.
+    Ipv6V6only = 25,
+    Ipv6UnicastHops = 26,
+    TcpNodelay = 27,
+    /// Seconds a connection is idle before the first keepalive probe.
+    TcpKeepidle = 28,
+    /// Seconds between keepalive probes.
+    TcpKeepintvl = 29,
+    /// Unanswered probes before the connection is dropped.
+    TcpKeepcnt = 30,
+}
+
+impl SocketOptName {
+    /// The level the option belongs to.
+    pub fn level(self) -> SocketOptLevel {
+        match self as u8 {
+            15..=18 | 23 | 24 => SocketOptLevel::IpProtoIp,
+            19..=22 | 25 | 26 => SocketOptLevel::IpProtoIpv6,
+            27..=30 => SocketOptLevel::IpProtoTcp,
+            _ => SocketOptLevel::SolSocket,
+        }
+    }
+}
+
+/// The value of [`SocketOptName::IpAddMembership`] and
+/// [`SocketOptName::IpDropMembership`], laid out as `struct ip_mreq`.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct IpMreq {
+    pub multiaddr: [u8; 4],
+    /// The address of the interface, or `0.0.0.0` to let the host choose.
+    pub interface: [u8; 4],
+}
+
+/// The value of [`SocketOptName::Ipv6JoinGroup`] and
+/// [`SocketOptName::Ipv6LeaveGroup`], laid out as `struct ipv6_mreq`.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct Ipv6Mreq {
+    pub multiaddr: [u8; 16],
+    /// The index of the interface, or 0 to let the host choose.
+    pub interface: u32,
+}
+
+/// How a TCP connection with keepalive enabled probes an idle peer. Fields
+/// left as `None` are not changed; the host's defaults are two hours, 75
+/// seconds and 9 probes on Linux.
+///
+/// ```ignore
+/// let keepalive = TcpKeepalive::new()
+///     .with_time(Duration::from_secs(60))
+///     .with_interval(Duration::from_secs(10))
+///     .with_retries(3);
+/// stream.set_keepalive(Some(&keepalive))?;
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TcpKeepalive {
+    /// How long the connection is idle before the first probe.
+    pub time: Option<Duration>,
+    /// The time between probes.
+    pub interval: Option<Duration>,
+    /// How many unanswered probes drop the connection.
+    pub retries: Option<u32>,
+}
+
+impl TcpKeepalive {
+    pub fn new() -> Self {
+        TcpKeepalive::default()
+    }
+
+    pub fn with_time(mut self, time: Duration) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = Some(retries);
+        self
+    }
+}
+
+/// Whole seconds for a keepalive option, rounded up. Linux takes 1 to 32767.
+fn keepalive_secs(duration: Duration) -> io::Result<i32> {
+    let secs = duration.as_secs() + (duration.subsec_nanos() > 0) as u64;
+    if (1..=32767).contains(&secs) {
+        Ok(secs as i32)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "keepalive times must be between 1 and 32767 seconds",
+        ))
+    }
 }
 
 impl TryFrom<i32> for SocketOptName {
@@ -289,8 +713,24 @@ impl TryFrom<i32> for SocketOptName {
             12 => Ok(Self::SoSndtimeo),
             13 => Ok(Self::SoAcceptconn),
             14 => Ok(Self::SoBindToDevice),
-
-            _ => Err(io::Error::from_raw_os_error(libc::EINVAL)),
+            15 => Ok(Self::IpAddMembership),
+            16 => Ok(Self::IpDropMembership),
+            17 => Ok(Self::IpMulticastTtl),
+            18 => Ok(Self::IpMulticastLoop),
+            19 => Ok(Self::Ipv6JoinGroup),
+            20 => Ok(Self::Ipv6LeaveGroup),
+            21 => Ok(Self::Ipv6MulticastHops),
+            22 => Ok(Self::Ipv6MulticastLoop),
+            23 => Ok(Self::IpTtl),
+            24 => Ok(Self::IpTos),
+            25 => Ok(Self::Ipv6V6only),
+            26 => Ok(Self::Ipv6UnicastHops),
+            27 => Ok(Self::TcpNodelay),
+            28 => Ok(Self::TcpKeepidle),
+            29 => Ok(Self::TcpKeepintvl),
+            30 => Ok(Self::TcpKeepcnt),
+
+            _ => Err(WasiErrno::Inval.into()),
         }
     }
 }
@@ -300,47 +740,62 @@ pub const MSG_WAITALL: u16 = 2; // __WASI_RIFLAGS_RECV_WAITALL
 
 pub const MSG_TRUNC: u16 = 1; // __WASI_ROFLAGS_RECV_DATA_TRUNCATED
 
-macro_rules! syscall {
-    ($fn: ident ( $($arg: expr),* $(,)* ) ) => {{
-        #[allow(unused_unsafe)]
-        let res = unsafe { libc::$fn($($arg, )*) };
-        if res == -1 {
-            Err(std::io::Error::last_os_error())
-        } else {
-            Ok(res)
-        }
-    }};
+pub const FDFLAGS_NONBLOCK: u16 = 1 << 2; // __WASI_FDFLAGS_NONBLOCK
+
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct Fdstat {
+    pub fs_filetype: u8,
+    pub fs_flags: u16,
+    pub fs_rights_base: u64,
+    pub fs_rights_inheriting: u64,
 }
 
-fn fcntl_get(fd: RawFd, cmd: i32) -> io::Result<i32> {
-    syscall!(fcntl(fd, cmd))
+fn fdflags_get(fd: RawFd) -> io::Result<u16> {
+    let mut stat = Fdstat::default();
+    let res = unsafe { fd_fdstat_get(fd as u32, &mut stat) };
+    if res == 0 {
+        Ok(stat.fs_flags)
+    } else {
+        Err(io_error(res))
+    }
 }
 
-fn fcntl_add(fd: RawFd, get_cmd: i32, set_cmd: i32, flag: i32) -> io::Result<()> {
-    let previous = syscall!(fcntl(fd, get_cmd))?;
+fn fdflags_set(fd: RawFd, flags: u16) -> io::Result<()> {
+    let res = unsafe { fd_fdstat_set_flags(fd as u32, flags) };
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(io_error(res))
+    }
+}
+
+fn fdflags_add(fd: RawFd, flag: u16) -> io::Result<()> {
+    let previous = fdflags_get(fd)?;
     let new = previous | flag;
     if new != previous {
-        syscall!(fcntl(fd, set_cmd, new)).map(|_| ())
+        fdflags_set(fd, new)
     } else {
         // Flag was already set.
         Ok(())
     }
 }
 
-/// Remove `flag` to the current set flags of `F_GETFD`.
-fn fcntl_remove(fd: RawFd, get_cmd: i32, set_cmd: i32, flag: i32) -> io::Result<()> {
-    let previous = syscall!(fcntl(fd, get_cmd))?;
+/// Remove `flag` from the current fd flags.
+fn fdflags_remove(fd: RawFd, flag: u16) -> io::Result<()> {
+    let previous = fdflags_get(fd)?;
     let new = previous & !flag;
     if new != previous {
-        syscall!(fcntl(fd, set_cmd, new)).map(|_| ())
+        fdflags_set(fd, new)
     } else {
-        // Flag was already set.
+        // Flag was already unset.
         Ok(())
     }
 }
 
+#[cfg(all(target_os = "wasi", not(wasmedge_socket_mock)))]
 mod wasi_sock {
-    use super::{IovecRead, IovecWrite, WasiAddress};
+    use super::{Fdstat, IovecRead, IovecWrite, WasiAddress, WasiAddrinfo};
 
     #[link(wasm_import_module = "wasi_snapshot_preview1")]
     extern "C" {
@@ -413,9 +868,28 @@ mod wasi_sock {
             flag: *const i32,
             flag_size: u32,
         ) -> u32;
+        pub fn sock_getaddrinfo(
+            node: *const u8,
+            node_len: u32,
+            server: *const u8,
+            server_len: u32,
+            hint: *const WasiAddrinfo,
+            res: *mut *mut WasiAddrinfo,
+            max_len: u32,
+            res_len: *mut u32,
+        ) -> u32;
+        pub fn fd_fdstat_get(fd: u32, stat: *mut Fdstat) -> u32;
+        pub fn fd_fdstat_set_flags(fd: u32, flags: u16) -> u32;
+        pub fn fd_close(fd: u32) -> u32;
     }
 }
 
+#[cfg(all(not(target_os = "wasi"), not(wasmedge_socket_mock)))]
+use crate::native as wasi_sock;
+
+#[cfg(wasmedge_socket_mock)]
+use crate::mock::host as wasi_sock;
+
 #[derive(Debug)]
 pub struct Socket {
     fd: RawFd,
@@ -429,7 +903,7 @@ fn into_timeval(duration: Option<Duration>) -> libc::timeval {
         // https://github.com/rust-lang/libc/issues/1848
         #[cfg_attr(target_env = "musl", allow(deprecated))]
         Some(duration) => libc::timeval {
-            tv_sec: duration.as_secs().min(libc::time_t::max_value() as u64) as libc::time_t,
+            tv_sec: duration.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
             tv_usec: duration.subsec_micros() as libc::suseconds_t,
         },
         None => libc::timeval {
@@ -449,6 +923,108 @@ fn from_timeval(duration: libc::timeval) -> Option<Duration> {
     }
 }
 
+/// Size of the `sockaddr_storage`-like address buffer used by the host.
+const ADDRESS_STORAGE_LEN: usize = 128;
+
+// WasmEdge's `sock_*` imports take an address buffer of 4 or 16 bytes, the
+// raw octets, or of 128 bytes. A 128 byte buffer, which `sock_recv_from` also
+// fills in, starts with the address family as a little endian `u16`, followed
+// by the 4 or 16 octets; the port is passed separately. WasmEdge reads and
+// writes only those bytes. The flow info and the scope id of an IPv6 address
+// follow the octets as little endian `u32`s, at 18..22 and 22..26, for hosts
+// that use them, such as the native backend.
+//
+// This is not the layout of the `sa_data` that `sock_getaddrinfo` returns,
+// see `decode_sa_data`.
+
+/// Encode `addr` into the address buffer passed to `sock_bind`,
+/// `sock_connect` and `sock_send_to`, returning the buffer and its used size.
+/// An IPv6 address with a flow info or a scope id needs the 128 byte form, so
+/// that link-local destinations keep their interface.
+///
+/// WasmEdge ignores the scope id, and would use whatever interface it picks
+/// for a link-local address, so a scope id fails there with `Unsupported`.
+fn encode_address(addr: &SocketAddr) -> io::Result<([u8; ADDRESS_STORAGE_LEN], usize)> {
+    let mut buf = [0u8; ADDRESS_STORAGE_LEN];
+    match addr {
+        SocketAddr::V4(addr) => {
+            buf[0..4].copy_from_slice(&addr.ip().octets());
+            Ok((buf, 4))
+        }
+        SocketAddr::V6(addr) if addr.flowinfo() == 0 && addr.scope_id() == 0 => {
+            buf[0..16].copy_from_slice(&addr.ip().octets());
+            Ok((buf, 16))
+        }
+        SocketAddr::V6(addr)
+            if cfg!(all(target_os = "wasi", not(wasmedge_socket_mock))) && addr.scope_id() != 0 =>
+        {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("WasmEdge does not support the scope id of {}", addr),
+            ))
+        }
+        SocketAddr::V6(addr) => {
+            buf[0..2].copy_from_slice(&(AddressFamily::Inet6 as u16).to_le_bytes());
+            buf[2..18].copy_from_slice(&addr.ip().octets());
+            buf[18..22].copy_from_slice(&addr.flowinfo().to_le_bytes());
+            buf[22..26].copy_from_slice(&addr.scope_id().to_le_bytes());
+            Ok((buf, ADDRESS_STORAGE_LEN))
+        }
+    }
+}
+
+/// Decode a 128 byte address buffer filled in by the host, the reverse of
+/// [`encode_address`].
+fn decode_address(buf: &[u8; ADDRESS_STORAGE_LEN], port: u16) -> io::Result<SocketAddr> {
+    let u32_at = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+    match u16::from_le_bytes([buf[0], buf[1]]) {
+        f if f == AddressFamily::Inet4 as u16 => {
+            let ip = Ipv4Addr::new(buf[2], buf[3], buf[4], buf[5]);
+            Ok(SocketAddr::V4(SocketAddrV4::new(ip, port)))
+        }
+        f if f == AddressFamily::Inet6 as u16 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&buf[2..18]);
+            Ok(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(octets),
+                port,
+                u32_at(18),
+                u32_at(22),
+            )))
+        }
+        _ => Err(WasiErrno::Afnosupport.into()),
+    }
+}
+
+fn check_level(level: SocketOptLevel, name: SocketOptName) -> io::Result<()> {
+    if level == name.level() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} is not a {:?} option", name, level),
+        ))
+    }
+}
+
+/// Hosts report options they do not know with `ENOPROTOOPT` or `ENOTSUP`.
+/// WasmEdge only knows the `SolSocket` level, and rejects the others as
+/// invalid, so there `EINVAL` at another level means the option is not
+/// supported. Native hosts have every level, and `EINVAL` is a bad value;
+/// the typed setters check the ranges they know before asking the host.
+fn sockopt_error(e: u32, level: SocketOptLevel, name: SocketOptName) -> io::Error {
+    let without_ip_levels = cfg!(any(target_os = "wasi", wasmedge_socket_mock));
+    match WasiErrno::from_raw(e) {
+        Some(WasiErrno::Noprotoopt | WasiErrno::Notsup) => {}
+        Some(WasiErrno::Inval) if without_ip_levels && level != SocketOptLevel::SolSocket => {}
+        _ => return io_error(e),
+    }
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("the host does not support the {:?} socket option", name),
+    )
+}
+
 impl Socket {
     pub fn new(addr_family: AddressFamily, sock_kind: SocketType) -> io::Result<Self> {
         unsafe {
@@ -457,7 +1033,7 @@ impl Socket {
             if res == 0 {
                 Ok(Socket { fd: fd as i32 })
             } else {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             }
         }
     }
@@ -484,7 +1060,7 @@ impl Socket {
                 Ok(Some(unsafe { &*(buf as *const [_] as *const [u8]) }.into()))
             }
         } else {
-            Err(io::Error::from_raw_os_error(e as i32))
+            Err(io_error(e))
         }
     }
 
@@ -506,7 +1082,7 @@ impl Socket {
             if e == 0 {
                 Ok(())
             } else {
-                Err(io::Error::from_raw_os_error(e as i32))
+                Err(io_error(e))
             }
         }
     }
@@ -536,7 +1112,7 @@ impl Socket {
             if e == 0 {
                 Ok(from_timeval(payload.assume_init()))
             } else {
-                Err(io::Error::from_raw_os_error(e as i32))
+                Err(io_error(e))
             }
         }
     }
@@ -566,11 +1142,66 @@ impl Socket {
             if e == 0 {
                 Ok(from_timeval(payload.assume_init()))
             } else {
-                Err(io::Error::from_raw_os_error(e as i32))
+                Err(io_error(e))
             }
         }
     }
 
+    /// The time to live of the IPv4 packets sent from this socket.
+    pub fn set_ttl(&self, ttl: u32) -> io::Result<()> {
+        if ttl == 0 || ttl > 255 {
+            return Err(WasiErrno::Inval.into());
+        }
+        self.setsockopt(SocketOptLevel::IpProtoIp, SocketOptName::IpTtl, ttl as i32)
+    }
+
+    pub fn ttl(&self) -> io::Result<u32> {
+        let ttl: i32 = self.getsockopt(SocketOptLevel::IpProtoIp, SocketOptName::IpTtl)?;
+        Ok(ttl as u32)
+    }
+
+    /// The type of service byte of the IPv4 packets sent from this socket,
+    /// with the DSCP in its upper six bits.
+    pub fn set_tos(&self, tos: u8) -> io::Result<()> {
+        self.setsockopt(SocketOptLevel::IpProtoIp, SocketOptName::IpTos, tos as i32)
+    }
+
+    pub fn tos(&self) -> io::Result<u8> {
+        let tos: i32 = self.getsockopt(SocketOptLevel::IpProtoIp, SocketOptName::IpTos)?;
+        Ok(tos as u8)
+    }
+
+    /// The hop limit of the unicast IPv6 packets sent from this socket.
+    pub fn set_unicast_hops_v6(&self, hops: u32) -> io::Result<()> {
+        if hops == 0 || hops > 255 {
+            return Err(WasiErrno::Inval.into());
+        }
+        self.setsockopt(
+            SocketOptLevel::IpProtoIpv6,
+            SocketOptName::Ipv6UnicastHops,
+            hops as i32,
+        )
+    }
+
+    pub fn unicast_hops_v6(&self) -> io::Result<u32> {
+        let hops: i32 =
+            self.getsockopt(SocketOptLevel::IpProtoIpv6, SocketOptName::Ipv6UnicastHops)?;
+        Ok(hops as u32)
+    }
+
+    pub fn set_only_v6(&self, only_v6: bool) -> io::Result<()> {
+        self.setsockopt(
+            SocketOptLevel::IpProtoIpv6,
+            SocketOptName::Ipv6V6only,
+            only_v6 as i32,
+        )
+    }
+
+    pub fn only_v6(&self) -> io::Result<bool> {
+        let on: i32 = self.getsockopt(SocketOptLevel::IpProtoIpv6, SocketOptName::Ipv6V6only)?;
+        Ok(on != 0)
+    }
+
     pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
         unsafe {
             let mut send_len: u32 = 0;
@@ -582,7 +1213,7 @@ impl Socket {
             if res == 0 {
                 Ok(send_len as usize)
             } else {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             }
         }
     }
@@ -609,20 +1240,17 @@ impl Socket {
             if res == 0 {
                 Ok(send_len as usize)
             } else {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             }
         }
     }
 
     pub fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
         let port = addr.port() as u32;
-        let vaddr = match addr {
-            SocketAddr::V4(ipv4) => ipv4.ip().octets().to_vec(),
-            SocketAddr::V6(ipv6) => ipv6.ip().octets().to_vec(),
-        };
+        let (vaddr, size) = encode_address(&addr)?;
         let addr = WasiAddress {
             buf: vaddr.as_ptr(),
-            size: vaddr.len(),
+            size,
         };
 
         let vec = IovecWrite {
@@ -645,7 +1273,7 @@ impl Socket {
             if res == 0 {
                 Ok(send_len as usize)
             } else {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             }
         }
     }
@@ -657,13 +1285,10 @@ impl Socket {
         flags: u16,
     ) -> io::Result<usize> {
         let port = addr.port() as u32;
-        let vaddr = match addr {
-            SocketAddr::V4(ipv4) => ipv4.ip().octets().to_vec(),
-            SocketAddr::V6(ipv6) => ipv6.ip().octets().to_vec(),
-        };
+        let (vaddr, size) = encode_address(&addr)?;
         let addr = WasiAddress {
             buf: vaddr.as_ptr(),
-            size: vaddr.len(),
+            size,
         };
 
         let mut write_bufs = Vec::with_capacity(bufs.len());
@@ -688,7 +1313,7 @@ impl Socket {
             if res == 0 {
                 Ok(send_len as usize)
             } else {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             }
         }
     }
@@ -714,7 +1339,7 @@ impl Socket {
             if res == 0 {
                 Ok(recv_len)
             } else {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             }
         }
     }
@@ -743,7 +1368,7 @@ impl Socket {
             if res == 0 {
                 Ok((recv_len, oflags))
             } else {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             }
         }
     }
@@ -764,7 +1389,7 @@ impl Socket {
             if res == 0 {
                 Ok((recv_len, oflags))
             } else {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             }
         }
     }
@@ -798,26 +1423,11 @@ impl Socket {
                 &mut oflags,
             );
             if res == 0 {
-                let sin_family = {
-                    let mut d = [0, 0];
-                    d.clone_from_slice(&addr_buf[0..2]);
-                    u16::from_le_bytes(d) as u8
-                };
-                let sin_addr = if sin_family == AddressFamily::Inet4 as u8 {
-                    let ip_addr = Ipv4Addr::new(addr_buf[2], addr_buf[3], addr_buf[4], addr_buf[5]);
-                    SocketAddr::V4(SocketAddrV4::new(ip_addr, sin_port as u16))
-                } else if sin_family == AddressFamily::Inet6 as u8 {
-                    let mut ipv6_addr = [0u8; 16];
-                    ipv6_addr.copy_from_slice(&addr_buf[2..18]);
-                    let ip_addr = Ipv6Addr::from(ipv6_addr);
-                    SocketAddr::V6(SocketAddrV6::new(ip_addr, sin_port as u16, 0, 0))
-                } else {
-                    unimplemented!("Address family not supported by protocol");
-                };
+                let sin_addr = decode_address(&addr_buf, sin_port as u16)?;
 
                 Ok((recv_len, sin_addr))
             } else {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             }
         }
     }
@@ -854,26 +1464,11 @@ impl Socket {
                 &mut oflags,
             );
             if res == 0 {
-                let sin_family = {
-                    let mut d = [0, 0];
-                    d.clone_from_slice(&addr_buf[0..2]);
-                    u16::from_le_bytes(d) as u8
-                };
-                let sin_addr = if sin_family == AddressFamily::Inet4 as u8 {
-                    let ip_addr = Ipv4Addr::new(addr_buf[2], addr_buf[3], addr_buf[4], addr_buf[5]);
-                    SocketAddr::V4(SocketAddrV4::new(ip_addr, sin_port as u16))
-                } else if sin_family == AddressFamily::Inet6 as u8 {
-                    let mut ipv6_addr = [0u8; 16];
-                    ipv6_addr.copy_from_slice(&addr_buf[2..18]);
-                    let ip_addr = Ipv6Addr::from(ipv6_addr);
-                    SocketAddr::V6(SocketAddrV6::new(ip_addr, sin_port as u16, 0, 0))
-                } else {
-                    unimplemented!("Address family not supported by protocol");
-                };
+                let sin_addr = decode_address(&addr_buf, sin_port as u16)?;
 
                 Ok((recv_len, sin_addr, oflags))
             } else {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             }
         }
     }
@@ -905,64 +1500,46 @@ impl Socket {
                 &mut oflags,
             );
             if res == 0 {
-                let sin_family = {
-                    let mut d = [0, 0];
-                    d.clone_from_slice(&addr_buf[0..2]);
-                    u16::from_le_bytes(d) as u8
-                };
-                let sin_addr = if sin_family == AddressFamily::Inet4 as u8 {
-                    let ip_addr = Ipv4Addr::new(addr_buf[2], addr_buf[3], addr_buf[4], addr_buf[5]);
-                    SocketAddr::V4(SocketAddrV4::new(ip_addr, sin_port as u16))
-                } else if sin_family == AddressFamily::Inet6 as u8 {
-                    let mut ipv6_addr = [0u8; 16];
-                    ipv6_addr.copy_from_slice(&addr_buf[2..18]);
-                    let ip_addr = Ipv6Addr::from(ipv6_addr);
-                    SocketAddr::V6(SocketAddrV6::new(ip_addr, sin_port as u16, 0, 0))
-                } else {
-                    unimplemented!("Address family not supported by protocol");
-                };
+                let sin_addr = decode_address(&addr_buf, sin_port as u16)?;
 
                 Ok((recv_len, sin_addr, oflags))
             } else {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             }
         }
     }
 
     pub fn nonblocking(&self) -> io::Result<bool> {
         let fd = self.as_raw_fd();
-        let file_status_flags = fcntl_get(fd, libc::F_GETFL)?;
-        Ok((file_status_flags & libc::O_NONBLOCK) != 0)
+        let fd_flags = fdflags_get(fd)?;
+        Ok((fd_flags & FDFLAGS_NONBLOCK) != 0)
     }
 
     pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
         let fd = self.as_raw_fd();
         if nonblocking {
-            fcntl_add(fd, libc::F_GETFL, libc::F_SETFL, libc::O_NONBLOCK)
+            fdflags_add(fd, FDFLAGS_NONBLOCK)
         } else {
-            fcntl_remove(fd, libc::F_GETFL, libc::F_SETFL, libc::O_NONBLOCK)
+            fdflags_remove(fd, FDFLAGS_NONBLOCK)
         }
     }
 
+    /// Connect to `addrs`. On WasmEdge, an IPv6 address with a scope id, such
+    /// as `[fe80::1%2]:80`, fails with `Unsupported`, as the host would not
+    /// use the interface it names; the same holds for `bind` and `send_to`.
     pub fn connect(&self, addrs: &SocketAddr) -> io::Result<()> {
         let fd = self.as_raw_fd();
-        let vaddr;
-        let port;
-        if let SocketAddr::V4(addrs) = addrs {
-            vaddr = addrs.ip().octets();
-            port = addrs.port();
-        } else {
-            return Err(io::Error::from(io::ErrorKind::Unsupported));
-        }
+        let (vaddr, size) = encode_address(addrs)?;
+        let port = addrs.port();
         let mut addr = WasiAddress {
             buf: vaddr.as_ptr(),
-            size: 4,
+            size,
         };
 
         unsafe {
             let res = sock_connect(fd as u32, &mut addr, port as u32);
             if res != 0 {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             } else {
                 Ok(())
             }
@@ -972,30 +1549,15 @@ impl Socket {
     pub fn bind(&self, addrs: &SocketAddr) -> io::Result<()> {
         unsafe {
             let fd = self.as_raw_fd();
-            let mut vaddr: [u8; 16] = [0; 16];
-            let port;
-            let size;
-            match addrs {
-                SocketAddr::V4(addr) => {
-                    let ip = addr.ip().octets();
-                    (&mut vaddr[0..4]).clone_from_slice(&ip);
-                    port = addr.port();
-                    size = 4;
-                }
-                SocketAddr::V6(addr) => {
-                    let ip = addr.ip().octets();
-                    vaddr.clone_from_slice(&ip);
-                    port = addr.port();
-                    size = 16;
-                }
-            }
+            let (vaddr, size) = encode_address(addrs)?;
+            let port = addrs.port();
             let mut addr = WasiAddress {
                 buf: vaddr.as_ptr(),
                 size,
             };
             let res = sock_bind(fd as u32, &mut addr, port as u32);
             if res != 0 {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             } else {
                 Ok(())
             }
@@ -1007,7 +1569,7 @@ impl Socket {
             let fd = self.as_raw_fd();
             let res = sock_listen(fd as u32, backlog as u32);
             if res != 0 {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             } else {
                 Ok(())
             }
@@ -1019,7 +1581,7 @@ impl Socket {
             let mut fd: u32 = 0;
             let res = sock_accept(self.as_raw_fd() as u32, &mut fd);
             if res != 0 {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             } else {
                 let s = Socket { fd: fd as i32 };
                 s.set_nonblocking(nonblocking)?;
@@ -1040,7 +1602,7 @@ impl Socket {
             let mut port = 0;
             let res = sock_getlocaladdr(fd as u32, &mut addr, &mut addr_type, &mut port);
             if res != 0 {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             } else {
                 if addr_type == 4 || addr_type == AddressFamily::Inet4 as u8 as u32 {
                     let ip_addr = Ipv4Addr::new(addr_buf[0], addr_buf[1], addr_buf[2], addr_buf[3]);
@@ -1072,7 +1634,7 @@ impl Socket {
             let mut port = 0;
             let res = sock_getpeeraddr(fd as u32, &mut addr, &mut addr_type, &mut port);
             if res != 0 {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             } else {
                 if addr_type == 4 || addr_type == AddressFamily::Inet4 as u8 as u32 {
                     let ip_addr = Ipv4Addr::new(addr_buf[0], addr_buf[1], addr_buf[2], addr_buf[3]);
@@ -1107,9 +1669,9 @@ impl Socket {
             if res == 0 && error == 0 {
                 Ok(())
             } else if res == 0 && error != 0 {
-                Err(io::Error::from_raw_os_error(error))
+                Err(io_error(error as u32))
             } else {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             }
         }
     }
@@ -1127,7 +1689,7 @@ impl Socket {
                 &mut len,
             );
             if res != 0 {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             } else {
                 Ok(val != 0)
             }
@@ -1147,12 +1709,12 @@ impl Socket {
                 &mut len,
             );
             if res != 0 {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             } else {
                 match val {
                     1 => Ok(SocketType::Datagram),
                     2 => Ok(SocketType::Stream),
-                    _ => Err(io::Error::from_raw_os_error(libc::EINVAL)),
+                    _ => Err(WasiErrno::Inval.into()),
                 }
             }
         }
@@ -1171,13 +1733,85 @@ impl Socket {
                 &mut len,
             );
             if res != 0 {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             } else {
                 Ok(val != 0)
             }
         }
     }
 
+    pub fn set_keepalive(&self, keepalive: bool) -> io::Result<()> {
+        self.setsockopt(
+            SocketOptLevel::SolSocket,
+            SocketOptName::SoKeepalive,
+            keepalive as i32,
+        )
+    }
+
+    /// Set the probe times and count that are given, and then enable
+    /// keepalive. If the host does not support one of them, keepalive is
+    /// left as it was.
+    pub fn set_tcp_keepalive(&self, keepalive: &TcpKeepalive) -> io::Result<()> {
+        let time = keepalive.time.map(keepalive_secs).transpose()?;
+        let interval = keepalive.interval.map(keepalive_secs).transpose()?;
+        if let Some(retries) = keepalive.retries {
+            if !(1..=127).contains(&retries) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "keepalive retries must be between 1 and 127",
+                ));
+            }
+        }
+        if let Some(time) = time {
+            self.setsockopt(SocketOptLevel::IpProtoTcp, SocketOptName::TcpKeepidle, time)?;
+        }
+        if let Some(interval) = interval {
+            self.setsockopt(
+                SocketOptLevel::IpProtoTcp,
+                SocketOptName::TcpKeepintvl,
+                interval,
+            )?;
+        }
+        if let Some(retries) = keepalive.retries {
+            self.setsockopt(
+                SocketOptLevel::IpProtoTcp,
+                SocketOptName::TcpKeepcnt,
+                retries as i32,
+            )?;
+        }
+        self.set_keepalive(true)
+    }
+
+    /// The probe times and count in effect, whether keepalive is enabled or
+    /// not.
+    pub fn tcp_keepalive(&self) -> io::Result<TcpKeepalive> {
+        let time: i32 = self.getsockopt(SocketOptLevel::IpProtoTcp, SocketOptName::TcpKeepidle)?;
+        let interval: i32 =
+            self.getsockopt(SocketOptLevel::IpProtoTcp, SocketOptName::TcpKeepintvl)?;
+        let retries: i32 =
+            self.getsockopt(SocketOptLevel::IpProtoTcp, SocketOptName::TcpKeepcnt)?;
+        Ok(TcpKeepalive {
+            time: Some(Duration::from_secs(time as u64)),
+            interval: Some(Duration::from_secs(interval as u64)),
+            retries: Some(retries as u32),
+        })
+    }
+
+    /// Send small writes at once, instead of holding them back until earlier
+    /// data is acknowledged (Nagle's algorithm).
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.setsockopt(
+            SocketOptLevel::IpProtoTcp,
+            SocketOptName::TcpNodelay,
+            nodelay as i32,
+        )
+    }
+
+    pub fn nodelay(&self) -> io::Result<bool> {
+        let on: i32 = self.getsockopt(SocketOptLevel::IpProtoTcp, SocketOptName::TcpNodelay)?;
+        Ok(on != 0)
+    }
+
     pub fn keepalive(&self) -> io::Result<bool> {
         unsafe {
             let fd = self.fd;
@@ -1191,7 +1825,7 @@ impl Socket {
                 &mut len,
             );
             if res != 0 {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             } else {
                 Ok(val != 0)
             }
@@ -1211,7 +1845,7 @@ impl Socket {
                 &mut len,
             );
             if res != 0 {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             } else {
                 Ok(val as usize)
             }
@@ -1231,7 +1865,7 @@ impl Socket {
                 &mut len,
             );
             if res != 0 {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             } else {
                 Ok(val as usize)
             }
@@ -1251,7 +1885,7 @@ impl Socket {
                 &mut len,
             );
             if res != 0 {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             } else {
                 Ok(val != 0)
             }
@@ -1264,6 +1898,7 @@ impl Socket {
         name: SocketOptName,
         payload: T,
     ) -> io::Result<()> {
+        check_level(level, name)?;
         unsafe {
             let fd = self.fd as u32;
             let flag = &payload as *const T as *const i32;
@@ -1272,7 +1907,32 @@ impl Socket {
             if e == 0 {
                 Ok(())
             } else {
-                Err(io::Error::from_raw_os_error(e as i32))
+                Err(sockopt_error(e, level, name))
+            }
+        }
+    }
+
+    /// Read an option whose value is a `T`, such as an `i32` flag.
+    pub fn getsockopt<T: Copy + Default>(
+        &self,
+        level: SocketOptLevel,
+        name: SocketOptName,
+    ) -> io::Result<T> {
+        check_level(level, name)?;
+        unsafe {
+            let mut payload = T::default();
+            let mut len = std::mem::size_of::<T>() as u32;
+            let e = sock_getsockopt(
+                self.fd as u32,
+                level as u8 as i32,
+                name as u8 as i32,
+                &mut payload as *mut T as *mut i32,
+                &mut len,
+            );
+            if e == 0 {
+                Ok(payload)
+            } else {
+                Err(sockopt_error(e, level, name))
             }
         }
     }
@@ -1288,7 +1948,7 @@ impl Socket {
             if res == 0 {
                 Ok(())
             } else {
-                Err(io::Error::from_raw_os_error(res as i32))
+                Err(io_error(res))
             }
         }
     }
@@ -1297,7 +1957,7 @@ impl Socket {
 impl Drop for Socket {
     fn drop(&mut self) {
         let _ = self.shutdown(Shutdown::Both);
-        unsafe { libc::close(self.fd) };
+        unsafe { fd_close(self.fd as u32) };
     }
 }
 
@@ -0,0 +1,627 @@
+//! A small authoritative DNS server for tests.
+//!
+//! A [`StubServer`] answers queries over UDP and TCP from a [`Zone`] kept in
+//! memory, so that code using [`nslookup_with_dns_server`], [`resolve`] or a
+//! [`Resolver`] can be tested without a real DNS server. It can be told to
+//! answer some names with a [`Fault`].
+//!
+//! ```ignore
+//! let zone = Zone::new("test").with("web.test", Ipv4Addr::new(10, 0, 0, 1));
+//! let server = StubServer::bind("127.0.0.1:0", zone)?;
+//! let serving = server.clone();
+//! std::thread::spawn(move || serving.serve());
+//! server.inject("slow.test", Fault::Delay(Duration::from_secs(2)));
+//! let addrs = nslookup_with_dns_server(&server.addr().to_string(), "web.test", "80")?;
+//! ```
+//!
+//! [`nslookup_with_dns_server`]: crate::nslookup_with_dns_server
+//! [`resolve`]: crate::resolve
+//! [`Resolver`]: crate::Resolver
+
+use crate::dns::{encode_name, CLASS_IN, MAX_CNAME_CHAIN, UDP_BUFFER_LEN};
+use crate::poll::{poll, Subscription};
+use crate::records::encode_record;
+pub use crate::records::{Record, RecordData};
+use crate::{TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+const TYPE_CNAME: u16 = 5;
+const TYPE_SOA: u16 = 6;
+const TYPE_ANY: u16 = 255;
+
+const NOERROR: u8 = 0;
+const FORMERR: u8 = 1;
+const SERVFAIL: u8 = 2;
+const NXDOMAIN: u8 = 3;
+const REFUSED: u8 = 5;
+
+const HEADER_LEN: usize = 12;
+// the largest answer sent over UDP, as EDNS is not supported (RFC 1035)
+const UDP_MAX: usize = 512;
+
+/// The records of the names under an origin, such as `test`.
+///
+/// Names match without regard to case or a trailing dot. A name with a CNAME
+/// record is followed to its target while the target is in the zone. "No
+/// such name" and "no records of this type" answers carry an SOA record of
+/// the origin, whose TTL tells resolvers how long to cache them. Names
+/// outside the origin are refused.
+#[derive(Clone, Debug)]
+pub struct Zone {
+    origin: String,
+    ttl: u32,
+    negative_ttl: u32,
+    records: Vec<Record>,
+}
+
+impl Zone {
+    /// An empty zone for the names under `origin`; `.` holds every name.
+    ///
+    /// # Panics
+    ///
+    /// If `origin` is not a valid DNS name, or is too long to name the
+    /// `hostmaster` of its SOA record below it.
+    pub fn new(origin: &str) -> Self {
+        let zone = Zone {
+            origin: origin.trim_end_matches('.').to_ascii_lowercase(),
+            ttl: 60,
+            negative_ttl: 60,
+            records: vec![],
+        };
+        if let Err(e) = encode_name(&mut vec![], origin) {
+            panic!("{}", e);
+        }
+        if let Err(e) = encode_name(&mut vec![], &zone.child("hostmaster")) {
+            panic!("{}", e);
+        }
+        zone
+    }
+
+    /// The TTL of the records added with [`with`](Zone::with); 60 seconds by
+    /// default.
+    pub fn with_ttl(mut self, ttl: u32) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// How long negative answers may be cached; 60 seconds by default.
+    pub fn with_negative_ttl(mut self, ttl: u32) -> Self {
+        self.negative_ttl = ttl;
+        self
+    }
+
+    /// Add a record. It is refused if its name, or a name in its data, is not
+    /// a valid DNS name, or if its data does not fit in a record.
+    pub fn insert(&mut self, name: &str, ttl: u32, data: RecordData) -> io::Result<()> {
+        let record = Record {
+            name: name.to_string(),
+            ttl,
+            data,
+        };
+        encode_record(&mut vec![], &record, CLASS_IN)?;
+        self.records.push(record);
+        Ok(())
+    }
+
+    /// Add a record with the TTL of the zone.
+    ///
+    /// # Panics
+    ///
+    /// If [`insert`](Zone::insert) refuses the record.
+    pub fn with<D: Into<RecordData>>(mut self, name: &str, data: D) -> Self {
+        let ttl = self.ttl;
+        if let Err(e) = self.insert(name, ttl, data.into()) {
+            panic!("{}", e);
+        }
+        self
+    }
+
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// The answer to the DNS message `query`, before any truncation for
+    /// UDP. Messages that are not queries get no answer.
+    pub fn answer(&self, query: &[u8]) -> Option<Vec<u8>> {
+        if query.len() < HEADER_LEN || query[2] & 0x80 != 0 {
+            return None;
+        }
+        let (name, qtype, end) = match parse_question(query) {
+            Some(question) => question,
+            None => return Some(self.response(query, HEADER_LEN, FORMERR, vec![])),
+        };
+        if !self.contains(&name) {
+            return Some(self.response(query, end, REFUSED, vec![]));
+        }
+        let (rcode, answers) = self.lookup(&name, qtype);
+        Some(self.response(query, end, rcode, answers))
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        self.origin.is_empty()
+            || name == self.origin
+            || name.ends_with(&format!(".{}", self.origin))
+    }
+
+    fn lookup(&self, name: &str, qtype: u16) -> (u8, Vec<Record>) {
+        let mut answers = vec![];
+        let mut name = name.to_string();
+        for _ in 0..=MAX_CNAME_CHAIN {
+            if !self.contains(&name) {
+                // the chain leaves the zone, and the client goes on elsewhere
+                return (NOERROR, answers);
+            }
+            let owned: Vec<&Record> = self
+                .records
+                .iter()
+                .filter(|r| same_name(&r.name, &name))
+                .collect();
+            if owned.is_empty() {
+                // a name with records only below it exists, with no data
+                let suffix = format!(".{}", name.trim_end_matches('.').to_ascii_lowercase());
+                let exists = self.records.iter().any(|r| {
+                    r.name
+                        .trim_end_matches('.')
+                        .to_ascii_lowercase()
+                        .ends_with(&suffix)
+                });
+                return (if exists { NOERROR } else { NXDOMAIN }, answers);
+            }
+            let cname = owned.iter().find_map(|r| match &r.data {
+                RecordData::Cname(target) => Some((*r, target)),
+                _ => None,
+            });
+            match cname {
+                Some((record, target)) if qtype != TYPE_CNAME && qtype != TYPE_ANY => {
+                    answers.push(record.clone());
+                    name = target.clone();
+                }
+                _ => {
+                    answers.extend(
+                        owned
+                            .into_iter()
+                            .filter(|r| qtype == TYPE_ANY || r.data.type_code() == qtype)
+                            .cloned(),
+                    );
+                    return (NOERROR, answers);
+                }
+            }
+        }
+        // a chain too long or in a loop; the client gives up on it
+        (NOERROR, answers)
+    }
+
+    /// A response to the question of `query`, which ends at `end`.
+    fn response(&self, query: &[u8], end: usize, rcode: u8, answers: Vec<Record>) -> Vec<u8> {
+        let authority = if answers.is_empty() && (rcode == NOERROR || rcode == NXDOMAIN) {
+            vec![self.soa()]
+        } else {
+            vec![]
+        };
+        let mut out = query[..2].to_vec();
+        // QR and AA, with the opcode and RD of the query
+        out.push(0x84 | (query[2] & 0x79));
+        out.push(rcode);
+        out.extend_from_slice(&[0, (end > HEADER_LEN) as u8]);
+        out.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+        out.extend_from_slice(&(authority.len() as u16).to_be_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(&query[HEADER_LEN..end]);
+        for record in answers.iter().chain(&authority) {
+            encode_record(&mut out, record, CLASS_IN).expect("records are checked when added");
+        }
+        out
+    }
+
+    /// The name of `label` below the origin.
+    fn child(&self, label: &str) -> String {
+        match self.origin.as_str() {
+            "" => label.to_string(),
+            origin => format!("{}.{}", label, origin),
+        }
+    }
+
+    fn soa(&self) -> Record {
+        // both names were checked in new
+        let mut data = vec![];
+        encode_name(&mut data, &self.child("ns")).expect("checked in Zone::new");
+        encode_name(&mut data, &self.child("hostmaster")).expect("checked in Zone::new");
+        // serial, refresh, retry, expire and the negative TTL
+        for n in [1, 3600, 600, 86400, self.negative_ttl] {
+            data.extend_from_slice(&u32::to_be_bytes(n));
+        }
+        Record {
+            name: match self.origin.as_str() {
+                "" => ".".to_string(),
+                origin => origin.to_string(),
+            },
+            ttl: self.negative_ttl,
+            data: RecordData::Other(TYPE_SOA, data),
+        }
+    }
+}
+
+/// A way for a [`StubServer`] to misbehave when asked about a name.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// Answer that the name does not exist, whatever the zone holds.
+    NxDomain,
+    /// Answer with a server failure.
+    ServFail,
+    /// Answer UDP queries with an empty answer with the TC bit set, so that
+    /// the client asks again over TCP, where the answer is sent as usual.
+    Truncate,
+    /// Answer after a while. Other queries are answered in the meantime.
+    Delay(Duration),
+    /// Do not answer.
+    Drop,
+}
+
+/// A query received by a [`StubServer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Query {
+    pub name: String,
+    pub qtype: u16,
+    /// Whether the query came over TCP rather than UDP.
+    pub tcp: bool,
+}
+
+/// A DNS server answering from a [`Zone`], see the [module](self) docs.
+///
+/// Clones share the sockets, the zone and the faults, so that one clone can
+/// serve on another thread while the test changes what it answers.
+#[derive(Clone)]
+pub struct StubServer {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    udp: UdpSocket,
+    tcp: TcpListener,
+    addr: SocketAddr,
+    zone: Mutex<Zone>,
+    // the fault for each name, and how many more queries it is for
+    faults: Mutex<HashMap<String, (Fault, Option<usize>)>>,
+    queries: Mutex<Vec<Query>>,
+    serving: Mutex<Serving>,
+}
+
+#[derive(Default)]
+struct Serving {
+    conns: HashMap<u64, Conn>,
+    next_conn: u64,
+    delayed: Vec<(SystemTime, Reply)>,
+}
+
+struct Conn {
+    stream: TcpStream,
+    buf: Vec<u8>,
+}
+
+enum Reply {
+    Udp(SocketAddr, Vec<u8>),
+    Tcp(u64, Vec<u8>),
+}
+
+const UDP: u64 = 0;
+const LISTENER: u64 = 1;
+const TIMER: u64 = 2;
+const CONNS: u64 = 3;
+
+impl StubServer {
+    /// Listen for UDP and TCP queries on the same port of `addr`, which may
+    /// be 0 for any free port.
+    pub fn bind<A: ToSocketAddrs>(addr: A, zone: Zone) -> io::Result<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No address."))?;
+        // the port UDP is given may be taken for TCP
+        let mut attempts = if addr.port() == 0 { 8 } else { 1 };
+        let (udp, tcp, addr) = loop {
+            let udp = UdpSocket::bind(addr)?;
+            let local = udp.as_ref().get_local()?;
+            match TcpListener::bind(local, true) {
+                Ok(tcp) => break (udp, tcp, local),
+                Err(e) if attempts > 1 && e.kind() == io::ErrorKind::AddrInUse => attempts -= 1,
+                Err(e) => return Err(e),
+            }
+        };
+        udp.as_ref().set_nonblocking(true)?;
+        Ok(StubServer {
+            inner: Arc::new(Inner {
+                udp,
+                tcp,
+                addr,
+                zone: Mutex::new(zone),
+                faults: Mutex::new(HashMap::new()),
+                queries: Mutex::new(vec![]),
+                serving: Mutex::new(Serving::default()),
+            }),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.inner.addr
+    }
+
+    pub fn set_zone(&self, zone: Zone) {
+        *self.inner.zone.lock().unwrap() = zone;
+    }
+
+    /// Answer every query for `name` with `fault`, until the faults are
+    /// cleared.
+    pub fn inject(&self, name: &str, fault: Fault) {
+        self.inner
+            .faults
+            .lock()
+            .unwrap()
+            .insert(key_name(name), (fault, None));
+    }
+
+    /// Answer the next `times` queries for `name` with `fault`.
+    pub fn inject_times(&self, name: &str, fault: Fault, times: usize) {
+        self.inner
+            .faults
+            .lock()
+            .unwrap()
+            .insert(key_name(name), (fault, Some(times)));
+    }
+
+    pub fn clear_faults(&self) {
+        self.inner.faults.lock().unwrap().clear();
+    }
+
+    /// The queries received so far, oldest first.
+    pub fn queries(&self) -> Vec<Query> {
+        self.inner.queries.lock().unwrap().clone()
+    }
+
+    /// Answer queries until an error.
+    pub fn serve(&self) -> io::Result<()> {
+        loop {
+            self.step(None)?;
+        }
+    }
+
+    /// Wait up to `timeout` for queries, or until a delayed answer is due,
+    /// and answer what has arrived. A server in a single-threaded test can
+    /// be driven by calling this in turn with the code under test.
+    pub fn step(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let mut serving = self.inner.serving.lock().unwrap();
+        let mut wake = timeout.map(|t| SystemTime::now() + t);
+        for (at, _) in &serving.delayed {
+            wake = Some(wake.map_or(*at, |w| w.min(*at)));
+        }
+        let mut subs = vec![
+            Subscription::io(UDP, &self.inner.udp, true, false, None),
+            Subscription::io(LISTENER, &self.inner.tcp, true, false, None),
+        ];
+        for (id, conn) in &serving.conns {
+            subs.push(Subscription::io(
+                CONNS + id,
+                &conn.stream,
+                true,
+                false,
+                None,
+            ));
+        }
+        if let Some(at) = wake {
+            subs.push(Subscription::timeout(TIMER, at));
+        }
+        poll(&subs)?;
+
+        // everything is nonblocking, so whatever is ready is handled
+        // without looking at the events
+        self.receive(&mut serving)?;
+        self.accept(&mut serving)?;
+        self.read_conns(&mut serving);
+        self.send_due(&mut serving);
+        Ok(())
+    }
+
+    fn receive(&self, serving: &mut Serving) -> io::Result<()> {
+        let mut buf = [0u8; UDP_BUFFER_LEN];
+        loop {
+            let (n, from) = match self.inner.udp.recv_from(&mut buf) {
+                Ok(r) => r,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            if let Some((delay, mut response)) = self.handle(&buf[..n], false) {
+                if response.len() > UDP_MAX {
+                    response = truncated(&response);
+                }
+                if delay.is_zero() {
+                    let _ = self.inner.udp.send_to(&response, from);
+                } else {
+                    let at = SystemTime::now() + delay;
+                    serving.delayed.push((at, Reply::Udp(from, response)));
+                }
+            }
+        }
+    }
+
+    fn accept(&self, serving: &mut Serving) -> io::Result<()> {
+        loop {
+            match self.inner.tcp.accept(true) {
+                Ok((stream, _)) => {
+                    let id = serving.next_conn;
+                    serving.next_conn += 1;
+                    serving.conns.insert(
+                        id,
+                        Conn {
+                            stream,
+                            buf: vec![],
+                        },
+                    );
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn read_conns(&self, serving: &mut Serving) {
+        let Serving { conns, delayed, .. } = serving;
+        let mut closed = vec![];
+        for (&id, conn) in conns.iter_mut() {
+            let mut buf = [0u8; 4096];
+            let open = loop {
+                match conn.stream.read(&mut buf) {
+                    Ok(0) => break false,
+                    Ok(n) => conn.buf.extend_from_slice(&buf[..n]),
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break true,
+                    Err(_) => break false,
+                }
+            };
+            // each message is preceded by its length
+            while conn.buf.len() >= 2 {
+                let len = u16::from_be_bytes([conn.buf[0], conn.buf[1]]) as usize;
+                if conn.buf.len() < 2 + len {
+                    break;
+                }
+                let query: Vec<u8> = conn.buf.drain(..2 + len).skip(2).collect();
+                let (delay, response) = match self.handle(&query, true) {
+                    Some(reply) => reply,
+                    None => continue,
+                };
+                if !delay.is_zero() {
+                    let at = SystemTime::now() + delay;
+                    delayed.push((at, Reply::Tcp(id, response)));
+                } else if write_framed(&mut conn.stream, &response).is_err() {
+                    closed.push(id);
+                }
+            }
+            if !open {
+                closed.push(id);
+            }
+        }
+        for id in closed {
+            conns.remove(&id);
+        }
+    }
+
+    fn send_due(&self, serving: &mut Serving) {
+        let now = SystemTime::now();
+        let (due, later) = std::mem::take(&mut serving.delayed)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(at, _)| *at <= now);
+        serving.delayed = later;
+        for (_, reply) in due {
+            match reply {
+                Reply::Udp(to, response) => {
+                    let _ = self.inner.udp.send_to(&response, to);
+                }
+                Reply::Tcp(id, response) => {
+                    if let Some(conn) = serving.conns.get_mut(&id) {
+                        if write_framed(&mut conn.stream, &response).is_err() {
+                            serving.conns.remove(&id);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The answer to `query` and how long to wait before sending it, or
+    /// `None` for no answer.
+    fn handle(&self, query: &[u8], tcp: bool) -> Option<(Duration, Vec<u8>)> {
+        let zone = self.inner.zone.lock().unwrap();
+        let question = match parse_question(query) {
+            Some(question) if query[2] & 0x80 == 0 => question,
+            _ => return zone.answer(query).map(|r| (Duration::ZERO, r)),
+        };
+        let (name, qtype, end) = question;
+        self.inner.queries.lock().unwrap().push(Query {
+            name: name.clone(),
+            qtype,
+            tcp,
+        });
+        match self.take_fault(&name) {
+            Some(Fault::Drop) => None,
+            Some(Fault::NxDomain) => {
+                Some((Duration::ZERO, zone.response(query, end, NXDOMAIN, vec![])))
+            }
+            Some(Fault::ServFail) => {
+                Some((Duration::ZERO, zone.response(query, end, SERVFAIL, vec![])))
+            }
+            Some(Fault::Truncate) if !tcp => {
+                Some((Duration::ZERO, truncated(&zone.answer(query)?)))
+            }
+            Some(Fault::Delay(delay)) => Some((delay, zone.answer(query)?)),
+            _ => Some((Duration::ZERO, zone.answer(query)?)),
+        }
+    }
+
+    fn take_fault(&self, name: &str) -> Option<Fault> {
+        let mut faults = self.inner.faults.lock().unwrap();
+        let key = key_name(name);
+        let (fault, times) = faults.get_mut(&key)?;
+        let fault = *fault;
+        if let Some(times) = times {
+            *times -= 1;
+            if *times == 0 {
+                faults.remove(&key);
+            }
+        }
+        Some(fault)
+    }
+}
+
+fn write_framed(stream: &mut TcpStream, message: &[u8]) -> io::Result<()> {
+    let mut frame = (message.len() as u16).to_be_bytes().to_vec();
+    frame.extend_from_slice(message);
+    stream.write_all(&frame)
+}
+
+/// `message` with only its question, and the TC bit set.
+fn truncated(message: &[u8]) -> Vec<u8> {
+    let end = parse_question(message).map_or(HEADER_LEN, |(_, _, end)| end);
+    let mut out = message[..end].to_vec();
+    out[2] |= 0x02;
+    out[6..HEADER_LEN].fill(0);
+    out
+}
+
+/// The name and type of the question of `message`, and where it ends.
+fn parse_question(message: &[u8]) -> Option<(String, u16, usize)> {
+    if message.len() < HEADER_LEN || message[4..6] != [0, 1] {
+        return None;
+    }
+    let mut labels = vec![];
+    let mut pos = HEADER_LEN;
+    loop {
+        let len = *message.get(pos)? as usize;
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        if len > 63 {
+            return None;
+        }
+        labels.push(String::from_utf8_lossy(message.get(pos..pos + len)?).into_owned());
+        pos += len;
+    }
+    let qtype = u16::from_be_bytes([*message.get(pos)?, *message.get(pos + 1)?]);
+    message.get(pos + 3)?;
+    Some((labels.join("."), qtype, pos + 4))
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.')
+        .eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+fn key_name(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
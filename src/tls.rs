@@ -0,0 +1,335 @@
+//! DNS over TLS (RFC 7858) and DNS over HTTPS (RFC 8484).
+//!
+//! A [`Resolver`](crate::Resolver) uses these with [`Transport::Tls`] or
+//! [`Transport::Https`] and the [`TlsSettings`] given to
+//! [`with_tls`](crate::Resolver::with_tls). Nothing is sent in plaintext, and
+//! there is no fallback to UDP or TCP.
+
+use crate::dns::{self, DnsOptions, Response, Transport};
+use crate::TcpStream;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The port of DNS over TLS servers.
+pub const DOT_PORT: u16 = 853;
+
+const DNS_MESSAGE: &str = "application/dns-message";
+
+/// How DNS over HTTPS queries are sent.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DohMethod {
+    /// The query is the body of a POST request.
+    Post,
+    /// The query is the `dns` parameter of a GET request, which HTTP caches
+    /// can keep.
+    Get,
+}
+
+/// The name the certificates of DNS servers are checked against, the roots
+/// they must chain to, and the path of the DNS over HTTPS endpoint.
+///
+/// ```ignore
+/// let resolver = Resolver::new(vec!["1.1.1.1:853".parse()?])
+///     .with_transport(Transport::Tls)
+///     .with_tls(TlsSettings::new("cloudflare-dns.com")?);
+/// ```
+#[derive(Clone, Debug)]
+pub struct TlsSettings {
+    name: String,
+    server_name: ServerName<'static>,
+    config: Arc<ClientConfig>,
+    path: String,
+}
+
+impl TlsSettings {
+    /// Trust the Mozilla root certificates of `webpki-roots`.
+    pub fn new(server_name: &str) -> io::Result<Self> {
+        let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        TlsSettings::with_roots(server_name, roots)
+    }
+
+    /// Trust only `certs`, such as the self-signed certificate of a local
+    /// server.
+    pub fn with_root_certificates(
+        server_name: &str,
+        certs: Vec<CertificateDer<'static>>,
+    ) -> io::Result<Self> {
+        let mut roots = RootCertStore::empty();
+        for cert in certs {
+            roots.add(cert).map_err(invalid_data)?;
+        }
+        TlsSettings::with_roots(server_name, roots)
+    }
+
+    /// Use a rustls configuration as it is.
+    pub fn with_config(server_name: &str, config: Arc<ClientConfig>) -> io::Result<Self> {
+        let parsed = ServerName::try_from(server_name.to_string()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid TLS server name {:?}", server_name),
+            )
+        })?;
+        Ok(TlsSettings {
+            name: server_name.to_string(),
+            server_name: parsed,
+            config,
+            path: "/dns-query".to_string(),
+        })
+    }
+
+    fn with_roots(server_name: &str, roots: RootCertStore) -> io::Result<Self> {
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        TlsSettings::with_config(server_name, Arc::new(config))
+    }
+
+    /// The path of the DNS over HTTPS endpoint; `/dns-query` by default.
+    pub fn with_path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    pub fn server_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// A TLS session with a DNS server, which [`resolve`](crate::resolve) can
+/// send queries over.
+pub type TlsStream = StreamOwned<ClientConnection, TcpStream>;
+
+/// Connect to `server` and finish the TLS handshake, so that a certificate
+/// that does not check out fails here. `timeout` is the read and write
+/// timeout of the connection.
+pub fn connect(
+    server: SocketAddr,
+    settings: &TlsSettings,
+    timeout: Duration,
+) -> io::Result<TlsStream> {
+    let sock = TcpStream::connect(server)?;
+    sock.as_ref().set_send_timeout(Some(timeout))?;
+    sock.as_ref().set_recv_timeout(Some(timeout))?;
+    let conn = ClientConnection::new(settings.config.clone(), settings.server_name.clone())
+        .map_err(invalid_data)?;
+    let mut stream = StreamOwned::new(conn, sock);
+    while stream.conn.is_handshaking() {
+        stream.conn.complete_io(&mut stream.sock)?;
+    }
+    Ok(stream)
+}
+
+/// Send a query for each of `qtypes` to `server` over one TLS connection,
+/// and read the answers in the order of `qtypes`.
+pub(crate) fn exchange_many(
+    server: SocketAddr,
+    name: &str,
+    qtypes: &[u16],
+    transport: Transport,
+    settings: &TlsSettings,
+    options: &DnsOptions,
+) -> io::Result<Vec<io::Result<Response>>> {
+    let mut stream = connect(server, settings, options.tcp_timeout)?;
+    let deadline = dns::Deadline::new(Instant::now() + options.tcp_timeout, &stream.sock);
+    match transport {
+        Transport::Https(method) => {
+            let mut conn = BufReader::new(stream);
+            Ok(qtypes
+                .iter()
+                .map(|&qtype| {
+                    // RFC 8484 asks for id 0, so that HTTP caches see the
+                    // same request for the same question
+                    let packet = dns::build_query(0, name, qtype)?;
+                    exchange_https(&mut conn, settings, method, &packet).map(|r| (r, 0))
+                })
+                .collect())
+        }
+        _ => Ok(qtypes
+            .iter()
+            .map(|&qtype| {
+                let id = rand::random();
+                let packet = dns::build_query(id, name, qtype)?;
+                dns::exchange_stream(&mut stream, &packet, Some(deadline)).map(|r| (r, id))
+            })
+            .collect()),
+    }
+}
+
+/// Send `packet` in an HTTP/1.1 request and read the DNS message in the
+/// response. The connection is kept open for the next request.
+fn exchange_https<S: Read + Write>(
+    conn: &mut BufReader<S>,
+    settings: &TlsSettings,
+    method: DohMethod,
+    packet: &[u8],
+) -> io::Result<Vec<u8>> {
+    let request = match method {
+        DohMethod::Post => {
+            let mut request = format!(
+                "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nAccept: {}\r\nContent-Length: {}\r\n\r\n",
+                settings.path,
+                settings.name,
+                DNS_MESSAGE,
+                DNS_MESSAGE,
+                packet.len()
+            )
+            .into_bytes();
+            request.extend_from_slice(packet);
+            request
+        }
+        DohMethod::Get => {
+            let separator = if settings.path.contains('?') {
+                '&'
+            } else {
+                '?'
+            };
+            format!(
+                "GET {}{}dns={} HTTP/1.1\r\nHost: {}\r\nAccept: {}\r\n\r\n",
+                settings.path,
+                separator,
+                base64url(packet),
+                settings.name,
+                DNS_MESSAGE
+            )
+            .into_bytes()
+        }
+    };
+    conn.get_mut().write_all(&request)?;
+    conn.get_mut().flush()?;
+    read_http_response(conn)
+}
+
+/// Read an HTTP/1.1 response, and return its body if it is a DNS message.
+/// A body without a length or chunked encoding is read up to the end of the
+/// connection, which the server has to say it closes.
+fn read_http_response<R: BufRead>(conn: &mut R) -> io::Result<Vec<u8>> {
+    let status = read_line(conn)?;
+    let code = status
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| invalid_data(format!("bad HTTP status line {:?}", status)))?;
+
+    let mut content_type = None;
+    let mut content_length = None;
+    let mut chunked = false;
+    // HTTP/1.0 closes the connection after the response unless asked not to
+    let mut close = status.starts_with("HTTP/1.0");
+    loop {
+        let line = read_line(conn)?;
+        if line.is_empty() {
+            break;
+        }
+        let (header, value) = match line.split_once(':') {
+            Some((header, value)) => (header.trim(), value.trim()),
+            None => continue,
+        };
+        if header.eq_ignore_ascii_case("content-type") {
+            content_type = Some(value.to_ascii_lowercase());
+        } else if header.eq_ignore_ascii_case("content-length") {
+            content_length = Some(
+                value
+                    .parse::<usize>()
+                    .map_err(|_| invalid_data(format!("bad Content-Length {:?}", value)))?,
+            );
+        } else if header.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.to_ascii_lowercase().contains("chunked");
+        } else if header.eq_ignore_ascii_case("connection") {
+            let value = value.to_ascii_lowercase();
+            close = value.contains("close") || (close && !value.contains("keep-alive"));
+        }
+    }
+
+    let body = if chunked {
+        read_chunked(conn)?
+    } else if let Some(len) = content_length {
+        let mut body = vec![0u8; len];
+        conn.read_exact(&mut body)?;
+        body
+    } else if close {
+        let mut body = vec![];
+        conn.read_to_end(&mut body)?;
+        body
+    } else {
+        // the body would only end when the server times the connection out
+        return Err(invalid_data(
+            "HTTP response without a length on a connection that stays open",
+        ));
+    };
+
+    if code != 200 {
+        return Err(io::Error::other(format!(
+            "DNS over HTTPS server answered {}",
+            status.trim()
+        )));
+    }
+    match content_type {
+        Some(t) if t.split(';').next() == Some(DNS_MESSAGE) => Ok(body),
+        t => Err(invalid_data(format!(
+            "DNS over HTTPS response of type {:?}",
+            t.unwrap_or_default()
+        ))),
+    }
+}
+
+fn read_chunked<R: BufRead>(conn: &mut R) -> io::Result<Vec<u8>> {
+    let mut body = vec![];
+    loop {
+        let line = read_line(conn)?;
+        let size = line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| invalid_data(format!("bad chunk size {:?}", line)))?;
+        if size == 0 {
+            // skip the trailers
+            while !read_line(conn)?.is_empty() {}
+            return Ok(body);
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        conn.read_exact(&mut body[start..])?;
+        read_line(conn)?;
+    }
+}
+
+// a line without its CRLF
+fn read_line<R: BufRead>(conn: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    if conn.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed in an HTTP response",
+        ));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Base64 with the URL and file name safe alphabet and no padding, as
+/// RFC 8484 asks for.
+fn base64url(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let mut out = String::with_capacity((data.len() * 4).div_ceil(3));
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    out
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
@@ -97,6 +97,7 @@ pub struct Subscription {
     pub u: SubscriptionU,
 }
 
+#[cfg(all(target_os = "wasi", not(wasmedge_socket_mock)))]
 #[link(wasm_import_module = "wasi_snapshot_preview1")]
 extern "C" {
     #[cfg(not(feature = "epoll"))]
@@ -105,28 +106,38 @@ extern "C" {
     pub fn epoll_oneoff(arg0: i32, arg1: i32, arg2: i32, arg3: i32) -> i32;
 }
 
+/// Wait for the events of `nsubscriptions` subscriptions.
+///
+/// # Safety
+///
+/// `in_` must point to `nsubscriptions` subscriptions, and `out` to room for
+/// as many events.
 pub unsafe fn poll(
     in_: *const Subscription,
     out: *mut Event,
     nsubscriptions: usize,
 ) -> std::io::Result<usize> {
     let mut rp0 = 0_usize;
-    #[cfg(not(feature = "epoll"))]
+    #[cfg(all(target_os = "wasi", not(wasmedge_socket_mock), not(feature = "epoll")))]
     let ret = poll_oneoff(
         in_ as i32,
         out as i32,
         nsubscriptions as i32,
         (&mut rp0) as *mut usize as i32,
     );
-    #[cfg(feature = "epoll")]
+    #[cfg(all(target_os = "wasi", not(wasmedge_socket_mock), feature = "epoll"))]
     let ret = epoll_oneoff(
         in_ as i32,
         out as i32,
         nsubscriptions as i32,
         (&mut rp0) as *mut usize as i32,
     );
+    #[cfg(all(not(target_os = "wasi"), not(wasmedge_socket_mock)))]
+    let ret = crate::native::poll_oneoff(in_, out, nsubscriptions, &mut rp0);
+    #[cfg(wasmedge_socket_mock)]
+    let ret = crate::mock::host::poll_oneoff(in_, out, nsubscriptions, &mut rp0);
     match ret {
         0 => Ok(rp0),
-        _ => Err(std::io::Error::from_raw_os_error(ret)),
+        _ => Err(crate::errno::io_error(ret as u32)),
     }
 }
@@ -0,0 +1,65 @@
+use std::net::{IpAddr, SocketAddr};
+use wasmedge_wasi_socket::authority::{Authority, Host};
+use wasmedge_wasi_socket::{Hosts, Resolver, ToSocketAddrs};
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn parse(s: &str) -> Authority {
+    Authority::parse(s).unwrap()
+}
+
+#[test]
+fn parses_hosts_and_ports() {
+    let a = parse("[fe80::1%2]:80");
+    assert_eq!(a.host, Host::Addr(ip("fe80::1"), 2));
+    assert_eq!(a.port.as_deref(), Some("80"));
+    assert_eq!(parse("::1").host, Host::Addr(ip("::1"), 0));
+    assert_eq!(parse("[::1]").port, None);
+    assert_eq!(
+        parse("example.com.:https").host,
+        Host::Name("example.com.".into())
+    );
+    assert_eq!(parse("_grpc.internal").port, None);
+}
+
+#[test]
+fn errors_name_the_wrong_part() {
+    for (s, message) in [
+        ("", "empty socket address"),
+        ("[::1:80", "missing ']'"),
+        ("[::1]80", "expected ':' after ']'"),
+        ("[1.2.3.4]:80", "IPv4 address \"1.2.3.4\" in brackets"),
+        ("[::zz]:80", "invalid IP address \"::zz\""),
+        ("fe80::1:80:x", "must be in brackets"),
+        ("1.2.3.4%1:80", "zone on a host"),
+        ("[fe80::1%no-such-if]:80", "unknown zone \"no-such-if\""),
+        ("host:", "empty port"),
+        (":80", "empty host"),
+        ("host:70000", "port 70000 out of range"),
+        ("host:ht/tp", "invalid port \"ht/tp\""),
+        ("a..b:80", "empty label"),
+        ("a b:80", "unexpected character ' '"),
+    ] {
+        let e = Authority::parse(s).unwrap_err();
+        assert!(e.to_string().contains(message), "{:?}: {}", s, e);
+    }
+}
+
+#[test]
+fn resolves() -> std::io::Result<()> {
+    let addrs: Vec<SocketAddr> = "[fe80::1%3]:8080".to_socket_addrs()?.collect();
+    match addrs[0] {
+        SocketAddr::V6(a) => assert_eq!((a.port(), a.scope_id()), (8080, 3)),
+        _ => panic!("{:?}", addrs),
+    }
+    let e = "localhost".to_socket_addrs().unwrap_err();
+    assert!(e.to_string().contains("missing port"));
+    let addrs = parse("127.0.0.1").to_socket_addrs(6379)?;
+    assert_eq!(addrs, vec!["127.0.0.1:6379".parse().unwrap()]);
+    let resolver = Resolver::host().with_hosts(Hosts::new().with("db.internal", ip("10.0.0.5")));
+    let addrs = parse("db.internal.").to_socket_addrs_with(6379, &resolver)?;
+    assert_eq!(addrs, vec!["10.0.0.5:6379".parse().unwrap()]);
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use std::io::{self, Read, Write};
+use wasmedge_wasi_socket::dns::MAX_CNAME_CHAIN;
+use wasmedge_wasi_socket::{resolve, Ipv4Addr};
+
+enum Record {
+    A(String, [u8; 4]),
+    Cname(String, String),
+}
+
+fn a(owner: &str, ip: [u8; 4]) -> Record {
+    Record::A(owner.into(), ip)
+}
+
+fn cname(owner: &str, target: &str) -> Record {
+    Record::Cname(owner.into(), target.into())
+}
+
+/// A DNS-over-TCP peer that answers each query from `answer`.
+struct Zone<F> {
+    answer: F,
+    written: Vec<u8>,
+    pending: Vec<u8>,
+    queries: usize,
+}
+
+impl<F: FnMut(&str) -> Vec<Record>> Zone<F> {
+    fn new(answer: F) -> Self {
+        Zone {
+            answer,
+            written: vec![],
+            pending: vec![],
+            queries: 0,
+        }
+    }
+}
+
+impl<F> Write for Zone<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&str) -> Vec<Record>> Read for Zone<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            let query = std::mem::take(&mut self.written);
+            let query = &query[2..];
+            let records = (self.answer)(&qname(query));
+            self.pending = response(query, &records);
+            self.queries += 1;
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+fn qname(query: &[u8]) -> String {
+    let mut labels = vec![];
+    let mut pos = 12;
+    while query[pos] != 0 {
+        let len = query[pos] as usize;
+        labels.push(String::from_utf8_lossy(&query[pos + 1..pos + 1 + len]).into_owned());
+        pos += len + 1;
+    }
+    labels.join(".")
+}
+
+fn encode_name(name: &str) -> Vec<u8> {
+    let mut out = vec![];
+    for label in name.split('.') {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+    out
+}
+
+/// The length-prefixed response to `query` carrying `records`.
+fn response(query: &[u8], records: &[Record]) -> Vec<u8> {
+    let mut msg = query[..2].to_vec();
+    msg.extend_from_slice(&[0x81, 0x80, 0, 1, 0, records.len() as u8, 0, 0, 0, 0]);
+    msg.extend_from_slice(&query[12..]);
+    for record in records {
+        let (owner, rtype, rdata) = match record {
+            Record::A(owner, ip) => (owner, 1u8, ip.to_vec()),
+            Record::Cname(owner, target) => (owner, 5u8, encode_name(target)),
+        };
+        msg.extend_from_slice(&encode_name(owner));
+        msg.extend_from_slice(&[0, rtype, 0, 1, 0, 0, 0, 60, 0, rdata.len() as u8]);
+        msg.extend_from_slice(&rdata);
+    }
+    let mut framed = (msg.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(&msg);
+    framed
+}
+
+fn error_kind(e: Box<dyn std::error::Error>) -> io::ErrorKind {
+    e.downcast::<io::Error>().expect("an io::Error").kind()
+}
+
+#[test]
+fn chain_in_one_response() {
+    // the whole chain in one response, with a record for an unrelated name
+    let mut conn = Zone::new(|_| {
+        vec![
+            cname("www.example.com", "cdn.example.net"),
+            cname("cdn.example.net", "edge.example.net"),
+            a("elsewhere.example.org", [192, 0, 2, 66]),
+            a("edge.example.net", [10, 0, 0, 1]),
+        ]
+    });
+    let addrs = resolve::<_, Ipv4Addr>(&mut conn, "www.example.com").unwrap();
+    assert_eq!(addrs, vec![Ipv4Addr::new(10, 0, 0, 1)]);
+    assert_eq!(conn.queries, 1);
+}
+
+#[test]
+fn alias_is_queried() {
+    // the response stops at the alias, which is queried next
+    let mut conn = Zone::new(|name| match name {
+        "www.example.com" => vec![cname("www.example.com", "edge.example.net")],
+        _ => vec![a("edge.example.net", [10, 0, 0, 2])],
+    });
+    let addrs = resolve::<_, Ipv4Addr>(&mut conn, "www.example.com").unwrap();
+    assert_eq!(addrs, vec![Ipv4Addr::new(10, 0, 0, 2)]);
+    assert_eq!(conn.queries, 2);
+}
+
+#[test]
+fn unrelated_records_are_dropped() {
+    // records for names outside the chain are dropped
+    let mut conn = Zone::new(|_| vec![a("attacker.example", [192, 0, 2, 66])]);
+    let addrs = resolve::<_, Ipv4Addr>(&mut conn, "www.example.com").unwrap();
+    assert!(addrs.is_empty());
+}
+
+#[test]
+fn loops_and_long_chains_fail() {
+    let mut conn = Zone::new(|_| {
+        vec![
+            cname("a.example", "b.example"),
+            cname("b.example", "a.example"),
+        ]
+    });
+    let e = resolve::<_, Ipv4Addr>(&mut conn, "a.example").unwrap_err();
+    assert_eq!(error_kind(e), io::ErrorKind::InvalidData);
+
+    // every hop is answered by a separate response
+    let mut conn = Zone::new(|name| {
+        let n: usize = name[1..].split('.').next().unwrap().parse().unwrap();
+        vec![cname(name, &format!("n{}.example", n + 1))]
+    });
+    let e = resolve::<_, Ipv4Addr>(&mut conn, "n0.example").unwrap_err();
+    assert_eq!(error_kind(e), io::ErrorKind::InvalidData);
+    assert!(conn.queries <= MAX_CNAME_CHAIN + 1);
+}
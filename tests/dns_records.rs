@@ -0,0 +1,143 @@
+use std::io::{self, Read, Write};
+use wasmedge_wasi_socket::records::{order_srv, Caa, Mx, Srv, Txt};
+use wasmedge_wasi_socket::resolve;
+
+/// A DNS-over-TCP peer that answers every query with `records`, given as
+/// (type, rdata) pairs owned by the name queried.
+struct Canned {
+    records: Vec<(u16, Vec<u8>)>,
+    written: Vec<u8>,
+    pending: Vec<u8>,
+}
+
+impl Canned {
+    fn new(records: Vec<(u16, Vec<u8>)>) -> Self {
+        Canned {
+            records,
+            written: vec![],
+            pending: vec![],
+        }
+    }
+}
+
+impl Write for Canned {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for Canned {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            let query = std::mem::take(&mut self.written);
+            self.pending = self.response(&query[2..]);
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Canned {
+    fn response(&self, query: &[u8]) -> Vec<u8> {
+        let mut msg = query[..2].to_vec();
+        // the answers, and an HTTPS record in the additional section that the
+        // parser does not know
+        let an = self.records.len() as u8;
+        msg.extend_from_slice(&[0x81, 0x80, 0, 1, 0, an, 0, 0, 0, 1]);
+        msg.extend_from_slice(&query[12..]);
+        for (rtype, rdata) in self.records.iter().chain([(65, vec![0, 1, 0])].iter()) {
+            msg.extend_from_slice(&[0xc0, 0x0c]);
+            msg.extend_from_slice(&rtype.to_be_bytes());
+            msg.extend_from_slice(&[0, 1, 0, 0, 0, 60]);
+            msg.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            msg.extend_from_slice(rdata);
+        }
+        let mut framed = (msg.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(&msg);
+        framed
+    }
+}
+
+fn name(name: &str) -> Vec<u8> {
+    let mut out = vec![];
+    for label in name.split('.') {
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+    out
+}
+
+fn srv(priority: u16, weight: u16, port: u16, target: &str) -> Srv {
+    Srv {
+        priority,
+        weight,
+        port,
+        target: target.into(),
+    }
+}
+
+#[test]
+fn srv_records() {
+    let mut rdata = [0u8, 10, 0, 5, 0x1f, 0x90].to_vec();
+    rdata.extend_from_slice(&name("backend.example.com"));
+    let mut conn = Canned::new(vec![(33, rdata)]);
+    let records = resolve::<_, Srv>(&mut conn, "_http._tcp.example.com").unwrap();
+    assert_eq!(records, vec![srv(10, 5, 8080, "backend.example.com")]);
+}
+
+#[test]
+fn mx() {
+    let mut rdata = vec![0, 10];
+    rdata.extend_from_slice(&name("mail.example.com"));
+    let mut conn = Canned::new(vec![(15, rdata)]);
+    let records = resolve::<_, Mx>(&mut conn, "example.com").unwrap();
+    assert_eq!(records[0].preference, 10);
+    assert_eq!(records[0].exchange, "mail.example.com");
+}
+
+#[test]
+fn txt() {
+    let mut conn = Canned::new(vec![(16, b"\x07v=spf1 \x04-all".to_vec())]);
+    let records = resolve::<_, Txt>(&mut conn, "example.com").unwrap();
+    assert_eq!(records[0].0.len(), 2);
+    assert_eq!(records[0].text(), "v=spf1 -all");
+}
+
+#[test]
+fn caa() {
+    let mut conn = Canned::new(vec![
+        (257, b"\x00\x05issueletsencrypt.org".to_vec()),
+        (257, b"\x80\x05tbsunknown".to_vec()),
+    ]);
+    let records = resolve::<_, Caa>(&mut conn, "example.com").unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].tag, "issue");
+    assert_eq!(records[0].value, b"letsencrypt.org");
+    assert!(!records[0].is_critical() && records[1].is_critical());
+}
+
+#[test]
+fn srv_order() {
+    // lower priorities first, and heavier records first more often
+    let mut first = [0; 2];
+    for _ in 0..1000 {
+        let ordered = order_srv(vec![
+            srv(20, 0, 1, "backup.example.com"),
+            srv(10, 1, 1, "light.example.com"),
+            srv(10, 9, 1, "heavy.example.com"),
+            srv(5, 0, 1, "."),
+        ]);
+        assert_eq!(ordered.len(), 3);
+        assert_eq!(ordered[2].target, "backup.example.com");
+        first[(ordered[0].target == "heavy.example.com") as usize] += 1;
+    }
+    assert!(first[1] > first[0] * 3, "{:?}", first);
+}
@@ -0,0 +1,193 @@
+use std::io::{self, Read, Write};
+use wasmedge_wasi_socket::{resolve, Ipv4Addr};
+
+/// A DNS-over-TCP peer that answers every query with one A record and hands
+/// the answer out `chunk` bytes per read.
+struct ChunkedStream {
+    chunk: usize,
+    written: Vec<u8>,
+    response: Vec<u8>,
+    pos: usize,
+    // drop the answer after this many bytes
+    cut_at: Option<usize>,
+    // fail every other read with this error
+    hiccup: Option<io::ErrorKind>,
+    // send this framed message instead of the answer
+    canned: Option<Vec<u8>>,
+    reads: usize,
+}
+
+impl ChunkedStream {
+    fn new(chunk: usize) -> Self {
+        ChunkedStream {
+            chunk,
+            written: vec![],
+            response: vec![],
+            pos: 0,
+            cut_at: None,
+            hiccup: None,
+            canned: None,
+            reads: 0,
+        }
+    }
+}
+
+impl Write for ChunkedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for ChunkedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reads += 1;
+        if let Some(kind) = self.hiccup {
+            if self.reads % 2 == 1 {
+                return Err(kind.into());
+            }
+        }
+        if self.response.is_empty() {
+            self.response = match self.canned.take() {
+                Some(canned) => canned,
+                None => answer(&self.written[2..]),
+            };
+        }
+        let end = self.cut_at.unwrap_or(self.response.len());
+        let n = buf.len().min(self.chunk).min(end - self.pos);
+        buf[..n].copy_from_slice(&self.response[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// The length-prefixed answer to `query`, with the address 10.0.0.1.
+fn answer(query: &[u8]) -> Vec<u8> {
+    let mut msg = vec![query[0], query[1], 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0];
+    msg.extend_from_slice(&query[12..]);
+    msg.extend_from_slice(&[0xc0, 0x0c, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 10, 0, 0, 1]);
+    let mut framed = (msg.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(&msg);
+    framed
+}
+
+fn error_kind(e: Box<dyn std::error::Error>) -> io::ErrorKind {
+    e.downcast::<io::Error>().expect("an io::Error").kind()
+}
+
+#[test]
+fn chunked_reads() {
+    for chunk in [1, 2, 3, 7, 512] {
+        let mut conn = ChunkedStream::new(chunk);
+        let addrs = resolve::<_, Ipv4Addr>(&mut conn, "example.com").unwrap();
+        assert_eq!(addrs, vec![Ipv4Addr::new(10, 0, 0, 1)], "chunk {}", chunk);
+    }
+}
+
+#[test]
+fn interrupted_reads() {
+    let mut conn = ChunkedStream::new(1);
+    conn.hiccup = Some(io::ErrorKind::Interrupted);
+    let addrs = resolve::<_, Ipv4Addr>(&mut conn, "example.com").unwrap();
+    assert_eq!(addrs, vec![Ipv4Addr::new(10, 0, 0, 1)]);
+}
+
+#[test]
+fn short_answers() {
+    // the connection closes inside the length prefix, then inside the body
+    for cut_at in [1, 20] {
+        let mut conn = ChunkedStream::new(4);
+        conn.cut_at = Some(cut_at);
+        let e = resolve::<_, Ipv4Addr>(&mut conn, "example.com").unwrap_err();
+        assert_eq!(error_kind(e), io::ErrorKind::UnexpectedEof);
+    }
+}
+
+#[test]
+fn short_message() {
+    // a complete message that is too short to hold a header
+    let mut conn = ChunkedStream::new(4);
+    conn.canned = Some(vec![0, 2, 0x81, 0x80]);
+    let e = resolve::<_, Ipv4Addr>(&mut conn, "example.com").unwrap_err();
+    assert_eq!(error_kind(e), io::ErrorKind::InvalidData);
+}
+
+// the mock has no sockets to wait on
+#[cfg(not(wasmedge_socket_mock))]
+mod deadline {
+    use super::error_kind;
+    use std::io::{self, Read, Write};
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::time::{Duration, Instant};
+    use wasmedge_wasi_socket::{resolve_with_deadline, Ipv4Addr, TcpListener, TcpStream};
+
+    /// A nonblocking connection to a server that never answers, which counts
+    /// the reads made on it.
+    struct SilentStream {
+        stream: TcpStream,
+        reads: usize,
+        _listener: TcpListener,
+    }
+
+    impl SilentStream {
+        fn connect() -> io::Result<Self> {
+            let listener = TcpListener::bind("127.0.0.1:0", false)?;
+            let stream = TcpStream::connect(listener.local_addr()?)?;
+            stream.set_nonblocking(true)?;
+            Ok(SilentStream {
+                stream,
+                reads: 0,
+                _listener: listener,
+            })
+        }
+    }
+
+    impl Read for SilentStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            self.stream.read(buf)
+        }
+    }
+
+    impl Write for SilentStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.stream.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.stream.flush()
+        }
+    }
+
+    impl AsRawFd for SilentStream {
+        fn as_raw_fd(&self) -> RawFd {
+            self.stream.as_raw_fd()
+        }
+    }
+
+    #[test]
+    fn polls_until_deadline() -> io::Result<()> {
+        // a stream without data is polled until the deadline, not read over and
+        // over
+        let mut conn = SilentStream::connect()?;
+        let deadline = Instant::now() + Duration::from_millis(200);
+        let e =
+            resolve_with_deadline::<_, Ipv4Addr>(&mut conn, "example.com", deadline).unwrap_err();
+        assert_eq!(error_kind(e), io::ErrorKind::TimedOut);
+        assert!(Instant::now() >= deadline);
+        assert!(conn.reads <= 3, "{} reads", conn.reads);
+        Ok(())
+    }
+}
+
+#[test]
+fn would_block_without_deadline() {
+    let mut conn = ChunkedStream::new(4);
+    conn.hiccup = Some(io::ErrorKind::WouldBlock);
+    let e = resolve::<_, Ipv4Addr>(&mut conn, "example.com").unwrap_err();
+    assert_eq!(error_kind(e), io::ErrorKind::WouldBlock);
+}
@@ -0,0 +1,62 @@
+use std::net::IpAddr;
+use wasmedge_wasi_socket::{Hosts, Resolver};
+
+const HOSTS: &str = "
+# pinned for the tests
+127.0.0.1   db.internal db    # the primary
+::1         db.internal
+10.0.0.7    cache.internal
+not-an-ip   ignored.internal
+";
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn parses() {
+    let hosts = Hosts::parse(HOSTS);
+    assert_eq!(
+        hosts.get("DB.internal."),
+        Some(&[ip("127.0.0.1"), ip("::1")][..])
+    );
+    assert_eq!(hosts.get("db"), Some(&[ip("127.0.0.1")][..]));
+    assert_eq!(hosts.names(ip("127.0.0.1")), vec!["db.internal", "db"]);
+    assert!(hosts.get("ignored.internal").is_none());
+}
+
+#[test]
+fn file_is_read() -> std::io::Result<()> {
+    let path = std::env::temp_dir().join(format!("hosts-{}", std::process::id()));
+    std::fs::write(&path, HOSTS)?;
+    let hosts = Hosts::from_file(&path);
+    std::fs::remove_file(&path)?;
+    assert_eq!(hosts?.get("cache.internal"), Some(&[ip("10.0.0.7")][..]));
+    Ok(())
+}
+
+#[test]
+fn pinned_names_need_no_dns_server() -> std::io::Result<()> {
+    let resolver = Resolver::host().with_hosts(Hosts::parse(HOSTS));
+    let addrs = resolver.lookup_ip("cache.internal")?;
+    assert_eq!(addrs, vec![ip("10.0.0.7")]);
+    assert_eq!(
+        resolver.lookup_addr(ip("10.0.0.7"))?,
+        vec!["cache.internal"]
+    );
+    Ok(())
+}
+
+// the mock has no sockets to connect
+#[cfg(not(wasmedge_socket_mock))]
+#[test]
+fn connects_to_pinned_name() -> std::io::Result<()> {
+    use wasmedge_wasi_socket::{TcpListener, TcpStream};
+
+    let resolver = Resolver::host().with_hosts(Hosts::new().with("api.internal", ip("127.0.0.1")));
+    let listener = TcpListener::bind("127.0.0.1:0", false)?;
+    let port = listener.local_addr()?.port();
+    let stream = TcpStream::connect_with(("api.internal", port), &resolver)?;
+    assert_eq!(stream.peer_addr()?.port(), port);
+    Ok(())
+}
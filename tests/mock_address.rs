@@ -0,0 +1,87 @@
+#![cfg(wasmedge_socket_mock)]
+
+use wasmedge_wasi_socket::mock::{self, Call, Import, Reply};
+use wasmedge_wasi_socket::{SocketAddr, TcpStream, UdpSocket};
+
+/// The address buffer and port of the last `sock_connect`.
+fn connected(addr: SocketAddr) -> (Vec<u8>, u32) {
+    drop(TcpStream::connect(addr).unwrap());
+    mock::take_calls()
+        .into_iter()
+        .find_map(|call| match call {
+            Call::SockConnect { addr, port, .. } => Some((addr, port)),
+            _ => None,
+        })
+        .unwrap()
+}
+
+/// A link-local address with a flow info and a scope id.
+fn scoped() -> SocketAddr {
+    match "[fe80::1%3]:8080".parse().unwrap() {
+        SocketAddr::V6(mut a) => {
+            a.set_flowinfo(0x12345);
+            SocketAddr::V6(a)
+        }
+        a => a,
+    }
+}
+
+/// The 128 byte form of `scoped()`: the family, the octets, the flow info
+/// and the scope id.
+fn scoped_bytes() -> Vec<u8> {
+    let mut expected = vec![0u8; 128];
+    expected[0..2].copy_from_slice(&2u16.to_le_bytes());
+    expected[2..4].copy_from_slice(&[0xfe, 0x80]);
+    expected[17] = 1;
+    expected[18..22].copy_from_slice(&0x12345u32.to_le_bytes());
+    expected[22..26].copy_from_slice(&3u32.to_le_bytes());
+    expected
+}
+
+#[test]
+fn connect() {
+    // plain addresses are passed as their octets
+    let (buf, port) = connected("10.0.0.1:80".parse().unwrap());
+    assert_eq!((buf, port), (vec![10, 0, 0, 1], 80));
+    let (buf, port) = connected("[fd00::1]:443".parse().unwrap());
+    assert_eq!(
+        buf,
+        "fd00::1".parse::<std::net::Ipv6Addr>().unwrap().octets()
+    );
+    assert_eq!(port, 443);
+
+    // with a flow info or a scope id, in the 128 byte form
+    let (buf, port) = connected(scoped());
+    assert_eq!(buf, scoped_bytes());
+    assert_eq!(port, 8080);
+}
+
+#[test]
+fn send_to_and_recv_from() -> std::io::Result<()> {
+    // the same buffer is read back from sock_recv_from
+    let socket = UdpSocket::bind("[::]:0")?;
+    socket.send_to(b"ping", scoped())?;
+    mock::push(Import::SockRecvFrom, Reply::data_from(b"pong", scoped()));
+    let mut data = [0u8; 16];
+    let (n, from) = socket.recv_from(&mut data)?;
+    assert_eq!(&data[..n], b"pong");
+    assert_eq!(from, scoped());
+    let sent = mock::take_calls().into_iter().find_map(|call| match call {
+        Call::SockSendTo { addr, .. } => Some(addr),
+        _ => None,
+    });
+    assert_eq!(sent, Some(scoped_bytes()));
+
+    // an unknown family is an error
+    mock::push(
+        Import::SockRecvFrom,
+        Reply::DataFrom {
+            data: vec![],
+            family: 9,
+            octets: vec![],
+            port: 0,
+        },
+    );
+    assert!(socket.recv_from(&mut data).is_err());
+    Ok(())
+}
@@ -0,0 +1,126 @@
+#![cfg(wasmedge_socket_mock)]
+
+use std::io::{ErrorKind, Read, Write};
+use std::time::Duration;
+use wasmedge_wasi_socket::mdns::{Querier, Responder, MDNS_V4, MDNS_V6};
+use wasmedge_wasi_socket::mock::{self, Call, Import, Reply};
+use wasmedge_wasi_socket::socket::{SocketOptName, TcpKeepalive};
+use wasmedge_wasi_socket::{Ipv4Addr, SocketAddr, TcpStream, UdpSocket, WasiErrno};
+
+fn server() -> SocketAddr {
+    "[fe80::1%3]:8080".parse().unwrap()
+}
+
+#[test]
+fn partial_send_and_eagain() -> std::io::Result<()> {
+    mock::push(Import::SockSend, Reply::Sent(2));
+    mock::push(Import::SockSend, Reply::Errno(WasiErrno::Again));
+    mock::push(Import::SockRecv, Reply::Data(b"pong".to_vec()));
+
+    let mut stream = TcpStream::connect(server())?;
+    assert_eq!(stream.write(b"ping")?, 2);
+    let e = stream.write(b"ng").unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::WouldBlock);
+    assert_eq!(WasiErrno::from_io_error(&e), Some(WasiErrno::Again));
+
+    let mut buf = [0u8; 16];
+    let n = stream.read(&mut buf)?;
+    assert_eq!(&buf[..n], b"pong");
+    drop(stream);
+
+    let calls = mock::take_calls();
+    match &calls[1] {
+        Call::SockConnect { addr, port, .. } => {
+            assert_eq!(*port, 8080);
+            assert_eq!(addr.len(), 128);
+            assert_eq!(
+                &addr[2..18],
+                &"fe80::1".parse::<std::net::Ipv6Addr>().unwrap().octets()
+            );
+            assert_eq!(&addr[22..26], &3u32.to_le_bytes());
+        }
+        call => panic!("unexpected call {:?}", call),
+    }
+    assert!(matches!(calls.last(), Some(Call::FdClose { .. })));
+    Ok(())
+}
+
+#[test]
+fn ip_level_is_unsupported() -> std::io::Result<()> {
+    // WasmEdge knows no option level but SOL_SOCKET
+    let stream = TcpStream::connect(server())?;
+    mock::push(Import::SockSetSockOpt, Reply::Errno(WasiErrno::Inval));
+    let e = stream.set_ttl(64).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Unsupported);
+    Ok(())
+}
+
+#[test]
+fn out_of_range_values_are_refused() -> std::io::Result<()> {
+    // bad values fail before the host is asked, so EINVAL from it can only
+    // mean a level it lacks
+    let stream = TcpStream::connect(server())?;
+    let udp = UdpSocket::bind("[::]:0")?;
+    mock::take_calls();
+    assert_eq!(
+        stream.set_ttl(0).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        udp.set_ttl(256).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    let e = udp.set_multicast_ttl_v4(256).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    let e = udp.set_multicast_hops_v6(300).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    assert!(mock::take_calls().is_empty());
+    Ok(())
+}
+
+#[test]
+fn multicast_is_unsupported() -> std::io::Result<()> {
+    // the multicast options, and so multicast DNS, are at the IP levels too
+    let udp = UdpSocket::bind("0.0.0.0:0")?;
+    mock::push(Import::SockSetSockOpt, Reply::Errno(WasiErrno::Inval));
+    let e = udp
+        .join_multicast_v4(&MDNS_V4, &Ipv4Addr::UNSPECIFIED)
+        .unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Unsupported);
+    mock::push(Import::SockSetSockOpt, Reply::Errno(WasiErrno::Inval));
+    let e = udp.join_multicast_v6(&MDNS_V6, 0).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Unsupported);
+    mock::push(Import::SockSetSockOpt, Reply::Errno(WasiErrno::Inval));
+    let e = Querier::new().err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::Unsupported);
+    // SO_REUSEADDR is known, joining the group is not
+    mock::push(Import::SockSetSockOpt, Reply::Ok);
+    mock::push(Import::SockSetSockOpt, Reply::Errno(WasiErrno::Inval));
+    let e = Responder::new("edge-1.local", vec![]).err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::Unsupported);
+    Ok(())
+}
+
+#[test]
+fn keepalive_without_tcp_level() -> std::io::Result<()> {
+    // keepalive is only enabled once the probe settings have been taken
+    let stream = TcpStream::connect(server())?;
+    mock::take_calls();
+    mock::push(Import::SockSetSockOpt, Reply::Errno(WasiErrno::Inval));
+    let probes = TcpKeepalive::new().with_time(Duration::from_secs(60));
+    let e = stream.set_keepalive(Some(&probes)).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Unsupported);
+    let enabled = mock::take_calls().into_iter().any(|call| {
+        matches!(call, Call::SockSetSockOpt { name, .. } if name == SocketOptName::SoKeepalive as i32)
+    });
+    assert!(!enabled);
+    // without the TCP level, keepalive is reported with no probe settings
+    stream.set_keepalive(Some(&TcpKeepalive::new()))?;
+    mock::push(
+        Import::SockGetSockOpt,
+        Reply::Opt(1i32.to_le_bytes().to_vec()),
+    );
+    mock::push(Import::SockGetSockOpt, Reply::Errno(WasiErrno::Inval));
+    assert_eq!(stream.keepalive()?, Some(TcpKeepalive::new()));
+    Ok(())
+}
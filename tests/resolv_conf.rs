@@ -0,0 +1,28 @@
+use std::time::Duration;
+use wasmedge_wasi_socket::resolv_conf::ResolvConf;
+
+const RESOLV_CONF: &str = "
+nameserver 10.96.0.10
+nameserver fe80::1%eth0
+domain example.com
+search default.svc.cluster.local svc.cluster.local cluster.local.
+options ndots:5 timeout:3 attempts:9 rotate
+";
+
+#[test]
+fn parses() {
+    let conf = ResolvConf::parse(RESOLV_CONF);
+    assert_eq!(conf.nameservers.len(), 2);
+    assert_eq!(conf.nameservers[0], "10.96.0.10:53".parse().unwrap());
+    assert_eq!(
+        conf.search,
+        vec![
+            "default.svc.cluster.local",
+            "svc.cluster.local",
+            "cluster.local"
+        ]
+    );
+    assert_eq!(conf.ndots, 5);
+    assert_eq!(conf.timeout, Duration::from_secs(3));
+    assert_eq!(conf.attempts, 5);
+}
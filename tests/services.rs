@@ -0,0 +1,60 @@
+use wasmedge_wasi_socket::services::Services;
+use wasmedge_wasi_socket::socket::AiProtocol;
+
+const SERVICES: &str = "
+# local services
+myapp        7777/tcp   app       # the backend
+myapp        7778/udp
+syslog       514/udp
+broken       port/tcp
+";
+
+#[test]
+fn standard_table() {
+    let standard = Services::standard();
+    assert_eq!(standard.port("https", AiProtocol::IPProtoTCP), Some(443));
+    assert_eq!(standard.port("WWW", AiProtocol::IPProtoTCP), Some(80));
+    assert_eq!(standard.port("ntp", AiProtocol::IPProtoTCP), None);
+    assert_eq!(standard.port("ntp", AiProtocol::IPProtoUDP), Some(123));
+}
+
+#[test]
+fn parses() {
+    let services = Services::parse(SERVICES);
+    assert_eq!(services.port("app", AiProtocol::IPProtoTCP), Some(7777));
+    assert_eq!(services.port("myapp", AiProtocol::IPProtoUDP), Some(7778));
+    assert_eq!(services.port("syslog", AiProtocol::IPProtoIP), Some(514));
+    assert!(services.port("broken", AiProtocol::IPProtoTCP).is_none());
+    assert!(services.resolve("nope", AiProtocol::IPProtoTCP).is_err());
+}
+
+// the mock has no host name lookup
+#[cfg(not(wasmedge_socket_mock))]
+#[test]
+fn service_names_as_ports() -> std::io::Result<()> {
+    use wasmedge_wasi_socket::ToSocketAddrs;
+
+    // an IP address with a service name needs no lookup
+    let addrs: Vec<_> = "127.0.0.1:https".to_socket_addrs()?.collect();
+    assert_eq!(addrs, vec!["127.0.0.1:443".parse().unwrap()]);
+    let addrs: Vec<_> = "localhost:http".to_socket_addrs()?.collect();
+    assert!(!addrs.is_empty() && addrs.iter().all(|a| a.port() == 80));
+    Ok(())
+}
+
+// the mock has no sockets to connect
+#[cfg(not(wasmedge_socket_mock))]
+#[test]
+fn connects_with_own_table() -> std::io::Result<()> {
+    use std::io::Write;
+    use wasmedge_wasi_socket::{Resolver, TcpListener, TcpStream};
+
+    let listener = TcpListener::bind("127.0.0.1:0", false)?;
+    let port = listener.local_addr()?.port();
+    let resolver =
+        Resolver::host().with_services(Services::new().with("myapp", port, AiProtocol::IPProtoTCP));
+    let mut stream = TcpStream::connect_with("localhost:myapp", &resolver)?;
+    stream.write_all(b"hello")?;
+    assert_eq!(stream.peer_addr()?.port(), port);
+    Ok(())
+}